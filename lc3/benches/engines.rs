@@ -0,0 +1,81 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Execution engines compared on the bundled examples.
+//!
+//! ```sh
+//! $ cargo bench --features std
+//! ```
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::Script;
+use lc3::{Engine, Lc3, TrapVectorTable, LC3OS};
+use std::time::{Duration, Instant};
+
+const EXAMPLES: [(&str, &[u8]); 3] = [
+    ("hello", include_bytes!("../../examples/hello.obj")),
+    ("2048", include_bytes!("../../examples/2048.obj")),
+    ("rogue", include_bytes!("../../examples/rogue.obj")),
+];
+const STEPS: usize = 20_000_000;
+
+fn machine(image: &[u8]) -> Box<Lc3<Script>> {
+    let mut lc3 = Box::new(Lc3::new(Script::new(b"wasdwdsa")));
+    lc3.load_image(&mut &LC3OS[..]).unwrap();
+    lc3.load_image(&mut &image[..]).unwrap();
+    lc3.registers.pc = Lc3::<Script>::USER_PROGRAMS_START;
+    lc3.reset();
+    lc3
+}
+
+/// Time `STEPS` instructions executed by `engine`,
+/// restarting the program whenever it halts.
+fn bench(image: &[u8], engine: &mut Engine) -> Duration {
+    let mut lc3 = machine(image);
+    let mut done = 0;
+    let start = Instant::now();
+    while done < STEPS {
+        if lc3.should_halt() {
+            lc3.registers.pc = Lc3::<Script>::USER_PROGRAMS_START;
+            lc3.reset();
+        }
+        done += engine
+            .next(&mut lc3, &mut TrapVectorTable, STEPS - done)
+            .unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    for (name, image) in EXAMPLES {
+        let mut interpreted = Duration::ZERO;
+        for engine in Engine::NAMES {
+            let elapsed = bench(image, &mut engine.parse().unwrap());
+            if engine == "interpreter" {
+                interpreted = elapsed;
+            }
+            println!(
+                "{name:>8}: {engine:>12} {:6.2} ns/inst, speedup {:.2}x",
+                elapsed.as_nanos() as f64 / STEPS as f64,
+                interpreted.as_secs_f64() / elapsed.as_secs_f64(),
+            );
+        }
+    }
+}
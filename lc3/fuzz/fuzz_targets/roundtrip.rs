@@ -0,0 +1,21 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+#![no_main]
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| lc3_fuzz::roundtrip(data));
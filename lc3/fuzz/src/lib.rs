@@ -0,0 +1,271 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Fuzz targets of the `lc3` crate, each panicking iff it finds a bug.
+//!
+//! They are run by `cargo fuzz` through the binaries in `fuzz_targets`, and
+//! offline on random and mutated inputs by the `fuzz` integration test.
+
+use lc3::{
+    Engine, Headless, ImageError, ImageFile, ImageFormat, ImageWriter, Instruction, Lc3,
+    LoadReport, PagedStorage, Storage, TrapHandler, TrapVectorTable, VirtualTrapVectorTable, LC3OS,
+};
+use std::convert::Infallible;
+
+/// Instructions executed per engine by [`interpreter`].
+const STEPS: usize = 10_000;
+
+/// Word memory is filled with before loading an image, to tell the words
+/// the image loaded from those it left untouched.
+const UNTOUCHED: u16 = 0xDEAD;
+
+/// An image file reading at most `chunk` bytes at once.
+struct Chunked<'a> {
+    data: &'a [u8],
+    chunk: usize,
+}
+
+impl ImageFile for Chunked<'_> {
+    type Error = Infallible;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let n = self.data.len().min(buf.len()).min(self.chunk);
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+/// Load an image into memories of different sizes, asserting the results
+/// match those of [`reference_load`].
+///
+/// The first byte of `data` selects the image format, the second how many
+/// bytes are read at once, and the third the size of the smaller memory in
+/// 256-word pages. Raw images are loaded at the origin in the next two bytes.
+pub fn load_image(data: &[u8]) {
+    let [format, chunk, pages, image @ ..] = data else {
+        return;
+    };
+    let (format, image) = match (format % 4, image) {
+        (0, _) => (ImageFormat::Object, image),
+        (1, _) => (ImageFormat::Hex, image),
+        (2, _) => (ImageFormat::Binary, image),
+        (_, [hi, lo, image @ ..]) => (ImageFormat::Raw(u16::from_be_bytes([*hi, *lo])), image),
+        _ => return,
+    };
+    let _ = ImageFormat::detect(image);
+
+    for len in [0x10000, *pages as usize * 0x100] {
+        let mut file = Chunked {
+            data: image,
+            chunk: 1 + *chunk as usize,
+        };
+        let mut memory = vec![UNTOUCHED; len];
+        let result = file.load_image_as(&mut memory[..], format);
+
+        let mut expected_memory = vec![UNTOUCHED; len];
+        let expected = reference_load(image, format, &mut expected_memory);
+        assert_eq!(result, expected, "{format:?} into {len} words");
+        assert!(
+            memory == expected_memory,
+            "{format:?} into {len} words: memory differs"
+        );
+    }
+
+    let mut paged = PagedStorage::new();
+    let paged_result = Chunked {
+        data: image,
+        chunk: 1,
+    }
+    .load_image_as(&mut paged, format);
+    let mut memory = vec![0; 0x10000];
+    let result = Chunked {
+        data: image,
+        chunk: 1,
+    }
+    .load_image_as(&mut memory[..], format);
+    assert_eq!(paged_result, result, "{format:?} into paged storage");
+    assert!(
+        (0..=0xFFFF).all(|addr| paged.load(addr) == memory[addr as usize]),
+        "{format:?} into paged storage: memory differs"
+    );
+}
+
+/// Load `image` in `format` into `memory` the obvious way, a whole
+/// line or word at a time.
+fn reference_load(
+    image: &[u8],
+    format: ImageFormat,
+    memory: &mut [u16],
+) -> Result<LoadReport, ImageError<Infallible>> {
+    let mut words = Vec::new();
+    match format {
+        ImageFormat::Object | ImageFormat::Raw(_) => {
+            for bytes in image.chunks(2) {
+                words.push(match *bytes {
+                    [hi, lo] => Ok(u16::from_be_bytes([hi, lo])),
+                    _ => Err(ImageError::OddLength),
+                });
+            }
+        }
+        ImageFormat::Hex | ImageFormat::Binary => {
+            let (radix, width) = match format {
+                ImageFormat::Hex => (16, 4),
+                _ => (2, 16),
+            };
+            for (i, line) in image.split(|&x| x == b'\n').enumerate() {
+                let digits: Vec<u8> = line
+                    .iter()
+                    .copied()
+                    .filter(|x| !b" \t\r".contains(x))
+                    .collect();
+                if digits.is_empty() {
+                    continue;
+                }
+                let word = (digits.len() <= width && (radix == 16 || digits.len() == width))
+                    .then(|| {
+                        digits.iter().try_fold(0u16, |word, &digit| {
+                            Some(word * radix as u16 + (digit as char).to_digit(radix)? as u16)
+                        })
+                    })
+                    .flatten();
+                words.push(word.ok_or(ImageError::InvalidWord { line: i + 1 }));
+            }
+        }
+    }
+
+    let mut words = words.into_iter();
+    let origin = match format {
+        ImageFormat::Raw(origin) => origin,
+        _ => words.next().ok_or(ImageError::Empty)??,
+    };
+    let mut len = 0;
+    for word in words {
+        let word = word?;
+        let addr = origin as usize + len;
+        if addr >= memory.len() {
+            return Err(ImageError::TooLarge);
+        }
+        memory[addr] = word;
+        len += 1;
+    }
+    if len == 0 {
+        return Err(ImageError::Empty);
+    }
+    Ok(LoadReport::new(origin, len))
+}
+
+/// Run a machine with memory filled with the words of `data` on every
+/// engine, asserting all of them end in the same state as the interpreter.
+///
+/// The first byte of `data` selects whether traps are serviced by the
+/// operating system, loaded over the words, or emulated. The words are
+/// also the keyboard input, and the first one is the initial PC.
+pub fn interpreter(data: &[u8]) {
+    let [flags, words @ ..] = data else {
+        return;
+    };
+    if flags & 1 == 0 {
+        compare_engines(words, false, || VirtualTrapVectorTable);
+    } else {
+        compare_engines(words, true, || TrapVectorTable);
+    }
+}
+
+fn compare_engines<T: TrapHandler<Headless>>(data: &[u8], os: bool, traps: impl Fn() -> T) {
+    let words: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|x| u16::from_be_bytes([x[0], x[1]]))
+        .collect();
+    if words.is_empty() {
+        return;
+    }
+
+    let mut results = Vec::new();
+    for name in Engine::NAMES {
+        let mut engine: Engine = name.parse().unwrap();
+        let mut lc3 = Box::new(Lc3::new(Headless::new(data)));
+        for (addr, word) in lc3.memory.as_mut().iter_mut().zip(words.iter().cycle()) {
+            *addr = *word;
+        }
+        if os {
+            lc3.load_image(&mut &LC3OS[..]).unwrap();
+        }
+        lc3.registers.pc = words[0];
+        lc3.reset();
+        let result = engine.next(&mut lc3, &mut traps(), STEPS);
+        results.push((name, result, lc3));
+    }
+
+    let (_, expected_result, expected) = &results[0];
+    for (name, result, lc3) in &results[1..] {
+        assert_eq!(result, expected_result, "{name}: result");
+        assert_eq!(lc3.registers, expected.registers, "{name}: registers");
+        assert_eq!(lc3.memory.io(), expected.memory.io(), "{name}: I/O");
+        assert!(**lc3 == **expected, "{name}: memory differs");
+    }
+}
+
+/// Round trip the words of `data` through instruction encoding, and
+/// through writing and loading `.obj` images, the first word being the
+/// origin.
+pub fn roundtrip(data: &[u8]) {
+    let words: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|x| u16::from_be_bytes([x[0], x[1]]))
+        .collect();
+
+    for &word in &words {
+        let inst = Instruction::decode(word);
+        let encoded = inst.encode();
+        assert_eq!(Instruction::decode(encoded), inst, "x{word:04X}: decode");
+        assert_eq!(
+            Instruction::decode(encoded).encode(),
+            encoded,
+            "x{word:04X}: encode"
+        );
+        assert!(!inst.to_string().is_empty(), "x{word:04X}: display");
+    }
+
+    let [origin, ref body @ ..] = words[..] else {
+        return;
+    };
+    let mut obj = Vec::new();
+    obj.write_image(origin, body).unwrap();
+    assert_eq!(obj, data[..data.len() & !1], "write_image");
+
+    let mut lc3 = Box::new(Lc3::new(Headless::default()));
+    let result = lc3.load_image(&mut Chunked {
+        data: &obj,
+        chunk: usize::MAX,
+    });
+    if body.is_empty() {
+        assert_eq!(result, Err(ImageError::Empty), "load_image");
+        return;
+    }
+    if origin as usize + body.len() > 0x10000 {
+        assert_eq!(result, Err(ImageError::TooLarge), "load_image");
+        return;
+    }
+    let report = result.unwrap();
+    assert_eq!(report, LoadReport::new(origin, body.len()), "load_image");
+    let mut dump = Vec::new();
+    lc3.dump_image(origin..=origin + (body.len() - 1) as u16, &mut dump)
+        .unwrap();
+    assert_eq!(dump, obj, "dump_image");
+}
@@ -0,0 +1,265 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::lc3::Written;
+use crate::memory::LEN;
+use crate::{Error, Instruction, IoDevice, IoDeviceRegister, Lc3, Memory, Storage, TrapHandler};
+
+/// Maximum number of instructions in a block.
+const MAX_BLOCK_LEN: usize = 64;
+
+/// No block, in [`Block::links`].
+const NO_BLOCK: u32 = u32::MAX;
+
+/// A cache of superblocks translated from memory, indexed by start address.
+///
+/// A superblock is a run of instructions with a single entry, following
+/// unconditional branches and subroutine calls, and ending at the first
+/// jump, trap, or illegal opcode. Conditional branches stay in the block
+/// and leave it when taken. Blocks are chained to the blocks executed
+/// after them, so that hot paths skip the lookup by address.
+///
+/// Writing to an address translated into any block invalidates the whole
+/// cache. Memory modified by other means, such as
+/// [`Lc3::load_image`][`crate::Lc3::load_image`] or
+/// [`Memory::as_mut`][`crate::Memory`], requires a call to [`BlockCache::clear`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct BlockCache {
+    blocks: Vec<Block>,
+    /// Generation and index of the block starting at each address.
+    entries: Box<[(u32, u32)]>,
+    /// Generation each address was last translated into a block in.
+    covered: Box<[u32]>,
+    /// Current generation, never zero so that zeroed entries are invalid.
+    generation: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+struct Block {
+    /// Instructions and their addresses in execution order.
+    ops: Box<[(u16, Instruction)]>,
+    /// Start addresses and indices of the blocks last executed after this one.
+    links: [(u16, u32); 2],
+}
+
+impl BlockCache {
+    /// Initialize an empty cache.
+    pub fn new() -> BlockCache {
+        BlockCache {
+            blocks: Vec::new(),
+            entries: vec![(0, 0); LEN].into_boxed_slice(),
+            covered: vec![0; LEN].into_boxed_slice(),
+            generation: 1,
+        }
+    }
+
+    /// Invalidate all translated blocks.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.generation = self.generation.wrapping_add(1);
+        if self.generation == 0 {
+            self.entries.fill((0, 0));
+            self.covered.fill(0);
+            self.generation = 1;
+        }
+    }
+
+    /// Number of translated blocks.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// True iff no blocks are translated.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// True iff `addr` was translated into any block.
+    fn covers(&self, addr: u16) -> bool {
+        self.covered[addr as usize] == self.generation
+    }
+
+    /// Index of the block starting at `pc`, following the links of the block
+    /// `prev` executed before it, and translating it on a miss.
+    ///
+    /// Instructions at I/O device registers are never translated.
+    fn block<IO: IoDevice, S: Storage>(
+        &mut self,
+        memory: &Memory<IO, S>,
+        prev: u32,
+        pc: u16,
+    ) -> Option<u32> {
+        if prev != NO_BLOCK {
+            let links = &self.blocks[prev as usize].links;
+            if let Some(&(_, index)) = links.iter().find(|&&(addr, x)| addr == pc && x != NO_BLOCK)
+            {
+                return Some(index);
+            }
+        }
+
+        let index = match self.entries[pc as usize] {
+            (generation, index) if generation == self.generation => index,
+            _ => self.translate(memory, pc)?,
+        };
+
+        if prev != NO_BLOCK {
+            let links = &mut self.blocks[prev as usize].links;
+            links[1] = links[0];
+            links[0] = (pc, index);
+        }
+
+        Some(index)
+    }
+
+    fn translate<IO: IoDevice, S: Storage>(
+        &mut self,
+        memory: &Memory<IO, S>,
+        pc: u16,
+    ) -> Option<u32> {
+        let mut ops = Vec::new();
+        let mut addr = pc;
+        while ops.len() < MAX_BLOCK_LEN
+            && IoDeviceRegister::from_u16(addr).is_none()
+            && !ops.iter().any(|&(x, _)| x == addr)
+        {
+            let inst = Instruction::decode(memory.load(addr));
+            ops.push((addr, inst));
+            self.covered[addr as usize] = self.generation;
+
+            let next = addr.wrapping_add(1);
+            addr = match inst {
+                Instruction::Br { cc, pcoffset9 } if cc.all() => next.wrapping_add(pcoffset9),
+                Instruction::Jsr { pcoffset11 } => next.wrapping_add(pcoffset11),
+                Instruction::Jmp { .. }
+                | Instruction::Jsrr { .. }
+                | Instruction::Trap { .. }
+                | Instruction::Rti
+                | Instruction::Res => break,
+                _ => next,
+            };
+        }
+
+        if ops.is_empty() {
+            return None;
+        }
+
+        let index = self.blocks.len() as u32;
+        self.blocks.push(Block {
+            ops: ops.into_boxed_slice(),
+            links: [(0, NO_BLOCK); 2],
+        });
+        self.entries[pc as usize] = (self.generation, index);
+        Some(index)
+    }
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        BlockCache::new()
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Lc3<IO, S> {
+    /// Run indefinitely at `addr` with blocks translated into `cache` and
+    /// traps serviced by `traps` until [`Self::should_halt`] returns true.
+    pub fn run_blocks_at<T: TrapHandler<IO, S>>(
+        &mut self,
+        addr: u16,
+        cache: &mut BlockCache,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
+        self.reset();
+        self.registers.pc = addr;
+        while !self.should_halt() {
+            self.next_blocks(cache, traps, usize::MAX)?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute at most `limit` instructions from blocks translated into `cache`
+    /// with traps serviced by `traps`, stopping early if [`Self::should_halt`]
+    /// returns true. Returns the number of executed instructions.
+    ///
+    /// The machine state after each instruction is the same as that of
+    /// [`Self::next_instruction_with_trap_handler`].
+    pub fn next_blocks<T: TrapHandler<IO, S>>(
+        &mut self,
+        cache: &mut BlockCache,
+        traps: &mut T,
+        limit: usize,
+    ) -> Result<usize, Error<IO::Error>> {
+        let mut count = 0;
+        self.next_blocks_counted(cache, traps, limit, &mut count)?;
+        Ok(count)
+    }
+
+    /// Like [`Self::next_blocks`], setting `count` to the number of
+    /// instructions executed so far as they execute.
+    pub(crate) fn next_blocks_counted<T: TrapHandler<IO, S>>(
+        &mut self,
+        cache: &mut BlockCache,
+        traps: &mut T,
+        limit: usize,
+        count: &mut usize,
+    ) -> Result<(), Error<IO::Error>> {
+        *count = 0;
+        let mut prev = NO_BLOCK;
+
+        while *count < limit && !self.should_halt() {
+            let Some(index) = cache.block(&self.memory, prev, self.registers.pc) else {
+                self.next_instruction_with_trap_handler(traps)?;
+                *count += 1;
+                prev = NO_BLOCK;
+                continue;
+            };
+
+            prev = index;
+            let mut invalidate = false;
+            for &(addr, inst) in cache.blocks[index as usize].ops.iter() {
+                if *count == limit || addr != self.registers.pc {
+                    break;
+                }
+
+                let written = self.execute(inst, traps)?;
+                *count += 1;
+                match written {
+                    Written::Nothing => (),
+                    Written::Word(addr) if cache.covers(addr) => {
+                        invalidate = true;
+                        break;
+                    }
+                    // A write to the machine control register may halt the machine.
+                    Written::Word(addr) if IoDeviceRegister::from_u16(addr).is_some() => break,
+                    Written::Word(_) => (),
+                    Written::Unknown => {
+                        invalidate = true;
+                        break;
+                    }
+                }
+            }
+
+            if invalidate {
+                cache.clear();
+                prev = NO_BLOCK;
+            }
+        }
+
+        Ok(())
+    }
+}
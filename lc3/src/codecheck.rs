@@ -0,0 +1,148 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::HashSet;
+
+use crate::memory::LEN;
+use crate::{DebugInfo, Instruction, IoDevice, IoDeviceRegister, Lc3, LineKind, Monitor, Storage};
+
+/// A checker of writes to code and execution of data.
+///
+/// Reports `ST`, `STI` and `STR` overwriting an address that was already
+/// executed as an instruction, and execution of addresses assembled from
+/// data directives such as `.FILL` and `.STRINGZ`, as told by
+/// [`CodeChecker::add_debug_info`]. Every event is reported once.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{CodeChecker, CodeEvent, Headless, Lc3, VirtualTrapVectorTable};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// lc3.memory.store(0x3000, 0x1021); // ADD R0, R0, #1
+/// lc3.memory.store(0x3001, 0x31FE); // ST R0, #-2
+///
+/// let mut checker = CodeChecker::stopping();
+/// lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut checker)
+///     .unwrap();
+/// assert_eq!(checker.events(), [CodeEvent::Overwritten { pc: 0x3001, addr: 0x3000 }]);
+/// assert_eq!(lc3.memory.load(0x3000), 0x1021);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CodeChecker {
+    /// True iff the machine is halted at the first event.
+    stop: bool,
+    /// Addresses executed as instructions.
+    executed: Box<[bool]>,
+    /// Addresses assembled from data directives.
+    data: Box<[bool]>,
+    events: Vec<CodeEvent>,
+    reported: HashSet<CodeEvent>,
+}
+
+/// An event reported by [`CodeChecker`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum CodeEvent {
+    /// The instruction at `pc` writes to `addr`, already executed as an instruction.
+    Overwritten { pc: u16, addr: u16 },
+    /// The word at `pc`, assembled as data, is executed as an instruction.
+    ExecutedData { pc: u16 },
+}
+
+impl CodeChecker {
+    /// Initialize a checker reporting every event.
+    pub fn new() -> CodeChecker {
+        CodeChecker {
+            stop: false,
+            executed: vec![false; LEN].into_boxed_slice(),
+            data: vec![false; LEN].into_boxed_slice(),
+            events: Vec::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Initialize a checker halting the machine at the first event, before
+    /// the instruction causing it executes.
+    pub fn stopping() -> CodeChecker {
+        CodeChecker {
+            stop: true,
+            ..CodeChecker::new()
+        }
+    }
+
+    /// Consider the addresses assembled from data directives in `debug_info` data.
+    pub fn add_debug_info(&mut self, debug_info: &DebugInfo) {
+        for (addr, line) in debug_info.iter() {
+            self.data[addr as usize] = line.kind != LineKind::Instruction;
+        }
+    }
+
+    /// Events, in the order they first happened.
+    pub fn events(&self) -> &[CodeEvent] {
+        &self.events
+    }
+
+    fn report(&mut self, event: CodeEvent) {
+        if self.reported.insert(event) {
+            self.events.push(event);
+        }
+    }
+
+    fn check_write(&mut self, pc: u16, addr: u16) {
+        if self.executed[addr as usize] && IoDeviceRegister::from_u16(addr).is_none() {
+            self.report(CodeEvent::Overwritten { pc, addr });
+        }
+    }
+}
+
+impl Default for CodeChecker {
+    fn default() -> Self {
+        CodeChecker::new()
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Monitor<IO, S> for CodeChecker {
+    fn executing(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) -> bool {
+        if self.stop && !self.events.is_empty() {
+            return false;
+        }
+
+        if self.data[pc as usize] {
+            self.report(CodeEvent::ExecutedData { pc });
+        }
+        self.executed[pc as usize] = true;
+
+        let next = pc.wrapping_add(1);
+        match inst {
+            Instruction::St { pcoffset9, .. } => self.check_write(pc, next.wrapping_add(pcoffset9)),
+            Instruction::Sti { pcoffset9, .. } => {
+                let addr = next.wrapping_add(pcoffset9);
+                if IoDeviceRegister::from_u16(addr).is_none() {
+                    self.check_write(pc, lc3.memory.load(addr));
+                }
+            }
+            Instruction::Str { baser, offset6, .. } => {
+                self.check_write(pc, lc3.registers[baser].wrapping_add(offset6));
+            }
+            _ => (),
+        }
+
+        !self.stop || self.events.is_empty()
+    }
+
+    fn executed(&mut self, _pc: u16, _inst: Instruction, _lc3: &Lc3<IO, S>) {}
+}
@@ -0,0 +1,240 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::memory::LEN;
+use crate::{
+    CondCodes, DebugInfo, Instruction, IoDevice, Lc3, LineKind, Monitor, Storage, SymbolTable,
+};
+
+/// Code coverage: executions per address, and the directions taken by
+/// every conditional `BR`.
+///
+/// Coverage is reported in the lcov format, mapped to the lines of
+/// assembly source by [`DebugInfo`], or as an annotated disassembly.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Coverage, Headless, Lc3, VirtualTrapVectorTable};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// lc3.memory.as_mut()[0x3000] = 0x1021; // ADD R0, R0, #1
+/// lc3.memory.as_mut()[0x3001] = 0x0401; // BRz #1
+/// lc3.memory.as_mut()[0x3002] = 0xF025; // HALT
+///
+/// let mut coverage = Coverage::new();
+/// lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut coverage)
+///     .unwrap();
+/// assert_eq!(coverage.count(0x3000), 1);
+/// assert_eq!(coverage.count(0x3003), 0);
+///
+/// let branch = coverage.branch(0x3001).unwrap();
+/// assert_eq!((branch.taken, branch.not_taken), (0, 1));
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Coverage {
+    /// Executions per address.
+    counts: Box<[u64]>,
+    branches: BTreeMap<u16, BranchCoverage>,
+}
+
+/// Directions taken by a conditional `BR`, see [`Coverage::branch`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct BranchCoverage {
+    /// Number of times the branch was taken.
+    pub taken: u64,
+    /// Number of times execution fell through to the next instruction.
+    pub not_taken: u64,
+}
+
+impl Coverage {
+    /// Initialize empty coverage.
+    pub fn new() -> Coverage {
+        Coverage {
+            counts: vec![0; LEN].into_boxed_slice(),
+            branches: BTreeMap::new(),
+        }
+    }
+
+    /// Number of instructions executed at `addr`.
+    pub fn count(&self, addr: u16) -> u64 {
+        self.counts[addr as usize]
+    }
+
+    /// Directions taken by the conditional `BR` at `addr`, if one was executed there.
+    pub fn branch(&self, addr: u16) -> Option<BranchCoverage> {
+        self.branches.get(&addr).copied()
+    }
+
+    /// Add the coverage of `other`, such as that of another run of a test suite.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+        for (&addr, other) in &other.branches {
+            let branch = self.branches.entry(addr).or_default();
+            branch.taken += other.taken;
+            branch.not_taken += other.not_taken;
+        }
+    }
+
+    /// Write coverage of the instructions in the sources of `debug_info`
+    /// as an lcov tracefile, with one record per source file. Conditional
+    /// branches are found by decoding the instructions in `memory`.
+    pub fn write_lcov<W, S>(&self, w: &mut W, memory: &S, debug_info: &DebugInfo) -> io::Result<()>
+    where
+        W: Write,
+        S: Storage + ?Sized,
+    {
+        for (index, file) in debug_info.files().iter().enumerate() {
+            // Executions and conditional branches of every instruction line.
+            let mut lines: BTreeMap<u32, (u64, Option<u16>)> = BTreeMap::new();
+            for (addr, source) in debug_info.iter() {
+                if source.file != index || source.kind != LineKind::Instruction {
+                    continue;
+                }
+                let line = lines.entry(source.line).or_default();
+                line.0 += self.count(addr);
+                if is_conditional_branch(Instruction::decode(memory.load(addr))) {
+                    line.1 = Some(addr);
+                }
+            }
+
+            writeln!(w, "TN:")?;
+            writeln!(w, "SF:{file}")?;
+            let (mut branches, mut branches_hit) = (0, 0);
+            for (line, &(_, addr)) in &lines {
+                let Some(addr) = addr else { continue };
+                let branch = self.branch(addr);
+                for direction in 0..2 {
+                    match branch {
+                        Some(branch) => {
+                            let taken = [branch.taken, branch.not_taken][direction];
+                            writeln!(w, "BRDA:{line},0,{direction},{taken}")?;
+                            branches_hit += (taken != 0) as usize;
+                        }
+                        None => writeln!(w, "BRDA:{line},0,{direction},-")?,
+                    }
+                    branches += 1;
+                }
+            }
+            writeln!(w, "BRF:{branches}")?;
+            writeln!(w, "BRH:{branches_hit}")?;
+            for (line, (count, _)) in &lines {
+                writeln!(w, "DA:{line},{count}")?;
+            }
+            writeln!(w, "LF:{}", lines.len())?;
+            writeln!(w, "LH:{}", lines.values().filter(|x| x.0 != 0).count())?;
+            writeln!(w, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the words of `memory` in `regions` disassembled, with the
+    /// executions of every address and the directions taken by branches.
+    /// Addresses are labelled after `symbols`.
+    pub fn write_annotated<W, S>(
+        &self,
+        w: &mut W,
+        memory: &S,
+        regions: &[RangeInclusive<u16>],
+        symbols: &SymbolTable,
+    ) -> io::Result<()>
+    where
+        W: Write,
+        S: Storage + ?Sized,
+    {
+        let addrs = || regions.iter().flat_map(|range| range.clone());
+        let executed = addrs().filter(|&addr| self.count(addr) != 0).count();
+        let branches: Vec<_> = addrs().filter_map(|addr| self.branch(addr)).collect();
+        let directions = branches
+            .iter()
+            .map(|x| (x.taken != 0) as usize + (x.not_taken != 0) as usize)
+            .sum::<usize>();
+        writeln!(
+            w,
+            "Coverage: {executed} of {} addresses executed, {directions} of {} branch directions taken",
+            addrs().count(),
+            2 * branches.len(),
+        )?;
+
+        for range in regions {
+            writeln!(w)?;
+            writeln!(w, "      count  address  word   instruction")?;
+            for addr in range.clone() {
+                if let Some(name) = symbols.name(addr) {
+                    writeln!(w, "{name}:")?;
+                }
+
+                let word = memory.load(addr);
+                let inst = Instruction::decode(word);
+                match self.count(addr) {
+                    0 => write!(w, "      #####")?,
+                    count => write!(w, "{count:>11}")?,
+                }
+                write!(w, "  x{addr:04X}    x{word:04X}  {inst}")?;
+                if let Some(target) = inst.target(addr) {
+                    write!(w, "  ; {}", symbols.display(target))?;
+                }
+                if let Some(branch) = self.branch(addr) {
+                    write!(
+                        w,
+                        "  [taken {}, not taken {}]",
+                        branch.taken, branch.not_taken
+                    )?;
+                }
+                writeln!(w)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Monitor<IO, S> for Coverage {
+    fn executed(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) {
+        self.counts[pc as usize] += 1;
+
+        if let Instruction::Br { cc, .. } = inst {
+            if is_conditional_branch(inst) {
+                // BR leaves the condition codes as they were when it executed.
+                let branch = self.branches.entry(pc).or_default();
+                if lc3.registers.cc.intersects(cc) {
+                    branch.taken += 1;
+                } else {
+                    branch.not_taken += 1;
+                }
+            }
+        }
+    }
+}
+
+/// True iff `inst` is a `BR` that is neither always nor never taken.
+fn is_conditional_branch(inst: Instruction) -> bool {
+    matches!(inst, Instruction::Br { cc, .. } if cc != CondCodes::NONE && cc != CondCodes::ALL)
+}
@@ -0,0 +1,282 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::BTreeMap;
+
+use crate::SymbolTable;
+
+/// A map from addresses to the lines of the assembly source they were
+/// assembled from.
+///
+/// Sources are read in the syntax accepted by common LC-3 assemblers:
+/// every instruction and `.FILL` occupies one word, `.BLKW n` occupies `n`
+/// words, and `.STRINGZ "..."` occupies one word per character plus the
+/// terminating zero, starting at the address given by the preceding `.ORIG`.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{DebugInfo, LineKind};
+/// let mut debug_info = DebugInfo::new();
+/// debug_info.add_source(
+///     "count.asm",
+///     "        .ORIG x3000
+///              AND R0, R0, #0 ; clear the counter
+///      LOOP    ADD R0, R0, #1
+///              BRnp LOOP
+///              HALT
+///      MSG     .STRINGZ \"done\"
+///              .END",
+/// );
+///
+/// let line = debug_info.line(0x3002).unwrap();
+/// assert_eq!((line.file, line.line, line.kind), (0, 4, LineKind::Instruction));
+/// assert_eq!(debug_info.line(0x3008).unwrap().kind, LineKind::Data);
+/// assert_eq!(debug_info.line(0x3009), None);
+/// assert_eq!(debug_info.symbols().get("MSG"), Some(0x3004));
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct DebugInfo {
+    files: Vec<String>,
+    lines: BTreeMap<u16, SourceLine>,
+    symbols: SymbolTable,
+}
+
+/// A line of assembly source, see [`DebugInfo::line`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct SourceLine {
+    /// Index of the source file in [`DebugInfo::files`].
+    pub file: usize,
+    /// Line number, starting at 1.
+    pub line: u32,
+    /// What the line assembles to.
+    pub kind: LineKind,
+}
+
+/// What a line of assembly source assembles to, see [`SourceLine`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum LineKind {
+    /// An instruction.
+    Instruction,
+    /// Initialized data, from `.FILL` or `.STRINGZ`.
+    Data,
+    /// Space reserved by `.BLKW`.
+    Reserved,
+}
+
+impl DebugInfo {
+    /// Initialize debug info without sources.
+    pub fn new() -> DebugInfo {
+        DebugInfo::default()
+    }
+
+    /// Add the assembly source `text` of the file `name`, returning its index.
+    ///
+    /// Addresses assembled from multiple sources keep the last one.
+    /// Lines that cannot be parsed are skipped.
+    pub fn add_source(&mut self, name: impl Into<String>, text: &str) -> usize {
+        let file = self.files.len();
+        self.files.push(name.into());
+
+        let mut addr = None;
+        for (line, code) in (1..).zip(text.lines()) {
+            let code = strip_comment(code);
+            let (mut op, mut rest) = split_word(code);
+            if op.is_empty() {
+                continue;
+            }
+            if !is_opcode(op) && !op.starts_with('.') {
+                if let Some(addr) = addr {
+                    let _ = self.symbols.insert(op.trim_end_matches(':'), addr);
+                }
+                (op, rest) = split_word(rest);
+                if op.is_empty() {
+                    continue;
+                }
+            }
+
+            let op = op.to_ascii_uppercase();
+            let (words, kind) = match op.as_str() {
+                ".ORIG" => {
+                    addr = parse_number(split_word(rest).0);
+                    continue;
+                }
+                ".END" => {
+                    addr = None;
+                    continue;
+                }
+                ".FILL" => (1, LineKind::Data),
+                ".BLKW" => match parse_number(split_word(rest).0) {
+                    Some(n) => (n, LineKind::Reserved),
+                    None => continue,
+                },
+                ".STRINGZ" => match string_len(rest) {
+                    Some(n) => (n + 1, LineKind::Data),
+                    None => continue,
+                },
+                _ if op.starts_with('.') => continue,
+                _ => (1, LineKind::Instruction),
+            };
+
+            let Some(start) = addr else { continue };
+            for offset in 0..words {
+                let source = SourceLine { file, line, kind };
+                self.lines.insert(start.wrapping_add(offset), source);
+            }
+            addr = Some(start.wrapping_add(words));
+        }
+
+        file
+    }
+
+    /// Add the sources of `other`, after those already added.
+    ///
+    /// Addresses and labels defined by both keep those of `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lc3::DebugInfo;
+    /// let mut debug_info = DebugInfo::new();
+    /// debug_info.add_source("main.asm", ".ORIG x3000\nMAIN HALT\n.END");
+    /// let mut source = DebugInfo::new();
+    /// source.add_source("sub.asm", ".ORIG x4000\nSUB RET\n.END");
+    /// debug_info.merge(source);
+    ///
+    /// assert_eq!(debug_info.files(), ["main.asm", "sub.asm"]);
+    /// assert_eq!(debug_info.line(0x4000).unwrap().file, 1);
+    /// assert_eq!(debug_info.symbols().get("SUB"), Some(0x4000));
+    /// ```
+    pub fn merge(&mut self, other: DebugInfo) {
+        let offset = self.files.len();
+        self.files.extend(other.files);
+        for (addr, line) in other.lines {
+            let file = line.file + offset;
+            self.lines.insert(addr, SourceLine { file, ..line });
+        }
+        for (name, addr) in other.symbols.iter() {
+            self.symbols.insert(name, addr);
+        }
+    }
+
+    /// Names of the source files, in the order they were added.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Source line `addr` was assembled from.
+    pub fn line(&self, addr: u16) -> Option<SourceLine> {
+        self.lines.get(&addr).copied()
+    }
+
+    /// Iterate over addresses with a source line, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, SourceLine)> + '_ {
+        self.lines.iter().map(|(&addr, &line)| (addr, line))
+    }
+
+    /// Labels defined by the sources.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+}
+
+/// Strip the comment, if any, from the line of code `code`.
+fn strip_comment(code: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in code.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &code[..i],
+            _ => (),
+        }
+    }
+    code
+}
+
+/// Split the first word, ended by whitespace or a comma, off `s`.
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    let end = s
+        .find(|c: char| c.is_whitespace() || c == ',')
+        .unwrap_or(s.len());
+    (
+        &s[..end],
+        s[end..].trim_start_matches(|c: char| c.is_whitespace() || c == ','),
+    )
+}
+
+/// True iff `word` is an instruction mnemonic, including trap aliases.
+fn is_opcode(word: &str) -> bool {
+    const OPCODES: [&str; 22] = [
+        "ADD", "AND", "NOT", "JMP", "RET", "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI",
+        "STR", "TRAP", "RTI", "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT",
+    ];
+    let word = word.to_ascii_uppercase();
+    if let Some(cc) = word.strip_prefix("BR") {
+        return ["", "N", "Z", "P", "NZ", "NP", "ZP", "NZP"].contains(&cc);
+    }
+    OPCODES.contains(&word.as_str())
+}
+
+/// Parse a number written as in LC-3 assembly, `x3000`, `0x3000`, `b101`,
+/// `#12288` or `12288`, into any integer type it fits in.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(lc3::parse_number::<u16>("x3000"), Some(0x3000));
+/// assert_eq!(lc3::parse_number::<u8>("#-1"), None);
+/// assert_eq!(lc3::parse_number::<usize>("100000"), Some(100_000));
+/// assert_eq!(lc3::parse_number::<u16>("100000"), None);
+/// ```
+pub fn parse_number<T: TryFrom<u64>>(s: &str) -> Option<T> {
+    let (digits, radix) = if let Some(hex) = s.strip_prefix(['x', 'X']) {
+        (hex, 16)
+    } else if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(bin) = s.strip_prefix(['b', 'B']) {
+        (bin, 2)
+    } else {
+        (s.strip_prefix('#').unwrap_or(s), 10)
+    };
+    if digits.starts_with('+') {
+        return None;
+    }
+    let n = u64::from_str_radix(digits, radix).ok()?;
+    T::try_from(n).ok()
+}
+
+/// Number of characters of the quoted string starting `s`, with escapes
+/// counted as one character.
+fn string_len(s: &str) -> Option<u16> {
+    let mut chars = s.trim_start().strip_prefix('"')?.chars();
+    let mut len = 0u16;
+    loop {
+        match chars.next()? {
+            '"' => return Some(len),
+            '\\' => {
+                chars.next()?;
+            }
+            _ => (),
+        }
+        len = len.wrapping_add(1);
+    }
+}
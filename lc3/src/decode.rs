@@ -16,7 +16,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
-use crate::{CondCodes, OpCode, Reg, TrapCode};
+use crate::{CondCodes, OpCode, Reg};
 
 /// Instruction decoding functions.
 ///
@@ -49,15 +49,6 @@ pub(crate) trait InstructionDecode {
     /// three 1-bit registers, but treating them as one
     /// 3-bit value is more efficient to.
     fn condcodes(self) -> CondCodes;
-    /// Trap code; bits \[7:0\].
-    ///
-    /// ```text
-    ///  XXXX XXXX XXXXXXXX
-    ///           │        │
-    ///           └────────┘
-    ///            trapcode
-    /// ```
-    fn trapcode(self) -> Option<TrapCode>;
     /// A DR/SR register value; bits \[11:9\].
     ///
     /// ```text
@@ -111,16 +102,6 @@ pub(crate) trait InstructionDecode {
     /// ```
     #[doc(alias = "offset6")]
     fn imm6(self) -> u16;
-    /// An 8-bit sign-extended value; bits \[7:0\] of an instruction.
-    ///
-    /// ```text
-    ///  XXXX XXXXX XXXXXXX
-    ///            │       │
-    ///            └───────┘
-    ///              imm8
-    /// ```
-    #[doc(alias = "trapvect8")]
-    fn imm8(self) -> u16;
     /// A 9-bit sign-extended value; bits \[8:0\] of an instruction.
     ///
     /// ```text
@@ -155,17 +136,13 @@ impl InstructionDecode for u16 {
         //
         // but std::mem::variant_count is nightly-only as of now.
         //
-        unsafe { core::mem::transmute((self >> 12) as i8) }
+        unsafe { core::mem::transmute((self >> 12) as u8) }
     }
 
     fn condcodes(self) -> CondCodes {
         CondCodes::from_u16(self >> 9)
     }
 
-    fn trapcode(self) -> Option<TrapCode> {
-        TrapCode::from_u16(self)
-    }
-
     fn reg1(self) -> Reg {
         Reg::from_u16(self >> 9)
     }
@@ -198,14 +175,6 @@ impl InstructionDecode for u16 {
         }
     }
 
-    fn imm8(self) -> u16 {
-        if self & 0x0080 == 0 {
-            self & 0x00FF
-        } else {
-            self | 0xFF00
-        }
-    }
-
     fn imm9(self) -> u16 {
         if self & 0x0100 == 0 {
             self & 0x01FF
@@ -0,0 +1,185 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::lc3::Startup;
+use crate::{BlockCache, DecodeCache, Error, IoDevice, Lc3, Storage, TrapHandler};
+use std::fmt;
+use std::str::FromStr;
+
+/// An execution engine, selecting how instructions are fetched and dispatched.
+///
+/// All engines leave the machine in the same state after each instruction.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Engine, Lc3, VirtualTrapVectorTable};
+/// # #[derive(Default)]
+/// # struct Null;
+/// # impl lc3::IoDevice for Null {
+/// #     type Error = ();
+/// #     fn read(&mut self, _: &mut [u8]) -> Result<usize, ()> { Ok(0) }
+/// #     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> { Ok(buf.len()) }
+/// #     fn flush(&mut self) -> Result<(), ()> { Ok(()) }
+/// # }
+/// let mut lc3 = Lc3::new(Null);
+/// lc3.memory.as_mut()[0x3000] = 0x1021; // ADD R0, R0, #1
+/// lc3.memory.as_mut()[0x3001] = 0x0FFE; // BRnzp #-2
+///
+/// let mut engine: Engine = "blocks".parse().unwrap();
+/// lc3.registers.pc = 0x3000;
+/// lc3.reset();
+/// let count = engine.next(&mut lc3, &mut VirtualTrapVectorTable, 10).unwrap();
+/// assert_eq!(count, 10);
+/// assert_eq!(lc3.registers.r0, 5);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub enum Engine {
+    /// Fetch and decode every instruction from memory.
+    #[default]
+    Interpreter,
+    /// Fetch instructions from a [`DecodeCache`].
+    Predecoded(DecodeCache),
+    /// Execute superblocks translated into a [`BlockCache`].
+    Blocks(BlockCache),
+}
+
+impl Engine {
+    /// Names of all engines, as accepted by [`Engine::from_str`].
+    pub const NAMES: [&'static str; 3] = ["interpreter", "predecoded", "blocks"];
+
+    /// Name of the engine.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Engine::Interpreter => Self::NAMES[0],
+            Engine::Predecoded(_) => Self::NAMES[1],
+            Engine::Blocks(_) => Self::NAMES[2],
+        }
+    }
+
+    /// Invalidate everything the engine cached from memory, required after
+    /// modifying memory other than by executing instructions.
+    pub fn clear(&mut self) {
+        match self {
+            Engine::Interpreter => (),
+            Engine::Predecoded(cache) => cache.clear(),
+            Engine::Blocks(cache) => cache.clear(),
+        }
+    }
+
+    /// Execute at most `limit` instructions of `lc3` with traps serviced by
+    /// `traps`, stopping early if [`Lc3::should_halt`] returns true.
+    /// Returns the number of executed instructions.
+    pub fn next<IO: IoDevice, S: Storage, T: TrapHandler<IO, S>>(
+        &mut self,
+        lc3: &mut Lc3<IO, S>,
+        traps: &mut T,
+        limit: usize,
+    ) -> Result<usize, Error<IO::Error>> {
+        let mut count = 0;
+        self.next_counted(lc3, traps, limit, &mut count)?;
+        Ok(count)
+    }
+
+    /// Like [`Engine::next`], setting `count` to the number of instructions
+    /// executed so far as they execute, so that it also holds the number
+    /// executed before an error.
+    pub fn next_counted<IO: IoDevice, S: Storage, T: TrapHandler<IO, S>>(
+        &mut self,
+        lc3: &mut Lc3<IO, S>,
+        traps: &mut T,
+        limit: usize,
+        count: &mut usize,
+    ) -> Result<(), Error<IO::Error>> {
+        *count = 0;
+        match self {
+            Engine::Interpreter => {
+                while *count < limit && !lc3.should_halt() {
+                    lc3.next_instruction_with_trap_handler(traps)?;
+                    *count += 1;
+                }
+            }
+            Engine::Predecoded(cache) => {
+                while *count < limit && !lc3.should_halt() {
+                    lc3.next_instruction_predecoded(cache, traps)?;
+                    *count += 1;
+                }
+            }
+            Engine::Blocks(cache) => lc3.next_blocks_counted(cache, traps, limit, count)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Engine {
+    type Err = UnknownEngine;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpreter" => Ok(Engine::Interpreter),
+            "predecoded" => Ok(Engine::Predecoded(DecodeCache::new())),
+            "blocks" => Ok(Engine::Blocks(BlockCache::new())),
+            _ => Err(UnknownEngine),
+        }
+    }
+}
+
+/// Error parsing an [`Engine`] name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct UnknownEngine;
+
+impl fmt::Display for UnknownEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown engine, expected one of: {}",
+            Engine::NAMES.join(", ")
+        )
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Lc3<IO, S> {
+    /// Run indefinitely at `addr` with instructions executed by `engine`
+    /// and traps serviced by `traps` until [`Self::should_halt`] returns true.
+    pub fn run_with_engine_at<T: TrapHandler<IO, S>>(
+        &mut self,
+        addr: u16,
+        engine: &mut Engine,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
+        self.reset();
+        self.registers.pc = addr;
+        while !self.should_halt() {
+            engine.next(self, traps, usize::MAX)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like [`Self::boot_with_trap_handler`], with instructions executed by `engine`.
+    pub fn boot_with_engine<T: TrapHandler<IO, S>>(
+        &mut self,
+        entry: u16,
+        engine: &mut Engine,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
+        self.run_with_engine_at(Self::OPERATING_SYSTEM_START, engine, &mut Startup(traps))?;
+        self.run_with_engine_at(entry, engine, traps)
+    }
+}
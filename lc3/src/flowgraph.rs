@@ -0,0 +1,317 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::memory::LEN;
+use crate::{CondCodes, Instruction, IoDeviceRegister, Reg, Storage, SymbolTable, TrapCode};
+
+/// Static control-flow graph of the code reachable from a set of entry
+/// points, made of basic blocks linked by [`Edge`]s.
+///
+/// Instructions are decoded from memory without running them. Targets of
+/// `BR` and `JSR` are followed, as are the instructions after a `JSR`,
+/// `JSRR` or `TRAP`, assuming the routine returns. Targets of `JMP` and
+/// `JSRR` are unknown statically, and trap service routines are not
+/// explored. Code never flows into the memory mapped I/O registers.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{ControlFlowGraph, Edge, Headless, Lc3};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// lc3.memory.as_mut()[0x3000] = 0x5020; // AND R0, R0, #0
+/// lc3.memory.as_mut()[0x3001] = 0x1021; // ADD R0, R0, #1
+/// lc3.memory.as_mut()[0x3002] = 0x09FE; // BRn #-2
+/// lc3.memory.as_mut()[0x3003] = 0xF025; // HALT
+///
+/// let cfg = ControlFlowGraph::build(lc3.memory.storage(), &[0x3000]);
+/// let starts: Vec<_> = cfg.blocks().map(|block| block.start).collect();
+/// assert_eq!(starts, [0x3000, 0x3001, 0x3003]);
+/// let block = cfg.block(0x3002).unwrap();
+/// assert_eq!(block.successors, [Edge::Branch(0x3001), Edge::Next(0x3003)]);
+/// assert_eq!(cfg.block(0x3003).unwrap().successors, [Edge::Trap(0x25)]);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ControlFlowGraph {
+    entries: Vec<u16>,
+    routines: BTreeSet<u16>,
+    blocks: BTreeMap<u16, BasicBlock>,
+}
+
+/// Straight-line sequence of instructions, entered only at its start and
+/// left only after its end.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct BasicBlock {
+    /// Address of the first instruction.
+    pub start: u16,
+    /// Address of the last instruction.
+    pub end: u16,
+    /// Where control flows after the last instruction.
+    pub successors: Vec<Edge>,
+}
+
+/// Flow of control out of a [`BasicBlock`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Edge {
+    /// Falling through to the block at the address.
+    Next(u16),
+    /// `BR` taken to the block at the address.
+    Branch(u16),
+    /// `JSR` to the routine at the address.
+    Call(u16),
+    /// `JSRR` to a routine unknown statically.
+    IndirectCall,
+    /// `JMP` or `RTI` to an address unknown statically.
+    IndirectJump,
+    /// `RET` from a routine.
+    Return,
+    /// `TRAP` to the service routine with the vector.
+    Trap(u8),
+}
+
+impl Edge {
+    /// Address of the block the edge leads to, if known.
+    pub fn target(self) -> Option<u16> {
+        match self {
+            Edge::Next(addr) | Edge::Branch(addr) | Edge::Call(addr) => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+impl ControlFlowGraph {
+    /// Build the graph of the code in `memory` reachable from `entries`.
+    pub fn build<S: Storage + ?Sized>(memory: &S, entries: &[u16]) -> ControlFlowGraph {
+        let is_code = |addr: u16| {
+            (addr as usize) < memory.len() && IoDeviceRegister::from_u16(addr).is_none()
+        };
+
+        let mut reached = vec![false; LEN].into_boxed_slice();
+        let mut leaders = BTreeSet::new();
+        let mut routines: BTreeSet<u16> = entries.iter().copied().collect();
+        let mut pending = entries.to_vec();
+        while let Some(start) = pending.pop() {
+            if !is_code(start) {
+                continue;
+            }
+            leaders.insert(start);
+            let mut addr = start;
+            loop {
+                if reached[addr as usize] {
+                    // Flowed into code already explored, which must start a block.
+                    leaders.insert(addr);
+                    break;
+                }
+                reached[addr as usize] = true;
+                let inst = Instruction::decode(memory.load(addr));
+                if let Some(successors) = successors(addr, inst) {
+                    for edge in successors {
+                        if let Edge::Call(target) = edge {
+                            routines.insert(target);
+                        }
+                        pending.extend(edge.target());
+                    }
+                    break;
+                }
+                addr = addr.wrapping_add(1);
+                if !is_code(addr) {
+                    break;
+                }
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let mut end = start;
+            let successors = loop {
+                let inst = Instruction::decode(memory.load(end));
+                if let Some(successors) = successors(end, inst) {
+                    break successors;
+                }
+                let next = end.wrapping_add(1);
+                if !reached[next as usize] {
+                    break Vec::new();
+                }
+                if leaders.contains(&next) {
+                    break vec![Edge::Next(next)];
+                }
+                end = next;
+            };
+            blocks.insert(
+                start,
+                BasicBlock {
+                    start,
+                    end,
+                    successors,
+                },
+            );
+        }
+
+        let mut unique = BTreeSet::new();
+        ControlFlowGraph {
+            entries: entries
+                .iter()
+                .copied()
+                .filter(|&x| unique.insert(x))
+                .collect(),
+            routines,
+            blocks,
+        }
+    }
+
+    /// Entry points the graph was built from.
+    pub fn entries(&self) -> &[u16] {
+        &self.entries
+    }
+
+    /// Addresses of the entry points and of all routines called by `JSR`,
+    /// in ascending order.
+    pub fn routines(&self) -> impl Iterator<Item = u16> + '_ {
+        self.routines.iter().copied()
+    }
+
+    /// Basic blocks in ascending order of address.
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    /// Basic block containing the instruction at `addr`, if reachable.
+    pub fn block(&self, addr: u16) -> Option<&BasicBlock> {
+        let (_, block) = self.blocks.range(..=addr).next_back()?;
+        (addr <= block.end).then_some(block)
+    }
+
+    /// Write the graph in the Graphviz DOT language, with blocks
+    /// disassembled from `memory` and addresses labelled after `symbols`.
+    pub fn write_dot<W, S>(&self, w: &mut W, memory: &S, symbols: &SymbolTable) -> io::Result<()>
+    where
+        W: Write,
+        S: Storage + ?Sized,
+    {
+        writeln!(w, "digraph cfg {{")?;
+        writeln!(w, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        let mut outside = BTreeSet::new();
+        let mut traps = BTreeSet::new();
+        let mut unknown = false;
+        for block in self.blocks() {
+            let mut label = String::new();
+            for addr in block.start..=block.end {
+                if let Some(name) = symbols.name(addr) {
+                    let _ = write!(label, "{name}:\\l");
+                }
+                let inst = Instruction::decode(memory.load(addr));
+                let _ = write!(label, "x{addr:04X}  {inst}");
+                if let Some(target) = inst.target(addr) {
+                    let _ = write!(label, "  ; {}", symbols.display(target));
+                }
+                label.push_str("\\l");
+            }
+            writeln!(
+                w,
+                "    b{:04X} [label=\"{}\"];",
+                block.start,
+                escape(&label)
+            )?;
+
+            let branches = block
+                .successors
+                .iter()
+                .any(|x| matches!(x, Edge::Branch(_)));
+            for &edge in &block.successors {
+                let from = block.start;
+                match edge {
+                    Edge::Next(to) if branches => {
+                        writeln!(w, "    b{from:04X} -> b{to:04X} [label=\"not taken\"];")?
+                    }
+                    Edge::Next(to) => writeln!(w, "    b{from:04X} -> b{to:04X};")?,
+                    Edge::Branch(to) => {
+                        writeln!(w, "    b{from:04X} -> b{to:04X} [label=\"taken\"];")?
+                    }
+                    Edge::Call(to) => writeln!(
+                        w,
+                        "    b{from:04X} -> b{to:04X} [label=\"call\", style=dashed];"
+                    )?,
+                    Edge::IndirectCall => writeln!(
+                        w,
+                        "    b{from:04X} -> unknown [label=\"call\", style=dashed];"
+                    )?,
+                    Edge::IndirectJump => writeln!(w, "    b{from:04X} -> unknown;")?,
+                    Edge::Return => {}
+                    Edge::Trap(vector) => {
+                        writeln!(w, "    b{from:04X} -> trap{vector:02X} [style=dashed];")?
+                    }
+                }
+                match edge {
+                    Edge::IndirectCall | Edge::IndirectJump => unknown = true,
+                    Edge::Trap(vector) => {
+                        traps.insert(vector);
+                    }
+                    _ => {}
+                }
+                if let Some(to) = edge.target() {
+                    if !self.blocks.contains_key(&to) {
+                        outside.insert(to);
+                    }
+                }
+            }
+        }
+
+        for addr in outside {
+            let label = escape(&symbols.display(addr).to_string());
+            writeln!(w, "    b{addr:04X} [label=\"{label}\", shape=plaintext];")?;
+        }
+        for vector in traps {
+            let name = Instruction::Trap { trapvect8: vector };
+            writeln!(w, "    trap{vector:02X} [label=\"{name}\", shape=ellipse];")?;
+        }
+        if unknown {
+            writeln!(w, "    unknown [label=\"?\", shape=ellipse];")?;
+        }
+        writeln!(w, "}}")
+    }
+}
+
+/// Edges out of `inst` at `pc`, or `None` if it does not end a block.
+fn successors(pc: u16, inst: Instruction) -> Option<Vec<Edge>> {
+    let next = pc.wrapping_add(1);
+    let target = inst.target(pc);
+    Some(match inst {
+        Instruction::Br { cc, .. } if cc == CondCodes::NONE => return None,
+        Instruction::Br { cc, .. } if cc == CondCodes::ALL => vec![Edge::Branch(target?)],
+        Instruction::Br { .. } => vec![Edge::Branch(target?), Edge::Next(next)],
+        Instruction::Jmp { baser: Reg::R7 } => vec![Edge::Return],
+        Instruction::Jmp { .. } | Instruction::Rti => vec![Edge::IndirectJump],
+        Instruction::Jsr { .. } => vec![Edge::Call(target?), Edge::Next(next)],
+        Instruction::Jsrr { .. } => vec![Edge::IndirectCall, Edge::Next(next)],
+        Instruction::Trap { trapvect8 } if trapvect8 as u16 == TrapCode::Halt as u16 => {
+            vec![Edge::Trap(trapvect8)]
+        }
+        Instruction::Trap { trapvect8 } => vec![Edge::Trap(trapvect8), Edge::Next(next)],
+        Instruction::Res => Vec::new(),
+        _ => return None,
+    })
+}
+
+/// `s` escaped for a DOT string literal, keeping `\l` line breaks.
+fn escape(s: &str) -> String {
+    s.replace('"', "\\\"")
+}
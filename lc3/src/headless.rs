@@ -0,0 +1,98 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::convert::Infallible;
+
+use crate::IoDevice;
+
+/// An [`IoDevice`][`crate::IoDevice`] reading keyboard input from a buffer
+/// and capturing display output, for running machines without a terminal.
+///
+/// The keyboard has input ready until the buffer is exhausted, after which
+/// reads return no bytes.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Headless, Lc3, VirtualTrapVectorTable};
+/// let mut lc3 = Lc3::new(Headless::new("a"));
+/// lc3.memory.as_mut()[0x3000] = 0xF020; // GETC
+/// lc3.memory.as_mut()[0x3001] = 0xF021; // OUT
+/// lc3.memory.as_mut()[0x3002] = 0xF021; // OUT
+/// lc3.memory.as_mut()[0x3003] = 0xF025; // HALT
+/// lc3.run_with_virtual_trap_vector_table().unwrap();
+/// assert_eq!(lc3.memory.io().output(), b"aa\n\n--- halting the LC-3 ---\n\n");
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct Headless {
+    input: Vec<u8>,
+    /// Number of bytes of `input` read.
+    consumed: usize,
+    output: Vec<u8>,
+}
+
+impl Headless {
+    /// Initialize a device with keyboard input `input`.
+    pub fn new(input: impl Into<Vec<u8>>) -> Headless {
+        Headless {
+            input: input.into(),
+            consumed: 0,
+            output: Vec::new(),
+        }
+    }
+
+    /// Keyboard input not read yet.
+    pub fn remaining_input(&self) -> &[u8] {
+        &self.input[self.consumed..]
+    }
+
+    /// All display output so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Take the display output.
+    pub fn into_output(self) -> Vec<u8> {
+        self.output
+    }
+}
+
+impl IoDevice for Headless {
+    type Error = Infallible;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let input = self.remaining_input();
+        let n = input.len().min(buf.len());
+        buf[..n].copy_from_slice(&input[..n]);
+        self.consumed += n;
+        Ok(n)
+    }
+
+    fn poll(&self) -> bool {
+        self.consumed < self.input.len()
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
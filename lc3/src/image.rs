@@ -16,7 +16,10 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
-use core::slice;
+use crate::Storage;
+
+use core::fmt;
+use core::ops::Range;
 
 /// An image file interface.
 #[cfg_attr(
@@ -36,52 +39,391 @@ pub trait ImageFile {
     /// guarantee that `0 <= n <= buf.len()`. A nonzero `n` value indicates
     /// that the buffer `buf` has been filled in with `n` bytes of data from this
     /// source. If `n` is `0`, then it can indicate one of two scenarios:
+    ///
+    /// 1. This source has reached its end of file.
+    /// 2. The buffer specified was 0 bytes in length.
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
 
     /// Load image into `memory`.
-    fn load_image_into(&mut self, memory: &mut [u16]) -> Result<(), Self::Error> {
-        let mut origin: [u8; 2] = [0; 2];
-        self.read(&mut origin)?;
-        let origin = u16::from_be_bytes(origin) as usize;
-
-        // Memory at `origin` as a slice of `u8` bytes
-        let slice = {
-            let data = &mut memory[origin] as *mut u16 as *mut u8;
-            let len = (memory.len() - origin) * 2;
-            unsafe { slice::from_raw_parts_mut(data, len) }
-        };
-
-        let end /* exclusive */ = origin + self.read(slice)? / 2;
-
-        //
-        // Proof that `end <= memory.len`
-        //
-        // [`ImageFile::read`] guarantees `0 <= n <= slice.len()`,
-        // where `n` is the return value of [`ImageFile::read`].
-        //
-        // Definitions:
-        // - `origin`: an arbitrary number.
-        // - `end`: `origin+n/2`.
-        // - `slice.len`: `2(memory.len-origin)`.
-        //
-        // 0      <=           n    <=               slice.len
-        // 0      <=           n/2  <=               slice.len/2
-        // origin <= (origin + n/2) <=               slice.len/2
-        // origin <= (origin + n/2) <= origin +      slice.len/2
-        // origin <= (origin + n/2) <= origin + (memory.len - origin)
-        // origin <=       end      <= memory.len
-        //
-        // From this it follows that: `end <= memory.len`. Q.E.D.
-        //
-
-        unsafe {
-            memory
-                .get_unchecked_mut(origin..end)
-                .iter_mut()
-                .for_each(|x| *x = u16::from_be(*x))
+    ///
+    /// Words are written to `memory` as they are read, so on error
+    /// `memory` may be partially overwritten.
+    fn load_image_into<S: Storage + ?Sized>(
+        &mut self,
+        memory: &mut S,
+    ) -> Result<LoadReport, ImageError<Self::Error>> {
+        let mut words = Words::new(self);
+        let origin = words.next()?.ok_or(ImageError::Empty)?;
+        non_empty(load_words_into(words, memory, origin)?)
+    }
+
+    /// Load image in `format` into `memory`.
+    fn load_image_as<S: Storage + ?Sized>(
+        &mut self,
+        memory: &mut S,
+        format: ImageFormat,
+    ) -> Result<LoadReport, ImageError<Self::Error>> {
+        match format {
+            ImageFormat::Object => self.load_image_into(memory),
+            ImageFormat::Raw(origin) => self.load_raw_image_into(memory, origin),
+            ImageFormat::Hex => load_text_image_into(self, memory, 16),
+            ImageFormat::Binary => load_text_image_into(self, memory, 2),
+        }
+    }
+
+    /// Load headerless image into `memory` at `origin`.
+    fn load_raw_image_into<S: Storage + ?Sized>(
+        &mut self,
+        memory: &mut S,
+        origin: u16,
+    ) -> Result<LoadReport, ImageError<Self::Error>> {
+        non_empty(load_words_into(Words::new(self), memory, origin)?)
+    }
+}
+
+/// An image writer interface, the counterpart of [`ImageFile`].
+#[cfg_attr(
+    feature = "std",
+    doc = "
+
+With the `std` feature enabled, all types that implement
+[`std::io::Write`] also implement [`ImageWriter`]."
+)]
+pub trait ImageWriter {
+    type Error;
+
+    /// Write all bytes in `buf`.
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error>;
+
+    /// Write `words` as an `.obj` image loaded at `origin`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lc3::ImageWriter;
+    /// let mut obj = Vec::new();
+    /// obj.write_image(0x3000, &[0xF025]).unwrap();
+    /// assert_eq!(obj, [0x30, 0x00, 0xF0, 0x25]);
+    /// ```
+    fn write_image(&mut self, origin: u16, words: &[u16]) -> Result<(), Self::Error> {
+        write_words(self, origin, words.iter().copied())
+    }
+}
+
+/// Write `words` as an `.obj` image loaded at `origin` to `writer`.
+pub(crate) fn write_words<W: ImageWriter + ?Sized>(
+    writer: &mut W,
+    origin: u16,
+    mut words: impl Iterator<Item = u16>,
+) -> Result<(), W::Error> {
+    writer.write_all(&origin.to_be_bytes())?;
+    let mut buf = [0; 256];
+    loop {
+        let mut len = 0;
+        for (bytes, word) in buf.chunks_exact_mut(2).zip(&mut words) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+            len += 2;
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buf[..len])?;
+    }
+}
+
+/// What an image was loaded as, returned by [`ImageFile::load_image_into`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct LoadReport {
+    /// Address of the first word loaded.
+    pub origin: u16,
+    /// Number of words loaded, never zero.
+    pub len: usize,
+    /// Memory addresses from the first through the last word of the image
+    /// that previous images loaded too. Only images loaded with
+    /// [`Lc3::load_image`][`crate::Lc3::load_image`] are tracked, into the
+    /// same machine; loading into bare storage always reports `None`.
+    pub overwritten: Option<Range<usize>>,
+}
+
+impl LoadReport {
+    /// A report of `len` words loaded at `origin`, overwriting nothing.
+    pub const fn new(origin: u16, len: usize) -> LoadReport {
+        LoadReport {
+            origin,
+            len,
+            overwritten: None,
+        }
+    }
+
+    /// Memory addresses the image was loaded to.
+    pub fn range(&self) -> Range<usize> {
+        self.origin as usize..self.origin as usize + self.len
+    }
+
+    /// Memory addresses loaded by both `self` and `other`, i.e. the region of a
+    /// previously loaded image `other` overwritten by `self`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lc3::LoadReport;
+    /// let os = LoadReport::new(0x0000, 0x0500);
+    /// let user = LoadReport::new(0x3000, 0x0100);
+    /// let patch = LoadReport::new(0x04F0, 0x0020);
+    /// assert_eq!(user.overlap(&os), None);
+    /// assert_eq!(patch.overlap(&os), Some(0x04F0..0x0500));
+    /// ```
+    pub fn overlap(&self, other: &LoadReport) -> Option<Range<usize>> {
+        let start = self.range().start.max(other.range().start);
+        let end = self.range().end.min(other.range().end);
+        (start < end).then_some(start..end)
+    }
+}
+
+/// Big-endian words read from an [`ImageFile`] until its end.
+struct Words<'a, F: ImageFile + ?Sized> {
+    file: &'a mut F,
+    buf: [u8; 256],
+    pos: usize,
+    len: usize,
+}
+
+impl<'a, F: ImageFile + ?Sized> Words<'a, F> {
+    fn new(file: &'a mut F) -> Words<'a, F> {
+        Words {
+            file,
+            buf: [0; 256],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, ImageError<F::Error>> {
+        if self.pos == self.len {
+            self.pos = 0;
+            self.len = self.file.read(&mut self.buf).map_err(ImageError::Io)?;
+            if self.len == 0 {
+                return Ok(None);
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    fn next(&mut self) -> Result<Option<u16>, ImageError<F::Error>> {
+        match (self.next_byte()?, self.next_byte()?) {
+            (Some(hi), Some(lo)) => Ok(Some(u16::from_be_bytes([hi, lo]))),
+            (Some(_), None) => Err(ImageError::OddLength),
+            (None, _) => Ok(None),
         }
+    }
+}
+
+/// Load all remaining `words` into `memory` at `origin`.
+fn load_words_into<F: ImageFile + ?Sized, S: Storage + ?Sized>(
+    mut words: Words<F>,
+    memory: &mut S,
+    origin: u16,
+) -> Result<LoadReport, ImageError<F::Error>> {
+    let mut addr = origin as usize;
+    while let Some(word) = words.next()? {
+        store(memory, addr, word)?;
+        addr += 1;
+    }
+
+    Ok(LoadReport::new(origin, addr - origin as usize))
+}
+
+/// `report`, failing if it loaded no words.
+fn non_empty<E>(report: LoadReport) -> Result<LoadReport, ImageError<E>> {
+    if report.len == 0 {
+        return Err(ImageError::Empty);
+    }
+    Ok(report)
+}
+
+/// Store `word` at `addr` in `memory`, failing if `addr` is past its end.
+fn store<S: Storage + ?Sized, E>(
+    memory: &mut S,
+    addr: usize,
+    word: u16,
+) -> Result<(), ImageError<E>> {
+    if addr >= memory.len() {
+        return Err(ImageError::TooLarge);
+    }
+    memory.store(addr as u16, word);
+    Ok(())
+}
 
+/// Load a text image with one word per line, written in `radix`, into `memory`.
+///
+/// The first word is the origin. Blank lines are skipped.
+fn load_text_image_into<F: ImageFile + ?Sized, S: Storage + ?Sized>(
+    file: &mut F,
+    memory: &mut S,
+    radix: u32,
+) -> Result<LoadReport, ImageError<F::Error>> {
+    // Number of digits in a full word.
+    let width = 16 / radix.ilog2() as usize;
+    let mut buf = [0; 256];
+    let mut line = 1;
+    let mut word: u16 = 0;
+    let mut digits = 0;
+    // Origin and the address of the next word, once the origin is read.
+    let mut loaded: Option<(u16, usize)> = None;
+
+    let mut end_of_line = |line: usize, word: u16, digits: usize| {
+        match (digits, loaded) {
+            (0, _) => (),
+            (n, _) if radix == 2 && n != width => return Err(ImageError::InvalidWord { line }),
+            (_, None) => loaded = Some((word, word as usize)),
+            (_, Some((origin, addr))) => {
+                store(memory, addr, word)?;
+                loaded = Some((origin, addr + 1));
+            }
+        }
         Ok(())
+    };
+
+    loop {
+        let n = file.read(&mut buf).map_err(ImageError::Io)?;
+        if n == 0 {
+            end_of_line(line, word, digits)?;
+            break;
+        }
+
+        for &byte in &buf[..n] {
+            match byte {
+                b'\n' => {
+                    end_of_line(line, word, digits)?;
+                    line += 1;
+                    word = 0;
+                    digits = 0;
+                }
+                b' ' | b'\t' | b'\r' => (),
+                _ => match (byte as char).to_digit(radix) {
+                    Some(digit) if digits < width => {
+                        word = word << radix.ilog2() | digit as u16;
+                        digits += 1;
+                    }
+                    _ => return Err(ImageError::InvalidWord { line }),
+                },
+            }
+        }
+    }
+
+    match loaded {
+        Some((origin, addr)) => non_empty(LoadReport::new(origin, addr - origin as usize)),
+        None => Err(ImageError::Empty),
+    }
+}
+
+/// Formats of image files understood by [`ImageFile::load_image_as`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum ImageFormat {
+    /// Big-endian binary words, the first being the origin (`.obj`).
+    Object,
+    /// Text with one hexadecimal word per line, the first being the origin (`.hex`).
+    Hex,
+    /// Text with 16 binary digits per line, the first being the origin (`.bin`).
+    Binary,
+    /// Big-endian binary words without an origin, loaded at the given address.
+    Raw(u16),
+}
+
+impl ImageFormat {
+    /// [`ImageFormat`] from a file extension.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lc3::ImageFormat;
+    /// assert_eq!(ImageFormat::from_extension("obj"), Some(ImageFormat::Object));
+    /// assert_eq!(ImageFormat::from_extension("HEX"), Some(ImageFormat::Hex));
+    /// assert_eq!(ImageFormat::from_extension("asm"), None);
+    /// ```
+    pub fn from_extension(ext: &str) -> Option<ImageFormat> {
+        if ext.eq_ignore_ascii_case("obj") {
+            Some(ImageFormat::Object)
+        } else if ext.eq_ignore_ascii_case("hex") {
+            Some(ImageFormat::Hex)
+        } else if ext.eq_ignore_ascii_case("bin") {
+            Some(ImageFormat::Binary)
+        } else {
+            None
+        }
+    }
+
+    /// Guess the [`ImageFormat`] of the image starting with `content`.
+    ///
+    /// Text made only of lines of 16 binary digits is [`ImageFormat::Binary`],
+    /// of up to 4 hexadecimal digits is [`ImageFormat::Hex`], and anything
+    /// else is [`ImageFormat::Object`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lc3::ImageFormat;
+    /// assert_eq!(ImageFormat::detect(b"3000\nF025\n"), ImageFormat::Hex);
+    /// assert_eq!(ImageFormat::detect(b"0011000000000000\n"), ImageFormat::Binary);
+    /// assert_eq!(ImageFormat::detect(b"\x30\x00\xF0\x25"), ImageFormat::Object);
+    /// ```
+    pub fn detect(content: &[u8]) -> ImageFormat {
+        let mut lines = content
+            .split(|&x| x == b'\n')
+            .map(|x| x.trim_ascii())
+            .filter(|x| !x.is_empty())
+            .peekable();
+
+        if lines.peek().is_none() {
+            ImageFormat::Object
+        } else if lines
+            .clone()
+            .all(|x| x.len() == 16 && x.iter().all(|&x| x == b'0' || x == b'1'))
+        {
+            ImageFormat::Binary
+        } else if lines.all(|x| x.len() <= 4 && x.iter().all(u8::is_ascii_hexdigit)) {
+            ImageFormat::Hex
+        } else {
+            ImageFormat::Object
+        }
+    }
+}
+
+/// Error type for [`ImageFile`] functions.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum ImageError<IO> {
+    Io(IO),
+    /// The image has no origin, or no words.
+    Empty,
+    /// The image ends in the middle of a word.
+    OddLength,
+    /// The image extends past the end of memory.
+    TooLarge,
+    /// A line of a text image isn't a valid word.
+    InvalidWord {
+        line: usize,
+    },
+}
+
+impl<IO: fmt::Display> fmt::Display for ImageError<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(err) => err.fmt(f),
+            ImageError::Empty => f.write_str("empty image."),
+            ImageError::OddLength => f.write_str("image ends in the middle of a word."),
+            ImageError::TooLarge => f.write_str("image extends past the end of memory."),
+            ImageError::InvalidWord { line } => write!(f, "line {line}: invalid word."),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Write> ImageWriter for T {
+    type Error = std::io::Error;
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        std::io::Write::write_all(self, buf)
     }
 }
 
@@ -90,6 +432,11 @@ impl<T: std::io::Read> ImageFile for T {
     type Error = std::io::Error;
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        std::io::Read::read(self, buf)
+        loop {
+            match std::io::Read::read(self, buf) {
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
     }
 }
@@ -0,0 +1,118 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::HashSet;
+
+use crate::{Instruction, IoDevice, IoDeviceRegister, Lc3, Monitor, Storage};
+
+/// A checker of reads from memory that was never initialized, see
+/// [`Memory::is_initialized`][`crate::Memory::is_initialized`].
+///
+/// Instruction fetches and the words read by `LD`, `LDI` and `LDR` are
+/// checked; memory-mapped I/O registers are always initialized. Every
+/// address is reported once per instruction reading it.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Headless, InitChecker, Lc3, UninitializedRead, VirtualTrapVectorTable};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// lc3.memory.store(0x3000, 0x2002); // LD R0, #2
+/// lc3.memory.store(0x3001, 0xF025); // HALT
+///
+/// let mut checker = InitChecker::new();
+/// lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut checker)
+///     .unwrap();
+/// assert_eq!(checker.reads(), [UninitializedRead { pc: 0x3000, addr: 0x3003 }]);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct InitChecker {
+    /// True iff the machine is halted at the first uninitialized read.
+    stop: bool,
+    reads: Vec<UninitializedRead>,
+    reported: HashSet<UninitializedRead>,
+}
+
+/// A read from memory that was never initialized, see [`InitChecker`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct UninitializedRead {
+    /// Address of the instruction reading memory.
+    pub pc: u16,
+    /// Address read, the same as `pc` for instruction fetches.
+    pub addr: u16,
+}
+
+impl InitChecker {
+    /// Initialize a checker reporting every uninitialized read.
+    pub fn new() -> InitChecker {
+        InitChecker::default()
+    }
+
+    /// Initialize a checker halting the machine at the first uninitialized
+    /// read, before the instruction reading memory executes.
+    pub fn stopping() -> InitChecker {
+        InitChecker {
+            stop: true,
+            ..InitChecker::default()
+        }
+    }
+
+    /// Uninitialized reads, in the order they first happened.
+    pub fn reads(&self) -> &[UninitializedRead] {
+        &self.reads
+    }
+
+    fn check<IO: IoDevice, S: Storage>(&mut self, pc: u16, addr: u16, lc3: &Lc3<IO, S>) {
+        if lc3.memory.is_initialized(addr) || IoDeviceRegister::from_u16(addr).is_some() {
+            return;
+        }
+        let read = UninitializedRead { pc, addr };
+        if self.reported.insert(read) {
+            self.reads.push(read);
+        }
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Monitor<IO, S> for InitChecker {
+    fn executing(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) -> bool {
+        if self.stop && !self.reads.is_empty() {
+            return false;
+        }
+
+        self.check(pc, pc, lc3);
+        let next = pc.wrapping_add(1);
+        match inst {
+            Instruction::Ld { pcoffset9, .. } => self.check(pc, next.wrapping_add(pcoffset9), lc3),
+            Instruction::Ldi { pcoffset9, .. } => {
+                let addr = next.wrapping_add(pcoffset9);
+                self.check(pc, addr, lc3);
+                if IoDeviceRegister::from_u16(addr).is_none() {
+                    self.check(pc, lc3.memory.load(addr), lc3);
+                }
+            }
+            Instruction::Ldr { baser, offset6, .. } => {
+                self.check(pc, lc3.registers[baser].wrapping_add(offset6), lc3);
+            }
+            _ => (),
+        }
+
+        !self.stop || self.reads.is_empty()
+    }
+
+    fn executed(&mut self, _pc: u16, _inst: Instruction, _lc3: &Lc3<IO, S>) {}
+}
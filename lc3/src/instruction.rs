@@ -0,0 +1,317 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::{CondCodes, InstructionDecode, OpCode, Reg, TrapCode};
+
+use core::fmt;
+
+/// A decoded LC-3 instruction.
+///
+/// Immediate values and offsets are sign-extended to 16 bits, so they
+/// can be added to registers and the program counter with wrapping
+/// arithmetic.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Instruction, Reg};
+/// assert_eq!(
+///     Instruction::decode(0x1261), // ADD R1, R1, #1
+///     Instruction::AddImm { dr: Reg::R1, sr1: Reg::R1, imm5: 1 },
+/// );
+/// assert_eq!(
+///     Instruction::decode(0xC1C0), // RET
+///     Instruction::Jmp { baser: Reg::R7 },
+/// );
+/// ```
+///
+/// Instructions are displayed in assembly language, with offsets
+/// relative to the incremented program counter:
+///
+/// ```
+/// # use lc3::Instruction;
+/// assert_eq!(Instruction::decode(0x1261).to_string(), "ADD R1, R1, #1");
+/// assert_eq!(Instruction::decode(0x0BFD).to_string(), "BRnp #-3");
+/// assert_eq!(Instruction::decode(0xF025).to_string(), "HALT");
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Instruction {
+    /// `ADD DR, SR1, SR2`.
+    Add { dr: Reg, sr1: Reg, sr2: Reg },
+    /// `ADD DR, SR1, imm5`.
+    AddImm { dr: Reg, sr1: Reg, imm5: u16 },
+    /// `AND DR, SR1, SR2`.
+    And { dr: Reg, sr1: Reg, sr2: Reg },
+    /// `AND DR, SR1, imm5`.
+    AndImm { dr: Reg, sr1: Reg, imm5: u16 },
+    /// `NOT DR, SR`.
+    Not { dr: Reg, sr: Reg },
+    /// `BRnzp PCoffset9`.
+    Br { cc: CondCodes, pcoffset9: u16 },
+    /// `JMP BaseR`, or `RET` if `baser` is [`Reg::R7`].
+    Jmp { baser: Reg },
+    /// `JSR PCoffset11`.
+    Jsr { pcoffset11: u16 },
+    /// `JSRR BaseR`.
+    Jsrr { baser: Reg },
+    /// `LD DR, PCoffset9`.
+    Ld { dr: Reg, pcoffset9: u16 },
+    /// `LDI DR, PCoffset9`.
+    Ldi { dr: Reg, pcoffset9: u16 },
+    /// `LDR DR, BaseR, offset6`.
+    Ldr { dr: Reg, baser: Reg, offset6: u16 },
+    /// `LEA DR, PCoffset9`.
+    Lea { dr: Reg, pcoffset9: u16 },
+    /// `ST SR, PCoffset9`.
+    St { sr: Reg, pcoffset9: u16 },
+    /// `STI SR, PCoffset9`.
+    Sti { sr: Reg, pcoffset9: u16 },
+    /// `STR SR, BaseR, offset6`.
+    Str { sr: Reg, baser: Reg, offset6: u16 },
+    /// `TRAP trapvect8`.
+    Trap { trapvect8: u8 },
+    /// `RTI`.
+    Rti,
+    /// The reserved opcode.
+    Res,
+}
+
+impl Instruction {
+    /// Decode the 16-bit instruction `inst`.
+    pub fn decode(inst: u16) -> Instruction {
+        match inst.opcode() {
+            OpCode::Add if inst.isbitset(5) => Instruction::AddImm {
+                dr: inst.reg1(),
+                sr1: inst.reg2(),
+                imm5: inst.imm5(),
+            },
+            OpCode::Add => Instruction::Add {
+                dr: inst.reg1(),
+                sr1: inst.reg2(),
+                sr2: inst.reg3(),
+            },
+            OpCode::And if inst.isbitset(5) => Instruction::AndImm {
+                dr: inst.reg1(),
+                sr1: inst.reg2(),
+                imm5: inst.imm5(),
+            },
+            OpCode::And => Instruction::And {
+                dr: inst.reg1(),
+                sr1: inst.reg2(),
+                sr2: inst.reg3(),
+            },
+            OpCode::Not => Instruction::Not {
+                dr: inst.reg1(),
+                sr: inst.reg2(),
+            },
+            OpCode::Br => Instruction::Br {
+                cc: inst.condcodes(),
+                pcoffset9: inst.imm9(),
+            },
+            OpCode::Jmp => Instruction::Jmp { baser: inst.reg2() },
+            OpCode::Jsr if inst.isbitset(11) => Instruction::Jsr {
+                pcoffset11: inst.imm11(),
+            },
+            OpCode::Jsr => Instruction::Jsrr { baser: inst.reg2() },
+            OpCode::Ld => Instruction::Ld {
+                dr: inst.reg1(),
+                pcoffset9: inst.imm9(),
+            },
+            OpCode::Ldi => Instruction::Ldi {
+                dr: inst.reg1(),
+                pcoffset9: inst.imm9(),
+            },
+            OpCode::Ldr => Instruction::Ldr {
+                dr: inst.reg1(),
+                baser: inst.reg2(),
+                offset6: inst.imm6(),
+            },
+            OpCode::Lea => Instruction::Lea {
+                dr: inst.reg1(),
+                pcoffset9: inst.imm9(),
+            },
+            OpCode::St => Instruction::St {
+                sr: inst.reg1(),
+                pcoffset9: inst.imm9(),
+            },
+            OpCode::Sti => Instruction::Sti {
+                sr: inst.reg1(),
+                pcoffset9: inst.imm9(),
+            },
+            OpCode::Str => Instruction::Str {
+                sr: inst.reg1(),
+                baser: inst.reg2(),
+                offset6: inst.imm6(),
+            },
+            OpCode::Trap => Instruction::Trap {
+                trapvect8: inst as u8,
+            },
+            OpCode::Rti => Instruction::Rti,
+            OpCode::Res => Instruction::Res,
+        }
+    }
+
+    /// [`OpCode`] of the instruction.
+    pub fn opcode(self) -> OpCode {
+        match self {
+            Instruction::Add { .. } | Instruction::AddImm { .. } => OpCode::Add,
+            Instruction::And { .. } | Instruction::AndImm { .. } => OpCode::And,
+            Instruction::Not { .. } => OpCode::Not,
+            Instruction::Br { .. } => OpCode::Br,
+            Instruction::Jmp { .. } => OpCode::Jmp,
+            Instruction::Jsr { .. } | Instruction::Jsrr { .. } => OpCode::Jsr,
+            Instruction::Ld { .. } => OpCode::Ld,
+            Instruction::Ldi { .. } => OpCode::Ldi,
+            Instruction::Ldr { .. } => OpCode::Ldr,
+            Instruction::Lea { .. } => OpCode::Lea,
+            Instruction::St { .. } => OpCode::St,
+            Instruction::Sti { .. } => OpCode::Sti,
+            Instruction::Str { .. } => OpCode::Str,
+            Instruction::Trap { .. } => OpCode::Trap,
+            Instruction::Rti => OpCode::Rti,
+            Instruction::Res => OpCode::Res,
+        }
+    }
+
+    /// Encode the instruction as a 16-bit word, the inverse of [`Instruction::decode`].
+    ///
+    /// Bits ignored by the decoder are encoded as the specification
+    /// requires, and operands are truncated to the width of their field.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lc3::{Instruction, Reg};
+    /// let inst = Instruction::AddImm { dr: Reg::R1, sr1: Reg::R1, imm5: 0xFFFF };
+    /// assert_eq!(inst.encode(), 0x127F); // ADD R1, R1, #-1
+    /// assert_eq!(Instruction::decode(0xC1FF).encode(), 0xC1C0); // RET
+    /// ```
+    pub fn encode(self) -> u16 {
+        let reg = |reg: Reg, at: u32| (reg as u16) << at;
+        let operands = match self {
+            Instruction::Add { dr, sr1, sr2 } | Instruction::And { dr, sr1, sr2 } => {
+                reg(dr, 9) | reg(sr1, 6) | reg(sr2, 0)
+            }
+            Instruction::AddImm { dr, sr1, imm5 } | Instruction::AndImm { dr, sr1, imm5 } => {
+                reg(dr, 9) | reg(sr1, 6) | 1 << 5 | imm5 & 0x1F
+            }
+            Instruction::Not { dr, sr } => reg(dr, 9) | reg(sr, 6) | 0x3F,
+            Instruction::Br { cc, pcoffset9 } => {
+                (cc.negative() as u16) << 11
+                    | (cc.zero() as u16) << 10
+                    | (cc.positive() as u16) << 9
+                    | pcoffset9 & 0x1FF
+            }
+            Instruction::Jmp { baser } | Instruction::Jsrr { baser } => reg(baser, 6),
+            Instruction::Jsr { pcoffset11 } => 1 << 11 | pcoffset11 & 0x7FF,
+            Instruction::Ld { dr, pcoffset9 }
+            | Instruction::Ldi { dr, pcoffset9 }
+            | Instruction::Lea { dr, pcoffset9 } => reg(dr, 9) | pcoffset9 & 0x1FF,
+            Instruction::St { sr, pcoffset9 } | Instruction::Sti { sr, pcoffset9 } => {
+                reg(sr, 9) | pcoffset9 & 0x1FF
+            }
+            Instruction::Ldr { dr, baser, offset6 } => reg(dr, 9) | reg(baser, 6) | offset6 & 0x3F,
+            Instruction::Str { sr, baser, offset6 } => reg(sr, 9) | reg(baser, 6) | offset6 & 0x3F,
+            Instruction::Trap { trapvect8 } => trapvect8 as u16,
+            Instruction::Rti | Instruction::Res => 0,
+        };
+        (self.opcode() as u16) << 12 | operands
+    }
+
+    /// Address referred to by the PC-relative operand of the instruction at `pc`.
+    #[cfg(feature = "std")]
+    pub(crate) fn target(self, pc: u16) -> Option<u16> {
+        match self {
+            Instruction::Br {
+                pcoffset9: offset, ..
+            }
+            | Instruction::Jsr { pcoffset11: offset }
+            | Instruction::Ld {
+                pcoffset9: offset, ..
+            }
+            | Instruction::Ldi {
+                pcoffset9: offset, ..
+            }
+            | Instruction::Lea {
+                pcoffset9: offset, ..
+            }
+            | Instruction::St {
+                pcoffset9: offset, ..
+            }
+            | Instruction::Sti {
+                pcoffset9: offset, ..
+            } => Some(pc.wrapping_add(1).wrapping_add(offset)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let imm = |value: u16| value as i16;
+        match *self {
+            Instruction::Add { dr, sr1, sr2 } => write!(f, "ADD {dr:?}, {sr1:?}, {sr2:?}"),
+            Instruction::AddImm { dr, sr1, imm5 } => {
+                write!(f, "ADD {dr:?}, {sr1:?}, #{}", imm(imm5))
+            }
+            Instruction::And { dr, sr1, sr2 } => write!(f, "AND {dr:?}, {sr1:?}, {sr2:?}"),
+            Instruction::AndImm { dr, sr1, imm5 } => {
+                write!(f, "AND {dr:?}, {sr1:?}, #{}", imm(imm5))
+            }
+            Instruction::Not { dr, sr } => write!(f, "NOT {dr:?}, {sr:?}"),
+            Instruction::Br { cc, pcoffset9 } if cc == CondCodes::NONE => {
+                write!(f, "NOP #{}", imm(pcoffset9))
+            }
+            Instruction::Br { cc, pcoffset9 } => {
+                f.write_str("BR")?;
+                for (set, name) in [(cc.negative(), "n"), (cc.zero(), "z"), (cc.positive(), "p")] {
+                    if set {
+                        f.write_str(name)?;
+                    }
+                }
+                write!(f, " #{}", imm(pcoffset9))
+            }
+            Instruction::Jmp { baser: Reg::R7 } => f.write_str("RET"),
+            Instruction::Jmp { baser } => write!(f, "JMP {baser:?}"),
+            Instruction::Jsr { pcoffset11 } => write!(f, "JSR #{}", imm(pcoffset11)),
+            Instruction::Jsrr { baser } => write!(f, "JSRR {baser:?}"),
+            Instruction::Ld { dr, pcoffset9 } => write!(f, "LD {dr:?}, #{}", imm(pcoffset9)),
+            Instruction::Ldi { dr, pcoffset9 } => write!(f, "LDI {dr:?}, #{}", imm(pcoffset9)),
+            Instruction::Ldr { dr, baser, offset6 } => {
+                write!(f, "LDR {dr:?}, {baser:?}, #{}", imm(offset6))
+            }
+            Instruction::Lea { dr, pcoffset9 } => write!(f, "LEA {dr:?}, #{}", imm(pcoffset9)),
+            Instruction::St { sr, pcoffset9 } => write!(f, "ST {sr:?}, #{}", imm(pcoffset9)),
+            Instruction::Sti { sr, pcoffset9 } => write!(f, "STI {sr:?}, #{}", imm(pcoffset9)),
+            Instruction::Str { sr, baser, offset6 } => {
+                write!(f, "STR {sr:?}, {baser:?}, #{}", imm(offset6))
+            }
+            Instruction::Trap { trapvect8 } => match TrapCode::from_u16(trapvect8 as u16) {
+                Some(TrapCode::Getc) => f.write_str("GETC"),
+                Some(TrapCode::Out) => f.write_str("OUT"),
+                Some(TrapCode::Puts) => f.write_str("PUTS"),
+                Some(TrapCode::In) => f.write_str("IN"),
+                Some(TrapCode::PutSp) => f.write_str("PUTSP"),
+                Some(TrapCode::Halt) => f.write_str("HALT"),
+                None => write!(f, "TRAP x{trapvect8:02X}"),
+            },
+            Instruction::Rti => f.write_str("RTI"),
+            Instruction::Res => f.write_str("RES"),
+        }
+    }
+}
@@ -16,41 +16,111 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
-use crate::ImageFile;
+use crate::image;
+use crate::memory::LEN;
+use crate::Instruction;
 use crate::InstructionDecode;
 use crate::IoDevice;
 use crate::IoDeviceRegister;
 use crate::Memory;
-use crate::OpCode;
+#[cfg(feature = "std")]
+use crate::PagedStorage;
+use crate::Storage;
 use crate::TrapCode;
 use crate::{CondCodes, Reg, Registers};
+use crate::{ImageError, ImageFile, ImageFormat, ImageWriter, LoadReport};
+use crate::{TrapAction, TrapHandler, TrapVectorTable, VirtualTrapVectorTable};
 
-use core::{fmt, slice};
+use core::fmt;
+use core::ops::RangeInclusive;
+
+/// Image of the operating system of the LC-3 simulator, loaded at
+/// [`Lc3::TRAP_VECTOR_TABLE_START`] and starting at
+/// [`Lc3::OPERATING_SYSTEM_START`]. It implements the standard traps
+/// and is booted by [`Lc3::boot_with_trap_handler`].
+pub const LC3OS: &[u8] = include_bytes!("lc3os.obj");
 
 /// LC-3 virtual machine.
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
-pub struct Lc3<IO: IoDevice> {
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Lc3<IO: IoDevice, S: Storage = [u16; LEN]> {
     pub registers: Registers,
-    pub memory: Memory<IO>,
+    pub memory: Memory<IO, S>,
+}
+
+impl<IO: IoDevice + Default> Default for Lc3<IO> {
+    fn default() -> Lc3<IO> {
+        Lc3::new(IO::default())
+    }
 }
 
 impl<IO: IoDevice> Lc3<IO> {
+    /// Initialize a new LC-3 virtual machine with an [`IoDevice`][`crate::IoDevice`].
+    pub const fn new(iodevice: IO) -> Lc3<IO> {
+        Lc3 {
+            registers: Registers::new(),
+            memory: Memory::new(iodevice),
+        }
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Lc3<IO, S> {
     pub const TRAP_VECTOR_TABLE_START: u16 = 0x0000;
     pub const INTERRUPT_VECTOR_TABLE_START: u16 = 0x0100;
     pub const OPERATING_SYSTEM_START: u16 = 0x0200;
     pub const USER_PROGRAMS_START: u16 = 0x3000;
 
-    /// Initialize a new LC-3 virtual machine with an [`IoDevice`][`crate::IoDevice`].
-    pub const fn new(iodevice: IO) -> Lc3<IO> {
+    /// Initialize a new LC-3 virtual machine with an [`IoDevice`][`crate::IoDevice`]
+    /// and memory kept in `storage`, see [`Storage`][`crate::Storage`].
+    pub const fn with_storage(iodevice: IO, storage: S) -> Lc3<IO, S> {
         Lc3 {
             registers: Registers::new(),
-            memory: Memory::new(iodevice),
+            memory: Memory::with_storage(iodevice, storage),
         }
     }
 
     /// Load an image from an [`ImageFile`][`crate::ImageFile`].
-    pub fn load_image<F: ImageFile>(&mut self, file: &mut F) -> Result<(), F::Error> {
-        file.load_image_into(self.memory.as_mut())
+    pub fn load_image<F: ImageFile>(
+        &mut self,
+        file: &mut F,
+    ) -> Result<LoadReport, ImageError<F::Error>> {
+        let report = file.load_image_into(self.memory.storage_mut())?;
+        Ok(self.mark_loaded(report))
+    }
+
+    /// Load an image in `format` from an [`ImageFile`][`crate::ImageFile`].
+    pub fn load_image_as<F: ImageFile>(
+        &mut self,
+        file: &mut F,
+        format: ImageFormat,
+    ) -> Result<LoadReport, ImageError<F::Error>> {
+        let report = file.load_image_as(self.memory.storage_mut(), format)?;
+        Ok(self.mark_loaded(report))
+    }
+
+    /// Consider the words loaded by the image of `report` initialized,
+    /// reporting those previous images loaded too.
+    fn mark_loaded(&mut self, report: LoadReport) -> LoadReport {
+        LoadReport {
+            overwritten: self.memory.mark_loaded(report.range()),
+            ..report
+        }
+    }
+
+    /// Write the memory at addresses `range` as an image to an [`ImageWriter`][`crate::ImageWriter`].
+    ///
+    /// Memory is read as stored, without the side effects of reading
+    /// memory-mapped I/O registers.
+    pub fn dump_image<W: ImageWriter>(
+        &self,
+        range: RangeInclusive<u16>,
+        writer: &mut W,
+    ) -> Result<(), W::Error> {
+        let len = self.memory.storage().len();
+        let words = range
+            .clone()
+            .take_while(|&addr| (addr as usize) < len)
+            .map(|addr| self.memory.load(addr));
+        image::write_words(writer, *range.start(), words)
     }
 
     /// Run indefinitely at [`Self::USER_PROGRAMS_START`] until [`Self::should_halt`] returns true.
@@ -60,7 +130,7 @@ impl<IO: IoDevice> Lc3<IO> {
 
     // Run indefinitely at `addr` until [`Self::should_halt`] returns true.
     pub fn run_at(&mut self, addr: u16) -> Result<(), Error<IO::Error>> {
-        self.run_common::<false>(addr)
+        self.run_with_trap_handler_at(addr, &mut TrapVectorTable)
     }
 
     /// Run indefinitely at [`Self::USER_PROGRAMS_START`] with trap emulated until [`Self::should_halt`] returns true.
@@ -74,57 +144,70 @@ impl<IO: IoDevice> Lc3<IO> {
         &mut self,
         addr: u16,
     ) -> Result<(), Error<IO::Error>> {
-        self.run_common::<true>(addr)
+        self.run_with_trap_handler_at(addr, &mut VirtualTrapVectorTable)
+    }
+
+    /// Run indefinitely at [`Self::USER_PROGRAMS_START`] with traps serviced by `traps`
+    /// until [`Self::should_halt`] returns true.
+    pub fn run_with_trap_handler<T: TrapHandler<IO, S>>(
+        &mut self,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
+        self.run_with_trap_handler_at(Self::USER_PROGRAMS_START, traps)
     }
 
-    fn run_common<const VIRT_TVT: bool>(&mut self, addr: u16) -> Result<(), Error<IO::Error>> {
+    /// Run indefinitely at `addr` with traps serviced by `traps`
+    /// until [`Self::should_halt`] returns true.
+    pub fn run_with_trap_handler_at<T: TrapHandler<IO, S>>(
+        &mut self,
+        addr: u16,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
         self.reset();
         self.registers.pc = addr;
         while !self.should_halt() {
-            self.next_instruction_common::<VIRT_TVT>()?;
+            self.next_instruction_with_trap_handler(traps)?;
         }
 
         Ok(())
     }
 
+    /// Boot the operating system with traps serviced by `traps`, then
+    /// run indefinitely at `entry` until [`Self::should_halt`] returns true.
+    ///
+    /// Booting runs the operating system's startup code at
+    /// [`Self::OPERATING_SYSTEM_START`] up to its first [`TrapCode::Halt`],
+    /// which hands control to the user program instead of halting the machine.
+    /// Privilege levels aren't modeled, so the operating system runs with
+    /// the same access to the machine as user programs.
+    pub fn boot_with_trap_handler<T: TrapHandler<IO, S>>(
+        &mut self,
+        entry: u16,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
+        self.run_with_trap_handler_at(Self::OPERATING_SYSTEM_START, &mut Startup(traps))?;
+        self.run_with_trap_handler_at(entry, traps)
+    }
+
     /// Execute next instruction.
     pub fn next_instruction(&mut self) -> Result<(), Error<IO::Error>> {
-        self.next_instruction_common::<false>()
+        self.next_instruction_with_trap_handler(&mut TrapVectorTable)
     }
 
     /// Execute next instruction with trap emulated.
     pub fn next_instruction_with_virtual_trap_vector_table(
         &mut self,
     ) -> Result<(), Error<IO::Error>> {
-        self.next_instruction_common::<true>()
+        self.next_instruction_with_trap_handler(&mut VirtualTrapVectorTable)
     }
 
-    fn next_instruction_common<const VIRT_TVT: bool>(&mut self) -> Result<(), Error<IO::Error>> {
+    /// Execute next instruction with traps serviced by `traps`.
+    pub fn next_instruction_with_trap_handler<T: TrapHandler<IO, S>>(
+        &mut self,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
         let inst = self.memory.read(self.registers.pc);
-
-        // All instructions with a PC offset parameter
-        // require PC to be incremented.
-        self.registers.pc = self.registers.pc.wrapping_add(1);
-
-        match inst.opcode() {
-            OpCode::Add => self.add(inst),
-            OpCode::And => self.and(inst),
-            OpCode::Not => self.not(inst),
-            OpCode::Br => self.br(inst),
-            OpCode::Jmp => self.jmp(inst),
-            OpCode::Jsr => self.jsr(inst),
-            OpCode::Ld => self.ld(inst),
-            OpCode::Ldi => self.ldi(inst),
-            OpCode::Ldr => self.ldr(inst),
-            OpCode::Lea => self.lea(inst),
-            OpCode::St => self.st(inst),
-            OpCode::Sti => self.sti(inst),
-            OpCode::Str => self.str(inst),
-            OpCode::Trap if VIRT_TVT => self.trap_emulated(inst)?,
-            OpCode::Trap => self.trap(inst),
-            OpCode::Rti | OpCode::Res => return Err(Error::OpCodeNotImplemented),
-        }
-
+        self.execute(Instruction::decode(inst), traps)?;
         Ok(())
     }
 
@@ -150,114 +233,164 @@ impl<IO: IoDevice> Lc3<IO> {
     }
 }
 
-impl<IO: IoDevice> Lc3<IO> {
-    fn add(&mut self, inst: u16) {
-        let dr = inst.reg1();
-        let sr1 = inst.reg2();
-        if inst.isbitset(5) {
-            let imm5 = inst.imm5();
-            let value = self.registers[sr1].wrapping_add(imm5);
-            self.registers[dr] = value;
-        } else {
-            let sr2 = inst.reg3();
-            let value = self.registers[sr1].wrapping_add(self.registers[sr2]);
-            self.registers[dr] = value;
+#[cfg(feature = "std")]
+impl<IO: IoDevice> Lc3<IO, PagedStorage> {
+    /// A copy of the machine attached to `iodevice`, sharing all memory
+    /// pages with `self` until either machine writes to them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lc3::{Lc3, PagedStorage};
+    /// # #[derive(Default)]
+    /// # struct Null;
+    /// # impl lc3::IoDevice for Null {
+    /// #     type Error = ();
+    /// #     fn read(&mut self, _: &mut [u8]) -> Result<usize, ()> { Ok(0) }
+    /// #     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> { Ok(buf.len()) }
+    /// #     fn flush(&mut self) -> Result<(), ()> { Ok(()) }
+    /// # }
+    /// let mut parent = Lc3::with_storage(Null, PagedStorage::new());
+    /// parent.memory.store(0x3000, 0xF025); // HALT
+    ///
+    /// let mut child = parent.fork(Null);
+    /// child.memory.store(0x3000, 0xF021); // OUT
+    /// assert_eq!(parent.memory.load(0x3000), 0xF025);
+    /// ```
+    pub fn fork<J: IoDevice>(&self, iodevice: J) -> Lc3<J, PagedStorage> {
+        Lc3 {
+            registers: self.registers,
+            memory: self.memory.fork(iodevice),
         }
-        self.setcc(dr);
     }
+}
 
-    fn and(&mut self, inst: u16) {
-        let dr = inst.reg1();
-        let sr1 = inst.reg2();
-        if inst.isbitset(5) {
-            let imm5 = inst.imm5();
-            self.registers[dr] = self.registers[sr1] & imm5;
-        } else {
-            let sr2 = inst.reg3();
-            self.registers[dr] = self.registers[sr1] & self.registers[sr2];
+/// Memory written by an executed instruction, see [`Lc3::execute`].
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) enum Written {
+    Nothing,
+    Word(u16),
+    /// Any word, e.g. by a [`TrapHandler`].
+    Unknown,
+}
+
+impl<IO: IoDevice, S: Storage> Lc3<IO, S> {
+    /// Execute `inst` fetched from [`Registers::pc`], returning the memory it wrote to.
+    pub(crate) fn execute<T: TrapHandler<IO, S>>(
+        &mut self,
+        inst: Instruction,
+        traps: &mut T,
+    ) -> Result<Written, Error<IO::Error>> {
+        // All instructions with a PC offset parameter
+        // require PC to be incremented.
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+
+        match inst {
+            Instruction::Add { dr, sr1, sr2 } => self.add(dr, sr1, self.registers[sr2]),
+            Instruction::AddImm { dr, sr1, imm5 } => self.add(dr, sr1, imm5),
+            Instruction::And { dr, sr1, sr2 } => self.and(dr, sr1, self.registers[sr2]),
+            Instruction::AndImm { dr, sr1, imm5 } => self.and(dr, sr1, imm5),
+            Instruction::Not { dr, sr } => self.not(dr, sr),
+            Instruction::Br { cc, pcoffset9 } => self.br(cc, pcoffset9),
+            Instruction::Jmp { baser } => self.jmp(baser),
+            Instruction::Jsr { pcoffset11 } => self.jsr(pcoffset11),
+            Instruction::Jsrr { baser } => self.jsrr(baser),
+            Instruction::Ld { dr, pcoffset9 } => self.ld(dr, pcoffset9),
+            Instruction::Ldi { dr, pcoffset9 } => self.ldi(dr, pcoffset9),
+            Instruction::Ldr { dr, baser, offset6 } => self.ldr(dr, baser, offset6),
+            Instruction::Lea { dr, pcoffset9 } => self.lea(dr, pcoffset9),
+            Instruction::St { sr, pcoffset9 } => return Ok(self.st(sr, pcoffset9)),
+            Instruction::Sti { sr, pcoffset9 } => return Ok(self.sti(sr, pcoffset9)),
+            Instruction::Str { sr, baser, offset6 } => return Ok(self.str(sr, baser, offset6)),
+            Instruction::Trap { trapvect8 } => return self.trap(trapvect8, traps),
+            Instruction::Rti | Instruction::Res => return Err(Error::OpCodeNotImplemented),
         }
+
+        Ok(Written::Nothing)
+    }
+
+    fn add(&mut self, dr: Reg, sr1: Reg, value: u16) {
+        self.registers[dr] = self.registers[sr1].wrapping_add(value);
         self.setcc(dr);
     }
 
-    fn not(&mut self, inst: u16) {
-        let dr = inst.reg1();
-        let sr = inst.reg2();
+    fn and(&mut self, dr: Reg, sr1: Reg, value: u16) {
+        self.registers[dr] = self.registers[sr1] & value;
+        self.setcc(dr);
+    }
+
+    fn not(&mut self, dr: Reg, sr: Reg) {
         self.registers[dr] = !self.registers[sr];
         self.setcc(dr);
     }
 
-    fn br(&mut self, inst: u16) {
-        let cc = inst.condcodes();
+    fn br(&mut self, cc: CondCodes, pcoffset9: u16) {
         if self.registers.cc.intersects(cc) {
-            let value = self.registers.pc.wrapping_add(inst.imm9());
+            let value = self.registers.pc.wrapping_add(pcoffset9);
             self.registers.pc = value;
         }
     }
 
-    fn jmp(&mut self, inst: u16) {
-        let baser = inst.reg2();
+    fn jmp(&mut self, baser: Reg) {
         self.registers.pc = self.registers[baser];
     }
 
-    fn jsr(&mut self, inst: u16) {
+    fn jsr(&mut self, pcoffset11: u16) {
         self.registers.r7 = self.registers.pc;
-        if inst.isbitset(11) {
-            let value = self.registers.pc.wrapping_add(inst.imm11());
-            self.registers.pc = value;
-        } else {
-            let baser = inst.reg2();
-            self.registers.pc = self.registers[baser];
-        }
+        let value = self.registers.pc.wrapping_add(pcoffset11);
+        self.registers.pc = value;
     }
 
-    fn ld(&mut self, inst: u16) {
-        let dr = inst.reg1();
-        let addr = self.registers.pc.wrapping_add(inst.imm9());
+    fn jsrr(&mut self, baser: Reg) {
+        // The target is BaseR before R7 is written: JSRR R7 jumps to the old
+        // R7, not to the return address it saves.
+        let target = self.registers[baser];
+        self.registers.r7 = self.registers.pc;
+        self.registers.pc = target;
+    }
+
+    fn ld(&mut self, dr: Reg, pcoffset9: u16) {
+        let addr = self.registers.pc.wrapping_add(pcoffset9);
         self.registers[dr] = self.memory.read(addr);
         self.setcc(dr);
     }
 
-    fn ldi(&mut self, inst: u16) {
-        let dr = inst.reg1();
-        let addr = self.registers.pc.wrapping_add(inst.imm9());
+    fn ldi(&mut self, dr: Reg, pcoffset9: u16) {
+        let addr = self.registers.pc.wrapping_add(pcoffset9);
         let addr = self.memory.read(addr);
         self.registers[dr] = self.memory.read(addr);
         self.setcc(dr);
     }
 
-    fn ldr(&mut self, inst: u16) {
-        let dr = inst.reg1();
-        let baser = inst.reg2();
-        let addr = self.registers[baser].wrapping_add(inst.imm6());
+    fn ldr(&mut self, dr: Reg, baser: Reg, offset6: u16) {
+        let addr = self.registers[baser].wrapping_add(offset6);
         self.registers[dr] = self.memory.read(addr);
         self.setcc(dr);
     }
 
-    fn lea(&mut self, inst: u16) {
-        let dr = inst.reg1();
-        let value = self.registers.pc.wrapping_add(inst.imm9());
+    fn lea(&mut self, dr: Reg, pcoffset9: u16) {
+        let value = self.registers.pc.wrapping_add(pcoffset9);
         self.registers[dr] = value;
         self.setcc(dr);
     }
 
-    fn st(&mut self, inst: u16) {
-        let sr = inst.reg1();
-        let addr = self.registers.pc.wrapping_add(inst.imm9());
+    fn st(&mut self, sr: Reg, pcoffset9: u16) -> Written {
+        let addr = self.registers.pc.wrapping_add(pcoffset9);
         self.memory.write(addr, self.registers[sr]);
+        Written::Word(addr)
     }
 
-    fn sti(&mut self, inst: u16) {
-        let sr = inst.reg1();
-        let addr = self.registers.pc.wrapping_add(inst.imm9());
+    fn sti(&mut self, sr: Reg, pcoffset9: u16) -> Written {
+        let addr = self.registers.pc.wrapping_add(pcoffset9);
         let addr = self.memory.read(addr);
         self.memory.write(addr, self.registers[sr]);
+        Written::Word(addr)
     }
 
-    fn str(&mut self, inst: u16) {
-        let sr = inst.reg1();
-        let baser = inst.reg2();
-        let addr = self.registers[baser].wrapping_add(inst.imm6());
+    fn str(&mut self, sr: Reg, baser: Reg, offset6: u16) -> Written {
+        let addr = self.registers[baser].wrapping_add(offset6);
         self.memory.write(addr, self.registers[sr]);
+        Written::Word(addr)
     }
 
     fn setcc(&mut self, dr: Reg) {
@@ -265,79 +398,50 @@ impl<IO: IoDevice> Lc3<IO> {
         self.registers.cc = CondCodes::from_signum(result);
     }
 
-    fn trap(&mut self, inst: u16) {
+    fn trap<T: TrapHandler<IO, S>>(
+        &mut self,
+        trapvect8: u8,
+        traps: &mut T,
+    ) -> Result<Written, Error<IO::Error>> {
         self.registers.r7 = self.registers.pc;
-        self.registers.pc = self.memory.read(inst.imm8());
+        match traps.trap(trapvect8, &mut self.registers, &mut self.memory)? {
+            TrapAction::Continue => (),
+            TrapAction::Halt => self.halt(),
+            TrapAction::Dispatch => self.registers.pc = self.memory.read(trapvect8 as u16),
+        }
+
+        if traps.writes_memory(trapvect8) {
+            Ok(Written::Unknown)
+        } else {
+            Ok(Written::Nothing)
+        }
     }
+}
 
-    fn trap_emulated(&mut self, inst: u16) -> Result<(), Error<IO::Error>> {
-        self.registers.r7 = self.registers.pc;
+/// Trap handler for the operating system's startup code, see [`Lc3::boot_with_trap_handler`].
+pub(crate) struct Startup<'a, T>(pub(crate) &'a mut T);
 
-        let trapcode = match inst.trapcode() {
-            Some(trapcode) => trapcode,
-            None => {
-                self.memory.io.write(b"UNDEFINED TRAP EXECUTED")?;
-                return Ok(());
-            }
-        };
-
-        match trapcode {
-            TrapCode::Getc => {
-                let mut byte = 0;
-                self.memory.io.read(slice::from_mut(&mut byte))?;
-                self.registers.r0 = byte as u16;
-                self.setcc(Reg::R0);
-            }
-            TrapCode::Out => {
-                let byte = self.registers.r0 as u8;
-                self.memory.io.write(slice::from_ref(&byte))?;
-                self.memory.io.flush()?;
-            }
-            TrapCode::Puts => {
-                let mut sp = self.registers.r0;
-                let mut byte = self.memory.read(sp) as u8;
-                while byte != 0 {
-                    self.memory.io.write(slice::from_ref(&byte))?;
-                    sp += 1;
-                    byte = self.memory.read(sp) as u8;
-                }
-                self.memory.io.flush()?;
-            }
-            TrapCode::In => {
-                self.memory.io.write(b"Enter a character: ")?;
-                let mut byte = 0;
-                self.memory.io.read(slice::from_mut(&mut byte))?;
-                self.memory.io.write(slice::from_ref(&byte))?;
-                self.memory.io.flush()?;
-                self.registers.r0 = byte as u16;
-            }
-            TrapCode::PutSp => unsafe {
-                let sp = self.registers.r0;
-                let start = self.memory.as_ref().as_ptr().add(sp as usize) as *const u8;
-                let end = {
-                    let mut end = start;
-                    while *end != b'\0' {
-                        end = end.add(1);
-                    }
-                    end
-                };
-                let len = end.offset_from(start) as usize;
-                let slice = slice::from_raw_parts(start, len);
-                self.memory.io.write(slice)?;
-                self.memory.io.flush()?;
-            },
-            TrapCode::Halt => {
-                self.memory.io.write(b"HALT\n")?;
-                self.halt();
-            }
+impl<IO: IoDevice, S: Storage, T: TrapHandler<IO, S>> TrapHandler<IO, S> for Startup<'_, T> {
+    fn trap(
+        &mut self,
+        vector: u8,
+        registers: &mut Registers,
+        memory: &mut Memory<IO, S>,
+    ) -> Result<TrapAction, Error<IO::Error>> {
+        if vector == TrapCode::Halt as u8 {
+            Ok(TrapAction::Halt)
+        } else {
+            self.0.trap(vector, registers, memory)
         }
+    }
 
-        Ok(())
+    fn writes_memory(&self, vector: u8) -> bool {
+        vector != TrapCode::Halt as u8 && self.0.writes_memory(vector)
     }
 }
 
 /// Error type for [`Lc3`] functions.
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub enum Error<IO> {
     Io(IO),
     OpCodeNotImplemented,
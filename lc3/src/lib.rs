@@ -23,26 +23,98 @@
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "std")]
+mod block;
+#[cfg(feature = "std")]
+mod codecheck;
 mod condcodes;
+#[cfg(feature = "std")]
+mod coverage;
+#[cfg(feature = "std")]
+mod debuginfo;
 mod decode;
+#[cfg(feature = "std")]
+mod engine;
+#[cfg(feature = "std")]
+mod flowgraph;
+#[cfg(feature = "std")]
+mod headless;
 mod image;
+#[cfg(feature = "std")]
+mod initcheck;
+mod instruction;
 mod io;
 mod lc3;
+#[cfg(feature = "std")]
+mod lint;
 mod memory;
+mod monitor;
 mod opcode;
+#[cfg(feature = "std")]
+mod predecode;
+#[cfg(feature = "std")]
+mod profile;
 mod registers;
+#[cfg(feature = "std")]
+mod stackcheck;
+#[cfg(feature = "std")]
+mod stats;
+mod storage;
+#[cfg(feature = "std")]
+mod symbolic;
+#[cfg(feature = "std")]
+mod symbols;
 #[cfg(feature = "termios")]
 mod termios;
+mod trap;
 mod trapcode;
 
+#[cfg(feature = "std")]
+pub use block::BlockCache;
+#[cfg(feature = "std")]
+pub use codecheck::{CodeChecker, CodeEvent};
 pub use condcodes::CondCodes;
+#[cfg(feature = "std")]
+pub use coverage::{BranchCoverage, Coverage};
+#[cfg(feature = "std")]
+pub use debuginfo::{parse_number, DebugInfo, LineKind, SourceLine};
 pub(crate) use decode::InstructionDecode;
-pub use image::ImageFile;
+#[cfg(feature = "std")]
+pub use engine::{Engine, UnknownEngine};
+#[cfg(feature = "std")]
+pub use flowgraph::{BasicBlock, ControlFlowGraph, Edge};
+#[cfg(feature = "std")]
+pub use headless::Headless;
+pub use image::{ImageError, ImageFile, ImageFormat, ImageWriter, LoadReport};
+#[cfg(feature = "std")]
+pub use initcheck::{InitChecker, UninitializedRead};
+pub use instruction::Instruction;
 pub use io::IoDevice;
-pub use lc3::{Error, Lc3};
+pub use lc3::{Error, Lc3, LC3OS};
+#[cfg(feature = "std")]
+pub use lint::{Diagnostic, LintKind, Linter, Severity};
 pub use memory::Memory;
+pub use monitor::Monitor;
 pub use opcode::OpCode;
+#[cfg(feature = "std")]
+pub use predecode::DecodeCache;
+#[cfg(feature = "std")]
+pub use profile::{CallProfile, Profiler, RoutineProfile};
 pub use registers::{IoDeviceRegister, Reg, Registers};
+#[cfg(feature = "std")]
+pub use stackcheck::{StackChecker, StackViolation, StackViolationKind};
+#[cfg(feature = "std")]
+pub use stats::{RegionStats, Stats};
+#[cfg(feature = "std")]
+pub use storage::PagedStorage;
+pub use storage::Storage;
+#[cfg(feature = "std")]
+pub use symbolic::{ExecutionPath, Explorer, Goal, PathEnd};
+#[cfg(feature = "std")]
+pub use symbols::{Conflict, SymbolError, SymbolTable};
 #[cfg(feature = "termios")]
 pub use termios::Termios;
+#[cfg(feature = "std")]
+pub use trap::TrapTable;
+pub use trap::{TrapAction, TrapHandler, TrapVectorTable, VirtualTrapVectorTable};
 pub use trapcode::TrapCode;
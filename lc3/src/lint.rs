@@ -0,0 +1,288 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::{
+    CondCodes, ControlFlowGraph, DebugInfo, Edge, Instruction, LineKind, Storage, SymbolTable,
+    TrapCode,
+};
+
+/// Static checks of the code reachable in a [`ControlFlowGraph`],
+/// reporting suspicious instructions as [`Diagnostic`]s.
+///
+/// Code is followed from the entry points of the graph until it flows
+/// into data or outside the program. Data is known from the assembly
+/// sources added with [`Linter::add_debug_info`], or else guessed from
+/// the words read by `LD` and `LDI`. The program is made of the regions
+/// added with [`Linter::add_region`], or all of memory if none are.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{ControlFlowGraph, Diagnostic, Headless, Lc3, LintKind, Linter};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// lc3.memory.as_mut()[0x3000] = 0x2002; // LD R0, #2
+/// lc3.memory.as_mut()[0x3001] = 0x0001; // NOP #1
+/// lc3.memory.as_mut()[0x3002] = 0xF021; // OUT
+/// lc3.memory.as_mut()[0x3003] = 0x0041; // .FILL x41
+///
+/// let memory = lc3.memory.storage();
+/// let cfg = ControlFlowGraph::build(memory, &[0x3000]);
+/// let mut linter = Linter::new();
+/// linter.add_region(0x3000..=0x3003);
+/// assert_eq!(
+///     linter.check(memory, &cfg),
+///     [
+///         Diagnostic { addr: 0x3001, kind: LintKind::NeverTaken },
+///         Diagnostic { addr: 0x3002, kind: LintKind::ExecutesData { target: 0x3003 } },
+///     ]
+/// );
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Linter {
+    regions: Vec<RangeInclusive<u16>>,
+    lines: BTreeMap<u16, LineKind>,
+}
+
+/// Suspicious instruction found by a [`Linter`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Diagnostic {
+    /// Address of the instruction.
+    pub addr: u16,
+    /// What is suspicious about it.
+    pub kind: LintKind,
+}
+
+/// What a [`Diagnostic`] is about.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum LintKind {
+    /// A `BR` without condition codes, which is never taken.
+    NeverTaken,
+    /// A run of `len` instructions of the assembly sources that is never reached.
+    Unreachable { len: usize },
+    /// Control flows into the data at `target`.
+    ExecutesData { target: u16 },
+    /// Control flows outside the program, to `target`.
+    LeavesProgram { target: u16 },
+    /// A `TRAP` to a vector not defined by [`TrapCode`].
+    UndefinedTrap { vector: u8 },
+    /// The reserved opcode, which cannot be executed.
+    ReservedOpcode,
+    /// A PC-relative `LD`, `LDI` or `LEA` of `target`, outside the program.
+    LoadsOutside { target: u16 },
+}
+
+/// How bad a [`Diagnostic`] is.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Severity {
+    /// The instruction is probably wrong.
+    Warning,
+    /// The program cannot run as intended.
+    Error,
+}
+
+impl Linter {
+    /// Initialize a linter of all of memory, without sources.
+    pub fn new() -> Linter {
+        Linter::default()
+    }
+
+    /// Add `region` to the program, e.g. the addresses an image was loaded to.
+    pub fn add_region(&mut self, region: RangeInclusive<u16>) {
+        self.regions.push(region);
+    }
+
+    /// Tell instructions from data after the assembly sources of `debug_info`.
+    pub fn add_debug_info(&mut self, debug_info: &DebugInfo) {
+        self.lines
+            .extend(debug_info.iter().map(|(addr, line)| (addr, line.kind)));
+    }
+
+    /// Check the code of `cfg` in `memory`, returning the diagnostics in
+    /// ascending order of address.
+    pub fn check<S: Storage + ?Sized>(
+        &self,
+        memory: &S,
+        cfg: &ControlFlowGraph,
+    ) -> Vec<Diagnostic> {
+        let decode = |addr| Instruction::decode(memory.load(addr));
+        let mut loaded = BTreeSet::new();
+        for block in cfg.blocks() {
+            for addr in block.start..=block.end {
+                let inst = decode(addr);
+                if let Instruction::Ld { .. } | Instruction::Ldi { .. } = inst {
+                    loaded.extend(inst.target(addr));
+                }
+            }
+        }
+        let is_data = |addr| match self.lines.get(&addr) {
+            Some(&kind) => kind != LineKind::Instruction,
+            None => loaded.contains(&addr),
+        };
+
+        let mut diagnostics = Vec::new();
+        let mut reached = BTreeSet::new();
+        let mut pending: Vec<u16> = cfg
+            .entries()
+            .iter()
+            .copied()
+            .filter(|&x| !is_data(x))
+            .collect();
+        while let Some(start) = pending.pop() {
+            let Some(block) = cfg.block(start) else {
+                continue;
+            };
+            if !reached.insert(start) {
+                continue;
+            }
+
+            let mut report = |addr, kind| diagnostics.push(Diagnostic { addr, kind });
+            let mut addr = start;
+            loop {
+                reached.insert(addr);
+                let inst = decode(addr);
+                match inst {
+                    Instruction::Br { cc, .. } if cc == CondCodes::NONE => {
+                        report(addr, LintKind::NeverTaken);
+                    }
+                    Instruction::Trap { trapvect8 }
+                        if TrapCode::from_u16(trapvect8 as u16).is_none() =>
+                    {
+                        report(addr, LintKind::UndefinedTrap { vector: trapvect8 });
+                    }
+                    Instruction::Res => report(addr, LintKind::ReservedOpcode),
+                    Instruction::Ld { .. } | Instruction::Ldi { .. } | Instruction::Lea { .. } => {
+                        if let Some(target) = inst.target(addr).filter(|&x| !self.contains(x)) {
+                            report(addr, LintKind::LoadsOutside { target });
+                        }
+                    }
+                    _ => {}
+                }
+
+                let next: &[Edge] = if addr == block.end {
+                    &block.successors
+                } else {
+                    &[Edge::Next(addr.wrapping_add(1))]
+                };
+                let mut falls_through = false;
+                for &edge in next {
+                    let Some(target) = edge.target() else {
+                        continue;
+                    };
+                    let returns = matches!(edge, Edge::Next(_))
+                        && matches!(inst, Instruction::Jsr { .. } | Instruction::Jsrr { .. });
+                    if returns && (is_data(target) || !self.contains(target)) {
+                        // The routine called may never return.
+                        continue;
+                    } else if is_data(target) {
+                        report(addr, LintKind::ExecutesData { target });
+                    } else if !self.contains(target) {
+                        report(addr, LintKind::LeavesProgram { target });
+                    } else if addr == block.end {
+                        pending.push(target);
+                    } else {
+                        falls_through = true;
+                    }
+                }
+                if !falls_through {
+                    break;
+                }
+                addr = addr.wrapping_add(1);
+            }
+        }
+
+        let mut unreachable: Option<Diagnostic> = None;
+        for (&addr, &kind) in &self.lines {
+            if kind != LineKind::Instruction || !self.contains(addr) || reached.contains(&addr) {
+                continue;
+            }
+            match &mut unreachable {
+                Some(Diagnostic {
+                    addr: start,
+                    kind: LintKind::Unreachable { len },
+                }) if start.wrapping_add(*len as u16) == addr => *len += 1,
+                _ => {
+                    diagnostics.extend(unreachable);
+                    unreachable = Some(Diagnostic {
+                        addr,
+                        kind: LintKind::Unreachable { len: 1 },
+                    });
+                }
+            }
+        }
+        diagnostics.extend(unreachable);
+
+        diagnostics.sort_by_key(|x| x.addr);
+        diagnostics
+    }
+
+    /// True iff `addr` is in the program.
+    fn contains(&self, addr: u16) -> bool {
+        self.regions.is_empty() || self.regions.iter().any(|x| x.contains(&addr))
+    }
+}
+
+impl Diagnostic {
+    /// How bad the diagnostic is.
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            LintKind::ExecutesData { .. } | LintKind::ReservedOpcode => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+
+    /// Describe the diagnostic, with addresses labelled after `symbols`.
+    pub fn message(&self, symbols: &SymbolTable) -> String {
+        match self.kind {
+            LintKind::NeverTaken => "branch without condition codes is never taken".into(),
+            LintKind::Unreachable { len: 1 } => "unreachable instruction".into(),
+            LintKind::Unreachable { len } => format!("{len} unreachable instructions"),
+            LintKind::ExecutesData { target } => {
+                format!("control flows into data at {}", symbols.display(target))
+            }
+            LintKind::LeavesProgram { target } => {
+                format!(
+                    "control flows outside the program to {}",
+                    symbols.display(target)
+                )
+            }
+            LintKind::UndefinedTrap { vector } => {
+                format!("TRAP x{vector:02X} is not a service routine of the operating system")
+            }
+            LintKind::ReservedOpcode => "reserved opcode cannot be executed".into(),
+            LintKind::LoadsOutside { target } => {
+                format!(
+                    "PC-relative address {} is outside the program",
+                    symbols.display(target)
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
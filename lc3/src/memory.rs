@@ -16,17 +16,42 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+use core::ops::{Range, RangeInclusive};
 use core::slice;
 
-use crate::{IoDevice, IoDeviceRegister};
+#[cfg(feature = "std")]
+use crate::PagedStorage;
+use crate::{IoDevice, IoDeviceRegister, Storage};
+#[cfg(feature = "std")]
+use std::sync::Arc;
 
 /// Number of 'words' in [`Memory`] or length of underlying slice.
-const LEN: usize = 1 << 16;
+pub(crate) const LEN: usize = 1 << 16;
 
 /// Main memory unit in LC-3.
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
-pub struct Memory<IO: IoDevice> {
-    words: [u16; LEN],
+///
+/// Words are kept in a [`Storage`], inline in the memory itself by default.
+///
+/// Memory also tracks which words were ever initialized, by
+/// [`Self::write`], [`Self::store`] or loading an image with
+/// [`Lc3::load_image`][`crate::Lc3::load_image`]. Words modified through
+/// [`Self::storage_mut`] or [`AsMut`] are initialized with
+/// [`Self::mark_initialized`]. Words loaded by images are tracked too, to
+/// report those overwritten by later images in their
+/// [`LoadReport`][`crate::LoadReport`]. Which words were initialized or
+/// loaded is left out of comparisons and hashing: memories holding the same
+/// words and I/O device are equal however they were written.
+///
+/// With the `std` feature enabled, which words were initialized or loaded
+/// is kept in copy-on-write pages on the heap, so memory backed by
+/// [`PagedStorage`] is small and cheap to [`Self::fork`].
+#[derive(Clone, Debug)]
+pub struct Memory<IO: IoDevice, S: Storage = [u16; LEN]> {
+    words: S,
+    /// Which words were initialized or loaded by an image.
+    marks: Marks,
     pub(crate) io: IO,
 }
 
@@ -34,6 +59,7 @@ impl<IO: IoDevice + Default> Default for Memory<IO> {
     fn default() -> Memory<IO> {
         Memory {
             words: [0; LEN],
+            marks: Marks::new(),
             io: Default::default(),
         }
     }
@@ -44,9 +70,94 @@ impl<IO: IoDevice> Memory<IO> {
     pub const fn new(iodevice: IO) -> Memory<IO> {
         Memory {
             words: [0; LEN],
+            marks: Marks::new(),
             io: iodevice,
         }
     }
+}
+
+impl<IO: IoDevice, S: Storage> Memory<IO, S> {
+    /// Initialize a new memory device with words kept in `storage`.
+    ///
+    /// No word is considered initialized, whatever `storage` holds.
+    pub const fn with_storage(iodevice: IO, storage: S) -> Memory<IO, S> {
+        Memory {
+            words: storage,
+            marks: Marks::new(),
+            io: iodevice,
+        }
+    }
+
+    /// The [`Storage`][`crate::Storage`] of memory.
+    pub fn storage(&self) -> &S {
+        &self.words
+    }
+
+    /// The [`Storage`][`crate::Storage`] of memory, mutably.
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.words
+    }
+
+    /// Deconstruct memory into its I/O device and storage.
+    pub fn into_parts(self) -> (IO, S) {
+        (self.io, self.words)
+    }
+
+    /// The word stored at `index`, without the side effects of reading
+    /// memory-mapped I/O registers.
+    pub fn load(&self, index: u16) -> u16 {
+        self.words.load(index)
+    }
+
+    /// Store `value` at `index`, without the side effects of writing
+    /// memory-mapped I/O registers.
+    pub fn store(&mut self, index: u16, value: u16) {
+        self.set_initialized(index);
+        self.words.store(index, value)
+    }
+
+    /// True iff the word at `index` was ever initialized.
+    pub fn is_initialized(&self, index: u16) -> bool {
+        self.marks.get(Mark::Initialized, index)
+    }
+
+    /// Consider the words at `range` initialized.
+    pub fn mark_initialized(&mut self, range: RangeInclusive<u16>) {
+        for index in range {
+            self.set_initialized(index);
+        }
+    }
+
+    fn set_initialized(&mut self, index: u16) {
+        self.marks.set(Mark::Initialized, index);
+    }
+
+    /// Consider the words at `range` loaded by an image, and initialized.
+    /// Returns the addresses from the first through the last word of
+    /// `range` already loaded by a previous image, if any.
+    pub(crate) fn mark_loaded(&mut self, range: Range<usize>) -> Option<Range<usize>> {
+        let mut overwritten: Option<Range<usize>> = None;
+        for addr in range {
+            let index = addr as u16;
+            if self.marks.get(Mark::Loaded, index) {
+                let start = overwritten.map_or(addr, |x| x.start);
+                overwritten = Some(start..addr + 1);
+            }
+            self.marks.set(Mark::Loaded, index);
+            self.set_initialized(index);
+        }
+        overwritten
+    }
+
+    /// The [`IoDevice`][`crate::IoDevice`] attached to memory.
+    pub fn io(&self) -> &IO {
+        &self.io
+    }
+
+    /// The [`IoDevice`][`crate::IoDevice`] attached to memory, mutably.
+    pub fn io_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
 
     /// Read the value at index `index` in memory.
     pub fn read(&mut self, index: u16) -> u16 {
@@ -69,7 +180,7 @@ impl<IO: IoDevice> Memory<IO> {
             }
             Some(IoDeviceRegister::Dsr) => IoDeviceRegister::STATUS_ACCEPT,
             Some(IoDeviceRegister::Ddr) => IoDeviceRegister::STATUS_DECLINE,
-            _ => self.words[index as usize],
+            _ => self.words.load(index),
         }
     }
 
@@ -77,27 +188,145 @@ impl<IO: IoDevice> Memory<IO> {
     pub fn write(&mut self, index: u16, value: u16) {
         match IoDeviceRegister::from_u16(index) {
             Some(IoDeviceRegister::Mcr) | None => {
-                self.words[index as usize] = value;
+                self.store(index, value);
             }
             Some(IoDeviceRegister::Ddr) => {
                 let byte = value as u8;
                 let _ = self.io.write(slice::from_ref(&byte));
                 let _ = self.io.flush();
-                return;
             }
-            _ => return,
+            _ => (),
         }
     }
 }
 
-impl<IO: IoDevice> AsRef<[u16]> for Memory<IO> {
+#[cfg(feature = "std")]
+impl<IO: IoDevice> Memory<IO, PagedStorage> {
+    /// A copy of memory attached to `iodevice`, sharing all pages
+    /// with `self` until either memory writes to them.
+    pub fn fork<J: IoDevice>(&self, iodevice: J) -> Memory<J, PagedStorage> {
+        Memory {
+            words: self.words.clone(),
+            marks: self.marks.clone(),
+            io: iodevice,
+        }
+    }
+}
+
+impl<IO: IoDevice, S: Storage + AsRef<[u16]>> AsRef<[u16]> for Memory<IO, S> {
     fn as_ref(&self) -> &[u16] {
-        &self.words
+        self.words.as_ref()
     }
 }
 
-impl<IO: IoDevice> AsMut<[u16]> for Memory<IO> {
+impl<IO: IoDevice, S: Storage + AsMut<[u16]>> AsMut<[u16]> for Memory<IO, S> {
     fn as_mut(&mut self) -> &mut [u16] {
-        &mut self.words
+        self.words.as_mut()
+    }
+}
+
+/// Number of words tracked by each element of a bitmap of addresses.
+const BITS: usize = u64::BITS as usize;
+
+/// Number of pages of [`Marks`].
+const MARK_PAGES: usize = 16;
+
+/// Number of words marked by a page of [`Marks`].
+const MARK_PAGE_LEN: usize = LEN / MARK_PAGES;
+
+/// A page of [`Marks`], with one bitmap per [`Mark`].
+type MarkPage = [[u64; MARK_PAGE_LEN / BITS]; 2];
+
+/// What a word of [`Memory`] is marked as.
+#[derive(Clone, Copy)]
+enum Mark {
+    Initialized,
+    Loaded,
+}
+
+/// Which words of [`Memory`] are marked as each [`Mark`], in pages
+/// allocated when first marked and, with the `std` feature enabled,
+/// shared between clones until either marks them.
+#[derive(Clone, Debug)]
+struct Marks {
+    #[cfg(feature = "std")]
+    pages: [Option<Arc<MarkPage>>; MARK_PAGES],
+    #[cfg(not(feature = "std"))]
+    pages: [MarkPage; MARK_PAGES],
+}
+
+impl Marks {
+    #[cfg(feature = "std")]
+    const fn new() -> Marks {
+        const NONE: Option<Arc<MarkPage>> = None;
+        Marks {
+            pages: [NONE; MARK_PAGES],
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    const fn new() -> Marks {
+        Marks {
+            pages: [[[0; MARK_PAGE_LEN / BITS]; 2]; MARK_PAGES],
+        }
+    }
+
+    /// True iff the word at `index` is marked as `mark`.
+    fn get(&self, mark: Mark, index: u16) -> bool {
+        let index = index as usize;
+        #[cfg(feature = "std")]
+        let Some(page) = &self.pages[index / MARK_PAGE_LEN] else {
+            return false;
+        };
+        #[cfg(not(feature = "std"))]
+        let page = &self.pages[index / MARK_PAGE_LEN];
+        let index = index % MARK_PAGE_LEN;
+        page[mark as usize][index / BITS] & (1 << (index % BITS)) != 0
+    }
+
+    /// Mark the word at `index` as `mark`.
+    fn set(&mut self, mark: Mark, index: u16) {
+        if self.get(mark, index) {
+            return;
+        }
+        let index = index as usize;
+        let page = &mut self.pages[index / MARK_PAGE_LEN];
+        #[cfg(feature = "std")]
+        let page =
+            Arc::make_mut(page.get_or_insert_with(|| Arc::new([[0; MARK_PAGE_LEN / BITS]; 2])));
+        let index = index % MARK_PAGE_LEN;
+        page[mark as usize][index / BITS] |= 1 << (index % BITS);
+    }
+}
+
+impl<IO: IoDevice + PartialEq, S: Storage + PartialEq> PartialEq for Memory<IO, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.words == other.words && self.io == other.io
+    }
+}
+
+impl<IO: IoDevice + Eq, S: Storage + Eq> Eq for Memory<IO, S> {}
+
+impl<IO: IoDevice + PartialOrd, S: Storage + PartialOrd> PartialOrd for Memory<IO, S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.words.partial_cmp(&other.words) {
+            Some(Ordering::Equal) => self.io.partial_cmp(&other.io),
+            ordering => ordering,
+        }
+    }
+}
+
+impl<IO: IoDevice + Ord, S: Storage + Ord> Ord for Memory<IO, S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.words
+            .cmp(&other.words)
+            .then_with(|| self.io.cmp(&other.io))
+    }
+}
+
+impl<IO: IoDevice + Hash, S: Storage + Hash> Hash for Memory<IO, S> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.words.hash(state);
+        self.io.hash(state);
     }
 }
@@ -0,0 +1,119 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::lc3::Startup;
+use crate::memory::LEN;
+use crate::{Error, Instruction, IoDevice, Lc3, Storage, TrapHandler};
+
+/// An observer of executed instructions, such as a profiler.
+///
+/// See [`Lc3::next_instruction_monitored`].
+pub trait Monitor<IO: IoDevice, S: Storage = [u16; LEN]> {
+    /// Called before `inst`, fetched from `pc`, executes on `lc3`.
+    ///
+    /// Returning false halts the machine instead, leaving `inst` unexecuted.
+    fn executing(&mut self, _pc: u16, _inst: Instruction, _lc3: &Lc3<IO, S>) -> bool {
+        true
+    }
+
+    /// Called after `inst`, fetched from `pc`, executed on `lc3`.
+    fn executed(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>);
+}
+
+impl<IO: IoDevice, S: Storage> Lc3<IO, S> {
+    /// Run indefinitely at `addr` with traps serviced by `traps` and every
+    /// instruction reported to `monitor` until [`Self::should_halt`] returns true.
+    pub fn run_monitored_at<T: TrapHandler<IO, S>, M: Monitor<IO, S>>(
+        &mut self,
+        addr: u16,
+        traps: &mut T,
+        monitor: &mut M,
+    ) -> Result<(), Error<IO::Error>> {
+        self.reset();
+        self.registers.pc = addr;
+        while !self.should_halt() {
+            self.next_instruction_monitored(traps, monitor)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like [`Self::boot_with_trap_handler`], with every instruction reported to `monitor`.
+    pub fn boot_monitored<T: TrapHandler<IO, S>, M: Monitor<IO, S>>(
+        &mut self,
+        entry: u16,
+        traps: &mut T,
+        monitor: &mut M,
+    ) -> Result<(), Error<IO::Error>> {
+        let addr = Self::OPERATING_SYSTEM_START;
+        self.run_monitored_at(addr, &mut Startup(traps), monitor)?;
+        self.run_monitored_at(entry, traps, monitor)
+    }
+
+    /// Execute next instruction with traps serviced by `traps`, reporting it
+    /// to `monitor` before and after, unless the monitor halts the machine.
+    pub fn next_instruction_monitored<T: TrapHandler<IO, S>, M: Monitor<IO, S>>(
+        &mut self,
+        traps: &mut T,
+        monitor: &mut M,
+    ) -> Result<(), Error<IO::Error>> {
+        let pc = self.registers.pc;
+        let inst = Instruction::decode(self.memory.read(pc));
+        if !monitor.executing(pc, inst, self) {
+            self.halt();
+            return Ok(());
+        }
+        self.execute(inst, traps)?;
+        monitor.executed(pc, inst, self);
+        Ok(())
+    }
+}
+
+/// Report to the monitor, if any.
+impl<IO: IoDevice, S: Storage, M: Monitor<IO, S>> Monitor<IO, S> for Option<M> {
+    fn executing(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) -> bool {
+        match self {
+            Some(monitor) => monitor.executing(pc, inst, lc3),
+            None => true,
+        }
+    }
+
+    fn executed(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) {
+        if let Some(monitor) = self {
+            monitor.executed(pc, inst, lc3);
+        }
+    }
+}
+
+/// Report to both monitors, in order, halting if either one does.
+impl<IO: IoDevice, S: Storage, A, B> Monitor<IO, S> for (A, B)
+where
+    A: Monitor<IO, S>,
+    B: Monitor<IO, S>,
+{
+    fn executing(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) -> bool {
+        let a = self.0.executing(pc, inst, lc3);
+        let b = self.1.executing(pc, inst, lc3);
+        a && b
+    }
+
+    fn executed(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) {
+        self.0.executed(pc, inst, lc3);
+        self.1.executed(pc, inst, lc3);
+    }
+}
@@ -16,6 +16,8 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
+use core::fmt;
+
 /// Instruction opcodes in LC-3.
 ///
 /// - Arithmetics: [`OpCode::Add`], [`OpCode::And`], [`OpCode::Not`]
@@ -26,6 +28,7 @@
 /// - Reserved: [`OpCode::Res`]
 ///
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
 pub enum OpCode {
     /// Conditional Branch.
     Br,
@@ -62,3 +65,49 @@ pub enum OpCode {
     /// System Call.
     Trap,
 }
+
+impl OpCode {
+    /// All opcodes, in order of encoding.
+    pub const ALL: [OpCode; 16] = [
+        OpCode::Br,
+        OpCode::Add,
+        OpCode::Ld,
+        OpCode::St,
+        OpCode::Jsr,
+        OpCode::And,
+        OpCode::Ldr,
+        OpCode::Str,
+        OpCode::Rti,
+        OpCode::Not,
+        OpCode::Ldi,
+        OpCode::Sti,
+        OpCode::Jmp,
+        OpCode::Res,
+        OpCode::Lea,
+        OpCode::Trap,
+    ];
+}
+
+/// Display the mnemonic of the opcode, e.g. `JSR`.
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OpCode::Br => "BR",
+            OpCode::Add => "ADD",
+            OpCode::Ld => "LD",
+            OpCode::St => "ST",
+            OpCode::Jsr => "JSR",
+            OpCode::And => "AND",
+            OpCode::Ldr => "LDR",
+            OpCode::Str => "STR",
+            OpCode::Rti => "RTI",
+            OpCode::Not => "NOT",
+            OpCode::Ldi => "LDI",
+            OpCode::Sti => "STI",
+            OpCode::Jmp => "JMP",
+            OpCode::Res => "RES",
+            OpCode::Lea => "LEA",
+            OpCode::Trap => "TRAP",
+        })
+    }
+}
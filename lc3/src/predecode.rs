@@ -0,0 +1,126 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::lc3::Written;
+use crate::memory::LEN;
+use crate::{Error, Instruction, IoDevice, IoDeviceRegister, Lc3, Memory, Storage, TrapHandler};
+
+/// A cache of instructions predecoded from memory, indexed by address.
+///
+/// Cached instructions are invalidated when the machine writes to their
+/// address. Memory modified by other means, such as
+/// [`Lc3::load_image`][`crate::Lc3::load_image`] or
+/// [`Memory::as_mut`][`crate::Memory`], requires a call to [`DecodeCache::clear`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct DecodeCache {
+    instructions: Box<[Instruction]>,
+    /// Generation each address was cached in, the instruction at an
+    /// address is cached iff its generation is the current one.
+    generations: Box<[u32]>,
+    /// Current generation, never zero so that zeroed entries are invalid.
+    generation: u32,
+}
+
+impl DecodeCache {
+    /// Initialize an empty cache.
+    pub fn new() -> DecodeCache {
+        DecodeCache {
+            instructions: vec![Instruction::Res; LEN].into_boxed_slice(),
+            generations: vec![0; LEN].into_boxed_slice(),
+            generation: 1,
+        }
+    }
+
+    /// Invalidate the instruction cached for `addr`.
+    pub fn invalidate(&mut self, addr: u16) {
+        self.generations[addr as usize] = 0;
+    }
+
+    /// Invalidate all cached instructions.
+    pub fn clear(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        if self.generation == 0 {
+            self.generations.fill(0);
+            self.generation = 1;
+        }
+    }
+
+    /// Fetch the instruction at `pc`, decoding and caching it on a miss.
+    ///
+    /// Cache hits skip the memory-mapped I/O checks of [`Memory::read`],
+    /// and instructions fetched from I/O device registers are never cached.
+    fn fetch<IO: IoDevice, S: Storage>(
+        &mut self,
+        memory: &mut Memory<IO, S>,
+        pc: u16,
+    ) -> Instruction {
+        if self.generations[pc as usize] == self.generation {
+            return self.instructions[pc as usize];
+        }
+
+        if IoDeviceRegister::from_u16(pc).is_some() {
+            return Instruction::decode(memory.read(pc));
+        }
+
+        let inst = Instruction::decode(memory.load(pc));
+        self.instructions[pc as usize] = inst;
+        self.generations[pc as usize] = self.generation;
+        inst
+    }
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        DecodeCache::new()
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Lc3<IO, S> {
+    /// Run indefinitely at `addr` with instructions fetched from `cache` and
+    /// traps serviced by `traps` until [`Self::should_halt`] returns true.
+    pub fn run_predecoded_at<T: TrapHandler<IO, S>>(
+        &mut self,
+        addr: u16,
+        cache: &mut DecodeCache,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
+        self.reset();
+        self.registers.pc = addr;
+        while !self.should_halt() {
+            self.next_instruction_predecoded(cache, traps)?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute next instruction fetched from `cache` with traps serviced by `traps`.
+    pub fn next_instruction_predecoded<T: TrapHandler<IO, S>>(
+        &mut self,
+        cache: &mut DecodeCache,
+        traps: &mut T,
+    ) -> Result<(), Error<IO::Error>> {
+        let inst = cache.fetch(&mut self.memory, self.registers.pc);
+        match self.execute(inst, traps)? {
+            Written::Nothing => (),
+            Written::Word(addr) => cache.invalidate(addr),
+            Written::Unknown => cache.clear(),
+        }
+
+        Ok(())
+    }
+}
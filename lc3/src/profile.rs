@@ -0,0 +1,387 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::memory::LEN;
+use crate::{Instruction, IoDevice, Lc3, Monitor, Reg, Storage, SymbolTable};
+
+/// No node, the parent of the root of the call tree.
+const NO_NODE: usize = usize::MAX;
+
+/// An instruction-level profiler counting executions per address and
+/// attributing them to subroutines.
+///
+/// Subroutines are entered by `JSR`, `JSRR` and traps dispatched through
+/// the trap vector table, and left by `RET` (`JMP R7`) to the return
+/// address of a subroutine on the call stack. Execution starts in a
+/// routine at the address of the first instruction profiled, and in a new
+/// one whenever the machine is restarted elsewhere, such as after booting.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Headless, Lc3, Profiler, VirtualTrapVectorTable};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// lc3.memory.as_mut()[0x3000] = 0x4802; // JSR #2
+/// lc3.memory.as_mut()[0x3001] = 0xF025; // HALT
+/// lc3.memory.as_mut()[0x3003] = 0xC1C0; // RET
+///
+/// let mut profiler = Profiler::new();
+/// lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut profiler)
+///     .unwrap();
+/// assert_eq!(profiler.instructions(), 3);
+/// assert_eq!(profiler.count(0x3003), 1);
+///
+/// let routines = profiler.routines();
+/// assert_eq!((routines[0].addr, routines[0].total_instructions), (0x3000, 3));
+/// assert_eq!((routines[1].addr, routines[1].calls), (0x3003, 1));
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Profiler {
+    /// Executions per address.
+    counts: Box<[u64]>,
+    /// Call trees, parents before children.
+    nodes: Vec<Node>,
+    /// Roots of the call trees by routine.
+    roots: HashMap<u16, usize>,
+    /// Address of the next instruction, after the last one profiled.
+    next: Option<u16>,
+    /// Node of the executing routine.
+    current: usize,
+    /// Return addresses of the calls on the call stack, innermost last.
+    returns: Vec<u16>,
+    instructions: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Node {
+    routine: u16,
+    parent: usize,
+    calls: u64,
+    /// Instructions executed in the routine itself.
+    instructions: u64,
+    children: HashMap<u16, usize>,
+}
+
+/// Cost of a subroutine, see [`Profiler::routines`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct RoutineProfile {
+    /// Entry address.
+    pub addr: u16,
+    /// Number of times the routine was called.
+    pub calls: u64,
+    /// Instructions executed in the routine itself.
+    pub self_instructions: u64,
+    /// Instructions executed in the routine and the routines it called.
+    pub total_instructions: u64,
+}
+
+/// Calls from one subroutine to another, see [`Profiler::calls`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct CallProfile {
+    /// Entry address of the calling routine.
+    pub caller: u16,
+    /// Entry address of the called routine.
+    pub callee: u16,
+    /// Number of calls.
+    pub calls: u64,
+    /// Instructions executed in the called routine and the routines
+    /// it called, during these calls.
+    pub total_instructions: u64,
+}
+
+impl Profiler {
+    /// Initialize an empty profile.
+    pub fn new() -> Profiler {
+        Profiler {
+            counts: vec![0; LEN].into_boxed_slice(),
+            nodes: Vec::new(),
+            roots: HashMap::new(),
+            next: None,
+            current: NO_NODE,
+            returns: Vec::new(),
+            instructions: 0,
+        }
+    }
+
+    /// Number of instructions profiled.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Number of instructions executed at `addr`.
+    pub fn count(&self, addr: u16) -> u64 {
+        self.counts[addr as usize]
+    }
+
+    /// All routines, most expensive in instructions executed in the routine itself first.
+    pub fn routines(&self) -> Vec<RoutineProfile> {
+        let totals = self.subtree_totals();
+        let mut routines: HashMap<u16, RoutineProfile> = HashMap::new();
+        for node in &self.nodes {
+            let routine = routines.entry(node.routine).or_insert(RoutineProfile {
+                addr: node.routine,
+                calls: 0,
+                self_instructions: 0,
+                total_instructions: 0,
+            });
+            routine.calls += node.calls;
+            routine.self_instructions += node.instructions;
+        }
+
+        // Add each subtree to the total of its routine only at the outermost
+        // occurrence of the routine on the path from the root, so that
+        // recursive routines aren't counted more than once.
+        let mut on_path: HashMap<u16, usize> = HashMap::new();
+        let mut stack: Vec<_> = self.roots.values().map(|&root| (root, false)).collect();
+        while let Some((index, leaving)) = stack.pop() {
+            let node = &self.nodes[index];
+            let depth = on_path.entry(node.routine).or_insert(0);
+            if leaving {
+                *depth -= 1;
+                continue;
+            }
+            if *depth == 0 {
+                routines.get_mut(&node.routine).unwrap().total_instructions += totals[index];
+            }
+            *depth += 1;
+            stack.push((index, true));
+            stack.extend(node.children.values().map(|&child| (child, false)));
+        }
+
+        let mut routines: Vec<_> = routines.into_values().collect();
+        routines.sort_by_key(|x| (u64::MAX - x.self_instructions, x.addr));
+        routines
+    }
+
+    /// Calls between all pairs of routines, in order of caller and callee.
+    pub fn calls(&self) -> Vec<CallProfile> {
+        let totals = self.subtree_totals();
+        let mut calls: HashMap<(u16, u16), CallProfile> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.parent == NO_NODE {
+                continue;
+            }
+            let caller = self.nodes[node.parent].routine;
+            let call = calls.entry((caller, node.routine)).or_insert(CallProfile {
+                caller,
+                callee: node.routine,
+                calls: 0,
+                total_instructions: 0,
+            });
+            call.calls += node.calls;
+            call.total_instructions += totals[index];
+        }
+
+        let mut calls: Vec<_> = calls.into_values().collect();
+        calls.sort_by_key(|x| (x.caller, x.callee));
+        calls
+    }
+
+    /// Write a flat profile of routines, the call graph, and the
+    /// execution counts of all executed addresses, with addresses
+    /// named after labels in `symbols`.
+    pub fn write_report<W: Write>(&self, w: &mut W, symbols: &SymbolTable) -> io::Result<()> {
+        let percent = |n: u64| 100.0 * n as f64 / self.instructions.max(1) as f64;
+
+        writeln!(w, "Flat profile: {} instructions", self.instructions)?;
+        writeln!(w)?;
+        writeln!(w, "  self%        self       total     calls  routine")?;
+        let routines = self.routines();
+        for x in &routines {
+            writeln!(
+                w,
+                "{:6.2}% {:>11} {:>11} {:>9}  {}",
+                percent(x.self_instructions),
+                x.self_instructions,
+                x.total_instructions,
+                x.calls,
+                symbols.display(x.addr),
+            )?;
+        }
+
+        writeln!(w)?;
+        writeln!(w, "Call graph")?;
+        let calls = self.calls();
+        for x in &routines {
+            writeln!(w)?;
+            writeln!(
+                w,
+                "{}  total {} ({:.2}%), self {}, {} calls",
+                symbols.display(x.addr),
+                x.total_instructions,
+                percent(x.total_instructions),
+                x.self_instructions,
+                x.calls,
+            )?;
+            for call in calls.iter().filter(|call| call.callee == x.addr) {
+                writeln!(
+                    w,
+                    "    <- {}  {} calls",
+                    symbols.display(call.caller),
+                    call.calls,
+                )?;
+            }
+            for call in calls.iter().filter(|call| call.caller == x.addr) {
+                writeln!(
+                    w,
+                    "    -> {}  {} calls, total {}",
+                    symbols.display(call.callee),
+                    call.calls,
+                    call.total_instructions,
+                )?;
+            }
+        }
+
+        writeln!(w)?;
+        writeln!(w, "Instructions")?;
+        writeln!(w)?;
+        writeln!(w, "      count  address")?;
+        let mut addrs: Vec<_> = (0..LEN).filter(|&addr| self.counts[addr] != 0).collect();
+        addrs.sort_by_key(|&addr| (u64::MAX - self.counts[addr], addr));
+        for addr in addrs {
+            writeln!(
+                w,
+                "{:>11}  {}",
+                self.counts[addr],
+                symbols.display(addr as u16)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the call stacks in folded format, one `ROUTINE;ROUTINE;... COUNT`
+    /// line per stack, as read by flame graph tools. Routines are named after
+    /// labels in `symbols`.
+    pub fn write_folded<W: Write>(&self, w: &mut W, symbols: &SymbolTable) -> io::Result<()> {
+        let name = |addr: u16| match symbols.locate(addr) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{name}+{offset}"),
+            None => format!("x{addr:04X}"),
+        };
+
+        let mut path = Vec::new();
+        for node in &self.nodes {
+            if node.instructions == 0 {
+                continue;
+            }
+            path.clear();
+            let mut index = node.parent;
+            path.push(name(node.routine));
+            while index != NO_NODE {
+                path.push(name(self.nodes[index].routine));
+                index = self.nodes[index].parent;
+            }
+            path.reverse();
+            writeln!(w, "{} {}", path.join(";"), node.instructions)?;
+        }
+
+        Ok(())
+    }
+
+    /// Instructions executed in the subtree of the call tree at each node.
+    fn subtree_totals(&self) -> Vec<u64> {
+        let mut totals: Vec<_> = self.nodes.iter().map(|node| node.instructions).collect();
+        for (index, node) in self.nodes.iter().enumerate().rev() {
+            if node.parent != NO_NODE {
+                totals[node.parent] += totals[index];
+            }
+        }
+        totals
+    }
+
+    /// Start over at the root of the call tree of `routine`.
+    fn enter(&mut self, routine: u16) {
+        let next = self.nodes.len();
+        self.current = *self.roots.entry(routine).or_insert(next);
+        if self.current == next {
+            self.nodes.push(Node {
+                routine,
+                parent: NO_NODE,
+                calls: 0,
+                instructions: 0,
+                children: HashMap::new(),
+            });
+        }
+        self.returns.clear();
+    }
+
+    fn call(&mut self, target: u16, ret: u16) {
+        let next = self.nodes.len();
+        let child = *self.nodes[self.current]
+            .children
+            .entry(target)
+            .or_insert(next);
+        if child == next {
+            self.nodes.push(Node {
+                routine: target,
+                parent: self.current,
+                calls: 0,
+                instructions: 0,
+                children: HashMap::new(),
+            });
+        }
+        self.nodes[child].calls += 1;
+        self.returns.push(ret);
+        self.current = child;
+    }
+
+    fn ret(&mut self, target: u16) {
+        let Some(depth) = self.returns.iter().rposition(|&ret| ret == target) else {
+            return;
+        };
+        for _ in depth..self.returns.len() {
+            self.current = self.nodes[self.current].parent;
+        }
+        self.returns.truncate(depth);
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Monitor<IO, S> for Profiler {
+    fn executed(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) {
+        if self.next != Some(pc) {
+            self.enter(pc);
+        }
+        self.next = Some(lc3.registers.pc);
+
+        self.counts[pc as usize] += 1;
+        self.instructions += 1;
+        self.nodes[self.current].instructions += 1;
+
+        let next = pc.wrapping_add(1);
+        match inst {
+            Instruction::Jsr { .. } | Instruction::Jsrr { .. } => {
+                self.call(lc3.registers.pc, next);
+            }
+            Instruction::Trap { .. } if lc3.registers.pc != next => {
+                self.call(lc3.registers.pc, next);
+            }
+            Instruction::Jmp { baser: Reg::R7 } => self.ret(lc3.registers.pc),
+            _ => (),
+        }
+    }
+}
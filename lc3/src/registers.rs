@@ -21,6 +21,7 @@ use core::ops::{Index, IndexMut};
 
 /// General purpose registers of LC-3.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[repr(u8)]
 pub enum Reg {
     /// First general-purpose register.
     R0,
@@ -47,8 +48,12 @@ impl Reg {
     }
 
     /// [`Reg`] from a 16-bit value (bounds unchecked).
+    ///
+    /// # Safety
+    ///
+    /// `value` must be less than 8.
     pub const unsafe fn from_u16_unchecked(value: u16) -> Reg {
-        core::mem::transmute(value as i8)
+        core::mem::transmute(value as u8)
     }
 }
 
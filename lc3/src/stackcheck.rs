@@ -0,0 +1,265 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::HashSet;
+use std::mem::{self, Discriminant};
+use std::ops::RangeInclusive;
+
+use crate::memory::LEN;
+use crate::{DebugInfo, Instruction, IoDevice, Lc3, LineKind, Monitor, Reg, Storage};
+
+/// A checker of the stack discipline and calling convention of subroutines.
+///
+/// Subroutines are called by `JSR` and `JSRR` with the return address in
+/// R7, and return with `RET` (`JMP R7`). On return, a subroutine must
+/// leave the stack pointer R6 and the callee-saved registers as they were
+/// when it was called. Pushes, `STR` relative to R6, must not write to
+/// protected memory: instructions already executed, and regions given to
+/// [`StackChecker::protect`] or [`StackChecker::add_debug_info`].
+///
+/// Traps dispatched through the trap vector table are tracked as calls,
+/// but nothing is checked until they return, as service routines don't
+/// follow the convention.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Headless, Lc3, Reg, StackChecker, StackViolationKind, VirtualTrapVectorTable};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// lc3.memory.store(0x3000, 0x4801); // JSR #1
+/// lc3.memory.store(0x3001, 0xF025); // HALT
+/// lc3.memory.store(0x3002, 0x1261); // ADD R1, R1, #1
+/// lc3.memory.store(0x3003, 0xC1C0); // RET
+///
+/// let mut checker = StackChecker::new(&[Reg::R1, Reg::R2]);
+/// lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut checker)
+///     .unwrap();
+///
+/// let violation = &checker.violations()[0];
+/// assert_eq!(violation.pc, 0x3003);
+/// assert_eq!(
+///     violation.kind,
+///     StackViolationKind::CalleeSaved { reg: Reg::R1, expected: 0, actual: 1 },
+/// );
+/// assert_eq!(violation.trace, [0x3000]);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StackChecker {
+    /// True iff the machine is halted at the first violation.
+    stop: bool,
+    callee_saved: Vec<Reg>,
+    /// Addresses pushes must not write to.
+    protected: Box<[bool]>,
+    /// Subroutines called and not yet returned from, innermost last.
+    frames: Vec<Frame>,
+    /// Address of the next instruction, after the last one checked.
+    next: Option<u16>,
+    violations: Vec<StackViolation>,
+    reported: HashSet<(u16, Discriminant<StackViolationKind>, Option<Reg>)>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Frame {
+    /// Address of the calling instruction.
+    call_site: u16,
+    /// True iff the frame is of a trap service routine.
+    trap: bool,
+    /// Registers right after the call.
+    registers: [u16; 8],
+}
+
+/// A violation reported by [`StackChecker`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct StackViolation {
+    /// Address of the instruction violating the convention.
+    pub pc: u16,
+    /// What was violated.
+    pub kind: StackViolationKind,
+    /// Addresses of the calls on the call stack, outermost first.
+    pub trace: Vec<u16>,
+}
+
+/// What a [`StackViolation`] violated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum StackViolationKind {
+    /// `RET` with R6 different from its value at the call.
+    Unbalanced { expected: u16, actual: u16 },
+    /// `RET` to an address other than the return address of the call.
+    ReturnAddress { expected: u16, actual: u16 },
+    /// `RET` with a callee-saved register different from its value at the call.
+    CalleeSaved {
+        reg: Reg,
+        expected: u16,
+        actual: u16,
+    },
+    /// A push writing to protected memory at `addr`.
+    Overflow { addr: u16 },
+}
+
+impl StackChecker {
+    /// Initialize a checker reporting every violation, with the registers
+    /// `callee_saved` preserved by subroutines.
+    pub fn new(callee_saved: &[Reg]) -> StackChecker {
+        StackChecker {
+            stop: false,
+            callee_saved: callee_saved.to_vec(),
+            protected: vec![false; LEN].into_boxed_slice(),
+            frames: Vec::new(),
+            next: None,
+            violations: Vec::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Like [`StackChecker::new`], but halting the machine at the first
+    /// violation, before the instruction violating the convention executes.
+    pub fn stopping(callee_saved: &[Reg]) -> StackChecker {
+        StackChecker {
+            stop: true,
+            ..StackChecker::new(callee_saved)
+        }
+    }
+
+    /// Protect the addresses `range` from pushes.
+    pub fn protect(&mut self, range: RangeInclusive<u16>) {
+        for addr in range {
+            self.protected[addr as usize] = true;
+        }
+    }
+
+    /// Protect the instructions and initialized data of the sources of
+    /// `debug_info` from pushes, leaving space reserved by `.BLKW` writable.
+    pub fn add_debug_info(&mut self, debug_info: &DebugInfo) {
+        for (addr, line) in debug_info.iter() {
+            if line.kind != LineKind::Reserved {
+                self.protected[addr as usize] = true;
+            }
+        }
+    }
+
+    /// Violations, in the order they first happened. Every kind of
+    /// violation is reported once per address.
+    pub fn violations(&self) -> &[StackViolation] {
+        &self.violations
+    }
+
+    fn report(&mut self, pc: u16, kind: StackViolationKind) {
+        let reg = match kind {
+            StackViolationKind::CalleeSaved { reg, .. } => Some(reg),
+            _ => None,
+        };
+        if self.reported.insert((pc, mem::discriminant(&kind), reg)) {
+            let trace = self.frames.iter().map(|frame| frame.call_site).collect();
+            self.violations.push(StackViolation { pc, kind, trace });
+        }
+    }
+
+    /// Check the return to `target` from the innermost frame.
+    fn ret<IO: IoDevice, S: Storage>(&mut self, pc: u16, target: u16, lc3: &Lc3<IO, S>) {
+        let Some(frame) = self.frames.last() else {
+            return;
+        };
+
+        let mut violations = Vec::new();
+        if !self.frames.iter().any(|frame| frame.trap) {
+            let expected = frame.registers[Reg::R7 as usize];
+            if target != expected {
+                violations.push(StackViolationKind::ReturnAddress {
+                    expected,
+                    actual: target,
+                });
+            }
+            let (expected, actual) = (frame.registers[Reg::R6 as usize], lc3.registers.r6);
+            if actual != expected {
+                violations.push(StackViolationKind::Unbalanced { expected, actual });
+            }
+            for &reg in &self.callee_saved {
+                let (expected, actual) = (frame.registers[reg as usize], lc3.registers[reg]);
+                if actual != expected {
+                    violations.push(StackViolationKind::CalleeSaved {
+                        reg,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+        for kind in violations {
+            self.report(pc, kind);
+        }
+
+        // Unwind to the frame returned to, if any, or else just the innermost one.
+        let depth = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.registers[Reg::R7 as usize] == target)
+            .unwrap_or(self.frames.len() - 1);
+        self.frames.truncate(depth);
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Monitor<IO, S> for StackChecker {
+    fn executing(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) -> bool {
+        if self.stop && !self.violations.is_empty() {
+            return false;
+        }
+
+        // The machine restarted elsewhere, such as after booting.
+        if self.next.is_some_and(|next| next != pc) {
+            self.frames.clear();
+        }
+
+        match inst {
+            Instruction::Jmp { baser: Reg::R7 } => self.ret(pc, lc3.registers.r7, lc3),
+            Instruction::Str {
+                baser: Reg::R6,
+                offset6,
+                ..
+            } if !self.frames.iter().any(|frame| frame.trap) => {
+                let addr = lc3.registers.r6.wrapping_add(offset6);
+                if self.protected[addr as usize] {
+                    self.report(pc, StackViolationKind::Overflow { addr });
+                }
+            }
+            _ => (),
+        }
+        self.protected[pc as usize] = true;
+
+        !self.stop || self.violations.is_empty()
+    }
+
+    fn executed(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) {
+        let next = pc.wrapping_add(1);
+        self.next = Some(lc3.registers.pc);
+
+        let trap = match inst {
+            Instruction::Jsr { .. } | Instruction::Jsrr { .. } => false,
+            Instruction::Trap { .. } if lc3.registers.pc != next => true,
+            _ => return,
+        };
+        let mut registers = [0; 8];
+        for (i, value) in registers.iter_mut().enumerate() {
+            *value = lc3.registers[Reg::from_u16(i as u16)];
+        }
+        self.frames.push(Frame {
+            call_site: pc,
+            trap,
+            registers,
+        });
+    }
+}
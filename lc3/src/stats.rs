@@ -0,0 +1,284 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::io::{self, Write};
+
+use crate::memory::LEN;
+use crate::{Instruction, IoDevice, Lc3, Monitor, OpCode, Storage, SymbolTable};
+
+/// Words per region of [`Stats::write_summary`].
+const REGION: usize = 16;
+
+/// Regions listed by [`Stats::write_summary`].
+const HOT_REGIONS: usize = 10;
+
+/// Execution statistics: instruction fetches, reads and writes per
+/// address, executions per opcode, and branches taken.
+///
+/// Reads are those of `LD`, `LDI` (two per instruction), `LDR`, and of
+/// the trap vector table by traps dispatched through it. Writes are those
+/// of `ST`, `STI` and `STR`.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Headless, Lc3, OpCode, Stats, VirtualTrapVectorTable};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// lc3.memory.as_mut()[0x3000] = 0x2003; // LD R0, #3
+/// lc3.memory.as_mut()[0x3001] = 0x103F; // ADD R0, R0, #-1
+/// lc3.memory.as_mut()[0x3002] = 0x03FE; // BRp #-2
+/// lc3.memory.as_mut()[0x3003] = 0xF025; // HALT
+/// lc3.memory.as_mut()[0x3004] = 3;
+///
+/// let mut stats = Stats::new();
+/// lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut stats)
+///     .unwrap();
+/// assert_eq!(stats.instructions(), 8);
+/// assert_eq!(stats.opcode(OpCode::Add), 3);
+/// assert_eq!((stats.branches(), stats.branches_taken()), (3, 2));
+/// assert_eq!((stats.fetches(0x3001), stats.reads(0x3004)), (3, 1));
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Stats {
+    fetches: Box<[u64]>,
+    reads: Box<[u64]>,
+    writes: Box<[u64]>,
+    /// Executions per opcode, indexed by encoding.
+    opcodes: [u64; 16],
+    branches: u64,
+    branches_taken: u64,
+    /// Reads and writes of the instruction executing, counted once it executed.
+    pending: Vec<Access>,
+}
+
+/// A read or write of the instruction executing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Access {
+    Read(u16),
+    Write(u16),
+}
+
+/// Accesses to a region of memory, see [`Stats::regions`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct RegionStats {
+    /// Address of the first word of the region.
+    pub start: u16,
+    /// Address of the last word of the region.
+    pub end: u16,
+    /// Instructions fetched from the region.
+    pub fetches: u64,
+    /// Words read from the region.
+    pub reads: u64,
+    /// Words written to the region.
+    pub writes: u64,
+}
+
+impl RegionStats {
+    /// Fetches, reads and writes.
+    pub fn accesses(&self) -> u64 {
+        self.fetches + self.reads + self.writes
+    }
+}
+
+impl Stats {
+    /// Initialize empty statistics.
+    pub fn new() -> Stats {
+        Stats {
+            fetches: vec![0; LEN].into_boxed_slice(),
+            reads: vec![0; LEN].into_boxed_slice(),
+            writes: vec![0; LEN].into_boxed_slice(),
+            opcodes: [0; 16],
+            branches: 0,
+            branches_taken: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Number of instructions executed.
+    pub fn instructions(&self) -> u64 {
+        self.opcodes.iter().sum()
+    }
+
+    /// Number of instructions fetched from `addr`.
+    pub fn fetches(&self, addr: u16) -> u64 {
+        self.fetches[addr as usize]
+    }
+
+    /// Number of reads from `addr`.
+    pub fn reads(&self, addr: u16) -> u64 {
+        self.reads[addr as usize]
+    }
+
+    /// Number of writes to `addr`.
+    pub fn writes(&self, addr: u16) -> u64 {
+        self.writes[addr as usize]
+    }
+
+    /// Number of instructions executed with `opcode`.
+    pub fn opcode(&self, opcode: OpCode) -> u64 {
+        self.opcodes[opcode as usize]
+    }
+
+    /// Number of `BR` instructions executed, other than those that never branch.
+    pub fn branches(&self) -> u64 {
+        self.branches
+    }
+
+    /// Number of `BR` instructions executed that branched.
+    pub fn branches_taken(&self) -> u64 {
+        self.branches_taken
+    }
+
+    /// Accesses to the aligned regions of `size` words that were accessed
+    /// at all, most accessed first.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is not a power of two.
+    pub fn regions(&self, size: usize) -> Vec<RegionStats> {
+        assert!(size.is_power_of_two());
+        let sum = |counts: &[u64], start: usize| counts[start..][..size].iter().sum();
+        let mut regions: Vec<_> = (0..LEN)
+            .step_by(size)
+            .map(|start| RegionStats {
+                start: start as u16,
+                end: (start + size - 1) as u16,
+                fetches: sum(&self.fetches, start),
+                reads: sum(&self.reads, start),
+                writes: sum(&self.writes, start),
+            })
+            .filter(|x| x.accesses() != 0)
+            .collect();
+        regions.sort_by_key(|x| (u64::MAX - x.accesses(), x.start));
+        regions
+    }
+
+    /// Write a summary of the instruction mix, branches and hottest
+    /// regions of memory. Regions are labelled after `symbols`.
+    pub fn write_summary<W: Write>(&self, w: &mut W, symbols: &SymbolTable) -> io::Result<()> {
+        let instructions = self.instructions();
+        let percent = |n: u64, total: u64| 100.0 * n as f64 / total.max(1) as f64;
+
+        writeln!(w, "Instructions: {instructions}")?;
+        writeln!(w)?;
+        writeln!(w, "Instruction mix")?;
+        let mut opcodes: Vec<_> = OpCode::ALL
+            .into_iter()
+            .filter(|&x| self.opcode(x) != 0)
+            .collect();
+        opcodes.sort_by_key(|&x| (u64::MAX - self.opcode(x), x));
+        for opcode in opcodes {
+            let count = self.opcode(opcode);
+            let share = percent(count, instructions);
+            writeln!(w, "  {:<4} {count:>11} {share:6.2}%", opcode.to_string())?;
+        }
+
+        writeln!(w)?;
+        writeln!(
+            w,
+            "Branches: {}, taken {} ({:.2}%)",
+            self.branches,
+            self.branches_taken,
+            percent(self.branches_taken, self.branches),
+        )?;
+
+        writeln!(w)?;
+        writeln!(w, "Hot regions ({REGION} words)")?;
+        writeln!(w, "        fetches       reads      writes  region")?;
+        for x in self.regions(REGION).iter().take(HOT_REGIONS) {
+            writeln!(
+                w,
+                "    {:>11} {:>11} {:>11}  {}-x{:04X}",
+                x.fetches,
+                x.reads,
+                x.writes,
+                symbols.display(x.start),
+                x.end,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats::new()
+    }
+}
+
+impl<IO: IoDevice, S: Storage> Monitor<IO, S> for Stats {
+    fn executing(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) -> bool {
+        // Another monitor may still halt the machine before `inst` executes.
+        self.pending.clear();
+        let next = pc.wrapping_add(1);
+        match inst {
+            Instruction::Ld { pcoffset9, .. } => {
+                self.pending
+                    .push(Access::Read(next.wrapping_add(pcoffset9)));
+            }
+            Instruction::Ldi { pcoffset9, .. } => {
+                let addr = next.wrapping_add(pcoffset9);
+                self.pending.push(Access::Read(addr));
+                self.pending.push(Access::Read(lc3.memory.load(addr)));
+            }
+            Instruction::Ldr { baser, offset6, .. } => {
+                let addr = lc3.registers[baser].wrapping_add(offset6);
+                self.pending.push(Access::Read(addr));
+            }
+            Instruction::St { pcoffset9, .. } => {
+                self.pending
+                    .push(Access::Write(next.wrapping_add(pcoffset9)));
+            }
+            Instruction::Sti { pcoffset9, .. } => {
+                let addr = next.wrapping_add(pcoffset9);
+                self.pending.push(Access::Read(addr));
+                self.pending.push(Access::Write(lc3.memory.load(addr)));
+            }
+            Instruction::Str { baser, offset6, .. } => {
+                let addr = lc3.registers[baser].wrapping_add(offset6);
+                self.pending.push(Access::Write(addr));
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn executed(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) {
+        self.fetches[pc as usize] += 1;
+        self.opcodes[inst.opcode() as usize] += 1;
+        for access in self.pending.drain(..) {
+            match access {
+                Access::Read(addr) => self.reads[addr as usize] += 1,
+                Access::Write(addr) => self.writes[addr as usize] += 1,
+            }
+        }
+
+        match inst {
+            Instruction::Br { cc, .. } if cc.any() => {
+                // BR leaves the condition codes as they were when it executed.
+                self.branches += 1;
+                self.branches_taken += lc3.registers.cc.intersects(cc) as u64;
+            }
+            Instruction::Trap { trapvect8 } if lc3.registers.pc != pc.wrapping_add(1) => {
+                self.reads[trapvect8 as usize] += 1;
+            }
+            _ => (),
+        }
+    }
+}
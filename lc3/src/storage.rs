@@ -0,0 +1,229 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+/// Backing store of the words of [`Memory`][`crate::Memory`].
+///
+/// Storage is addressed by word, and holds at most `1 << 16` words. Arrays and
+/// slices of fewer words read as zero past [`Storage::len`], and ignore
+/// writes there, like unconnected memory. A machine whose storage doesn't
+/// reach [`IoDeviceRegister::Mcr`][`crate::IoDeviceRegister::Mcr`] is
+/// therefore always halted, but may still be stepped one instruction at a time.
+///
+/// Implemented for arrays and slices of words, mutable references to and, with
+/// the `std` feature enabled, boxes of any storage, and [`PagedStorage`].
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Lc3, Storage};
+/// # #[derive(Default)]
+/// # struct Null;
+/// # impl lc3::IoDevice for Null {
+/// #     type Error = ();
+/// #     fn read(&mut self, _: &mut [u8]) -> Result<usize, ()> { Ok(0) }
+/// #     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> { Ok(buf.len()) }
+/// #     fn flush(&mut self) -> Result<(), ()> { Ok(()) }
+/// # }
+/// let mut words = vec![0; 1 << 16];
+/// let mut lc3 = Lc3::with_storage(Null, words.as_mut_slice());
+/// lc3.memory.store(0x3000, 0xF025); // HALT
+/// drop(lc3);
+/// assert_eq!(words[0x3000], 0xF025);
+/// ```
+pub trait Storage {
+    /// Number of words in storage.
+    fn len(&self) -> usize;
+
+    /// True iff storage holds no words.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Word at `addr`.
+    fn load(&self, addr: u16) -> u16;
+
+    /// Set the word at `addr` to `value`.
+    fn store(&mut self, addr: u16, value: u16);
+}
+
+impl Storage for [u16] {
+    fn len(&self) -> usize {
+        <[u16]>::len(self)
+    }
+
+    fn load(&self, addr: u16) -> u16 {
+        self.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    fn store(&mut self, addr: u16, value: u16) {
+        if let Some(word) = self.get_mut(addr as usize) {
+            *word = value;
+        }
+    }
+}
+
+impl<const N: usize> Storage for [u16; N] {
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn load(&self, addr: u16) -> u16 {
+        self.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    fn store(&mut self, addr: u16, value: u16) {
+        if let Some(word) = self.get_mut(addr as usize) {
+            *word = value;
+        }
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for &mut S {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn load(&self, addr: u16) -> u16 {
+        (**self).load(addr)
+    }
+
+    fn store(&mut self, addr: u16, value: u16) {
+        (**self).store(addr, value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn load(&self, addr: u16) -> u16 {
+        (**self).load(addr)
+    }
+
+    fn store(&mut self, addr: u16, value: u16) {
+        (**self).store(addr, value)
+    }
+}
+
+/// Number of bits of an address indexing into a page.
+#[cfg(feature = "std")]
+const PAGE_BITS: u32 = 8;
+
+/// Number of words in a page of [`PagedStorage`].
+#[cfg(feature = "std")]
+const PAGE_LEN: usize = 1 << PAGE_BITS;
+
+/// Copy-on-write storage of `1 << 16` words in pages shared between clones.
+///
+/// Cloning copies only the page table. A page is copied the first time a
+/// clone writes to it while shared, and pages never written to aren't
+/// allocated at all, so thousands of machines forked from one loaded image
+/// cost little more than the memory their programs write.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{PagedStorage, Storage};
+/// let mut parent = PagedStorage::new();
+/// parent.store(0x3000, 0xF025);
+///
+/// let mut child = parent.clone();
+/// child.store(0x3000, 0xF021);
+/// assert_eq!(parent.load(0x3000), 0xF025);
+/// assert_eq!(child.load(0x3000), 0xF021);
+/// assert_eq!(parent.shared_pages(&child), 0);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct PagedStorage {
+    pages: Box<[Option<Arc<[u16; PAGE_LEN]>>]>,
+}
+
+#[cfg(feature = "std")]
+impl PagedStorage {
+    /// Initialize storage with all words zero.
+    pub fn new() -> PagedStorage {
+        PagedStorage {
+            pages: vec![None; (1 << 16) / PAGE_LEN].into_boxed_slice(),
+        }
+    }
+
+    /// Number of pages allocated.
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.iter().filter(|page| page.is_some()).count()
+    }
+
+    /// Number of allocated pages shared with `other`.
+    pub fn shared_pages(&self, other: &PagedStorage) -> usize {
+        self.pages
+            .iter()
+            .zip(other.pages.iter())
+            .filter(|(a, b)| matches!((a, b), (Some(a), Some(b)) if Arc::ptr_eq(a, b)))
+            .count()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for PagedStorage {
+    fn default() -> Self {
+        PagedStorage::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for PagedStorage {
+    fn eq(&self, other: &Self) -> bool {
+        const ZERO: [u16; PAGE_LEN] = [0; PAGE_LEN];
+        self.pages.iter().zip(other.pages.iter()).all(|(a, b)| {
+            let a = a.as_deref().unwrap_or(&ZERO);
+            let b = b.as_deref().unwrap_or(&ZERO);
+            a == b
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl Eq for PagedStorage {}
+
+#[cfg(feature = "std")]
+impl Storage for PagedStorage {
+    fn len(&self) -> usize {
+        1 << 16
+    }
+
+    fn load(&self, addr: u16) -> u16 {
+        match &self.pages[(addr >> PAGE_BITS) as usize] {
+            Some(page) => page[addr as usize % PAGE_LEN],
+            None => 0,
+        }
+    }
+
+    fn store(&mut self, addr: u16, value: u16) {
+        let page = &mut self.pages[(addr >> PAGE_BITS) as usize];
+        if page.is_none() && value == 0 {
+            return;
+        }
+        let page = page.get_or_insert_with(|| Arc::new([0; PAGE_LEN]));
+        Arc::make_mut(page)[addr as usize % PAGE_LEN] = value;
+    }
+}
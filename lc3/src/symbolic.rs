@@ -0,0 +1,811 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use crate::trap::{HALT_MESSAGE, IN_PROMPT, UNDEFINED_TRAP_MESSAGE};
+use crate::{CondCodes, Instruction, IoDeviceRegister, Reg, Registers, Storage, TrapCode};
+
+/// Default maximum number of instructions executed per path.
+const DEFAULT_STEPS: u64 = 100_000;
+
+/// Default maximum number of paths explored.
+const DEFAULT_PATHS: usize = 1000;
+
+/// Default maximum number of characters read per path.
+const DEFAULT_INPUTS: usize = 8;
+
+/// Assignments tried by the solver before giving up on a path.
+const SEARCH_LIMIT: u64 = 1 << 20;
+
+/// Symbolic execution of a program over all of its inputs.
+///
+/// Registers and memory hold expressions of the characters read by
+/// `GETC`, `IN` and from [`IoDeviceRegister::Kbdr`], each one unknown
+/// within an alphabet. Execution forks on every `BR` whose condition
+/// depends on the input, and each path is given a concrete input taking
+/// it, found by a brute-force solver over the alphabet. Traps are
+/// serviced as by [`VirtualTrapVectorTable`][`crate::VirtualTrapVectorTable`],
+/// so running the program on that input reproduces the path.
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{Explorer, Goal, Headless, Lc3, PathEnd};
+/// let mut lc3 = Lc3::new(Headless::default());
+/// let words = [
+///     0xF020, // GETC
+///     0x2205, // LD R1, #5
+///     0x1001, // ADD R0, R0, R1
+///     0x0401, // BRz #1
+///     0xF025, // HALT
+///     0xF021, // OUT
+///     0xF025, // HALT
+///     0xFFBF, // .FILL #-65
+/// ];
+/// lc3.memory.as_mut()[0x3000..][..words.len()].copy_from_slice(&words);
+/// lc3.registers.pc = 0x3000;
+///
+/// let explorer = Explorer::new();
+/// let paths = explorer.explore(lc3.memory.storage(), lc3.registers);
+/// assert_eq!(paths.len(), 2);
+///
+/// let path = explorer
+///     .find(lc3.memory.storage(), lc3.registers, &Goal::Reach(0x3005))
+///     .unwrap();
+/// assert_eq!((path.input, path.end), (b"A".to_vec(), PathEnd::Reached));
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Explorer {
+    max_steps: u64,
+    max_paths: usize,
+    max_inputs: usize,
+    alphabet: Vec<u8>,
+}
+
+/// What [`Explorer::find`] looks for.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Goal {
+    /// Fetching an instruction from the address.
+    Reach(u16),
+    /// Halting after writing exactly the output, not counting the
+    /// message written by `HALT`.
+    Output(Vec<u8>),
+}
+
+/// A path through a program, with an input taking it.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct ExecutionPath {
+    /// Characters read along the path.
+    pub input: Vec<u8>,
+    /// Characters written along the path, given `input`.
+    pub output: Vec<u8>,
+    /// Instructions executed.
+    pub steps: u64,
+    /// How the path ended.
+    pub end: PathEnd,
+}
+
+/// How an [`ExecutionPath`] ended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum PathEnd {
+    /// The machine halted.
+    Halted,
+    /// The address of [`Goal::Reach`] was reached.
+    Reached,
+    /// The maximum number of instructions was executed.
+    StepLimit,
+    /// The maximum number of characters was read.
+    InputLimit,
+    /// The instruction at the address cannot be executed symbolically:
+    /// `RTI`, the reserved opcode, or an instruction jumping to, loading
+    /// from or storing to an address that depends on the input.
+    Unsupported { pc: u16 },
+}
+
+/// A 16-bit expression of the input.
+#[derive(PartialEq, Eq, Debug)]
+enum Expr {
+    Const(u16),
+    /// The character read with the index.
+    Input(usize),
+    Add(Value, Value),
+    And(Value, Value),
+    Not(Value),
+}
+
+type Value = Rc<Expr>;
+
+/// A condition on the input taking a path.
+#[derive(Clone, Debug)]
+enum Constraint {
+    /// The condition codes of `value` intersect `cc` iff `holds`.
+    Sign {
+        value: Value,
+        cc: CondCodes,
+        holds: bool,
+    },
+    /// `value` is `expected`.
+    Equals { value: Value, expected: u16 },
+}
+
+/// Condition codes, known or set by a value.
+#[derive(Clone, Debug)]
+enum Cc {
+    Known(CondCodes),
+    Sign(Value),
+}
+
+/// The machine along a path.
+#[derive(Clone, Debug)]
+struct State {
+    pc: u16,
+    registers: [Value; 8],
+    cc: Cc,
+    /// Memory written along the path.
+    written: BTreeMap<u16, Value>,
+    inputs: usize,
+    output: Vec<Value>,
+    constraints: Vec<Constraint>,
+    steps: u64,
+}
+
+/// What executing an instruction led to.
+enum Step {
+    Continue,
+    /// A `BR` to the address, taken iff the condition codes of the value intersect the codes.
+    Fork(Value, CondCodes, u16),
+    End(PathEnd),
+    /// The path cannot lead to the goal.
+    Abandon,
+}
+
+impl Explorer {
+    /// Initialize an explorer of at most 1000 paths, each executing at
+    /// most 100000 instructions and reading at most 8 characters, all
+    /// printable ASCII or newlines.
+    pub fn new() -> Explorer {
+        let mut alphabet: Vec<u8> = (b' '..=b'~').collect();
+        alphabet.push(b'\n');
+        Explorer {
+            max_steps: DEFAULT_STEPS,
+            max_paths: DEFAULT_PATHS,
+            max_inputs: DEFAULT_INPUTS,
+            alphabet,
+        }
+    }
+
+    /// Execute at most `steps` instructions per path.
+    pub fn max_steps(&mut self, steps: u64) -> &mut Explorer {
+        self.max_steps = steps;
+        self
+    }
+
+    /// Explore at most `paths` paths.
+    pub fn max_paths(&mut self, paths: usize) -> &mut Explorer {
+        self.max_paths = paths;
+        self
+    }
+
+    /// Read at most `inputs` characters per path.
+    pub fn max_inputs(&mut self, inputs: usize) -> &mut Explorer {
+        self.max_inputs = inputs;
+        self
+    }
+
+    /// Read characters from `alphabet`, preferring those listed first.
+    pub fn alphabet(&mut self, alphabet: &[u8]) -> &mut Explorer {
+        self.alphabet = alphabet.to_vec();
+        self
+    }
+
+    /// Explore the paths of the program in `memory`, starting from `registers`.
+    pub fn explore<S: Storage + ?Sized>(
+        &self,
+        memory: &S,
+        registers: Registers,
+    ) -> Vec<ExecutionPath> {
+        let mut paths = Vec::new();
+        self.search(memory, registers, None, |path| {
+            paths.push(path);
+            false
+        });
+        paths
+    }
+
+    /// Find a path of the program in `memory`, starting from `registers`,
+    /// that leads to `goal`.
+    pub fn find<S: Storage + ?Sized>(
+        &self,
+        memory: &S,
+        registers: Registers,
+        goal: &Goal,
+    ) -> Option<ExecutionPath> {
+        let mut found = None;
+        self.search(memory, registers, Some(goal), |path| {
+            let done = match goal {
+                Goal::Reach(_) => path.end == PathEnd::Reached,
+                Goal::Output(_) => path.end == PathEnd::Halted,
+            };
+            if done {
+                found = Some(path);
+            }
+            done
+        });
+        found
+    }
+
+    /// Explore paths depth first, passing each one to `visit` until it returns true.
+    fn search<S, F>(&self, memory: &S, registers: Registers, goal: Option<&Goal>, mut visit: F)
+    where
+        S: Storage + ?Sized,
+        F: FnMut(ExecutionPath) -> bool,
+    {
+        let r = registers;
+        let mut pending = vec![State {
+            pc: r.pc,
+            registers: [r.r0, r.r1, r.r2, r.r3, r.r4, r.r5, r.r6, r.r7].map(constant),
+            cc: Cc::Known(r.cc),
+            written: BTreeMap::new(),
+            inputs: 0,
+            output: Vec::new(),
+            constraints: Vec::new(),
+            steps: 0,
+        }];
+
+        let mut paths = 0;
+        while let Some(mut state) = pending.pop() {
+            let end = loop {
+                match self.step(&mut state, memory, goal) {
+                    Step::Continue => continue,
+                    Step::Fork(value, cc, target) => {
+                        let mut taken = state.clone();
+                        taken.pc = target;
+                        // Paths falling through are explored first.
+                        for (mut state, holds) in [(taken, true), (state, false)] {
+                            state.constraints.push(Constraint::Sign {
+                                value: value.clone(),
+                                cc,
+                                holds,
+                            });
+                            if self.solve(&state).is_some() {
+                                pending.push(state);
+                            }
+                        }
+                        break None;
+                    }
+                    Step::End(end) => break Some((state, end)),
+                    Step::Abandon => break None,
+                }
+            };
+            let Some((state, end)) = end else {
+                continue;
+            };
+            let Some(model) = self.solve(&state) else {
+                continue;
+            };
+
+            paths += 1;
+            let path = ExecutionPath {
+                input: model.iter().map(|&x| x as u8).collect(),
+                output: state.output.iter().map(|x| x.eval(&model) as u8).collect(),
+                steps: state.steps,
+                end,
+            };
+            if visit(path) || paths >= self.max_paths {
+                return;
+            }
+        }
+    }
+
+    /// Execute the instruction at the PC of `state`.
+    fn step<S: Storage + ?Sized>(
+        &self,
+        state: &mut State,
+        memory: &S,
+        goal: Option<&Goal>,
+    ) -> Step {
+        let pc = state.pc;
+        if goal == Some(&Goal::Reach(pc)) {
+            return Step::End(PathEnd::Reached);
+        }
+        if state.steps >= self.max_steps {
+            return Step::End(PathEnd::StepLimit);
+        }
+        let unsupported = Step::End(PathEnd::Unsupported { pc });
+        let Some(word) = state.load(memory, pc).constant() else {
+            return unsupported;
+        };
+        state.steps += 1;
+        state.pc = pc.wrapping_add(1);
+
+        let inst = Instruction::decode(word);
+        match inst {
+            Instruction::Add { dr, sr1, sr2 } => {
+                let value = add(state.reg(sr1), state.reg(sr2));
+                state.set(dr, value);
+            }
+            Instruction::AddImm { dr, sr1, imm5 } => {
+                let value = add(state.reg(sr1), &constant(imm5));
+                state.set(dr, value);
+            }
+            Instruction::And { dr, sr1, sr2 } => {
+                let value = and(state.reg(sr1), state.reg(sr2));
+                state.set(dr, value);
+            }
+            Instruction::AndImm { dr, sr1, imm5 } => {
+                let value = and(state.reg(sr1), &constant(imm5));
+                state.set(dr, value);
+            }
+            Instruction::Not { dr, sr } => {
+                let value = not(state.reg(sr));
+                state.set(dr, value);
+            }
+            Instruction::Br { cc, .. } => {
+                let target = inst.target(pc).unwrap();
+                let taken = match &state.cc {
+                    Cc::Known(known) => known.intersects(cc),
+                    Cc::Sign(value) => match value.constant() {
+                        Some(value) => CondCodes::from_signum(value).intersects(cc),
+                        None => return Step::Fork(value.clone(), cc, target),
+                    },
+                };
+                if taken {
+                    state.pc = target;
+                }
+            }
+            Instruction::Jmp { baser } => match state.reg(baser).constant() {
+                Some(target) => state.pc = target,
+                None => return unsupported,
+            },
+            Instruction::Jsr { .. } => {
+                state.registers[Reg::R7 as usize] = constant(state.pc);
+                state.pc = inst.target(pc).unwrap();
+            }
+            Instruction::Jsrr { baser } => {
+                let Some(target) = state.reg(baser).constant() else {
+                    return unsupported;
+                };
+                state.registers[Reg::R7 as usize] = constant(state.pc);
+                state.pc = target;
+            }
+            Instruction::Ld { dr, .. }
+            | Instruction::Ldi { dr, .. }
+            | Instruction::Ldr { dr, .. } => {
+                let value = state
+                    .address(memory, inst, pc, self.max_inputs)
+                    .and_then(|addr| state.read(memory, addr, self.max_inputs));
+                match value {
+                    Ok(value) => state.set(dr, value),
+                    Err(end) => return Step::End(end),
+                }
+            }
+            Instruction::Lea { dr, .. } => state.set(dr, constant(inst.target(pc).unwrap())),
+            Instruction::St { sr, .. }
+            | Instruction::Sti { sr, .. }
+            | Instruction::Str { sr, .. } => {
+                let addr = match state.address(memory, inst, pc, self.max_inputs) {
+                    Ok(addr) => addr,
+                    Err(end) => return Step::End(end),
+                };
+                let value = state.reg(sr).clone();
+                match IoDeviceRegister::from_u16(addr) {
+                    Some(IoDeviceRegister::Ddr) => return state.write(&value, goal),
+                    Some(IoDeviceRegister::Mcr) => {
+                        state.written.insert(addr, value.clone());
+                        match value.constant() {
+                            Some(value) if value & IoDeviceRegister::STATUS_ACCEPT == 0 => {
+                                return state.halt(goal);
+                            }
+                            Some(_) => {}
+                            None => return unsupported,
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        state.written.insert(addr, value);
+                    }
+                }
+            }
+            Instruction::Trap { trapvect8 } => {
+                state.registers[Reg::R7 as usize] = constant(state.pc);
+                return self.trap(state, memory, trapvect8, goal);
+            }
+            Instruction::Rti | Instruction::Res => return unsupported,
+        }
+        Step::Continue
+    }
+
+    /// Service the trap `vector` as [`VirtualTrapVectorTable`][`crate::VirtualTrapVectorTable`] does.
+    fn trap<S: Storage + ?Sized>(
+        &self,
+        state: &mut State,
+        memory: &S,
+        vector: u8,
+        goal: Option<&Goal>,
+    ) -> Step {
+        let pc = state.pc.wrapping_sub(1);
+        let Some(trapcode) = TrapCode::from_u16(vector as u16) else {
+            if let Step::Abandon = state.write_all(UNDEFINED_TRAP_MESSAGE, goal) {
+                return Step::Abandon;
+            }
+            return self.trap(state, memory, TrapCode::Halt as u8, goal);
+        };
+        match trapcode {
+            TrapCode::Getc => {
+                let Some(value) = state.input(self.max_inputs) else {
+                    return Step::End(PathEnd::InputLimit);
+                };
+                state.set(Reg::R0, value);
+            }
+            TrapCode::Out => {
+                state.cc = Cc::Sign(state.reg(Reg::R1).clone());
+                let value = state.reg(Reg::R0).clone();
+                return state.write(&value, goal);
+            }
+            TrapCode::Puts | TrapCode::PutSp => {
+                let Some(addr) = state.reg(Reg::R0).constant() else {
+                    return Step::End(PathEnd::Unsupported { pc });
+                };
+                let packed = matches!(trapcode, TrapCode::PutSp);
+                for offset in 0..=u16::MAX {
+                    let word = match state.read(memory, addr.wrapping_add(offset), self.max_inputs)
+                    {
+                        Ok(value) => value.constant(),
+                        Err(end) => return Step::End(end),
+                    };
+                    let Some(word) = word else {
+                        return Step::End(PathEnd::Unsupported { pc });
+                    };
+                    let chars = word.to_le_bytes();
+                    let (len, last) = match (packed, chars) {
+                        (false, _) => (usize::from(word != 0), word == 0),
+                        (true, [0, _]) => (0, true),
+                        (true, [_, 0]) => (1, true),
+                        (true, _) => (2, false),
+                    };
+                    if let Step::Abandon = state.write_all(&chars[..len], goal) {
+                        return Step::Abandon;
+                    }
+                    if last {
+                        break;
+                    }
+                }
+                state.cc = Cc::Sign(state.reg(Reg::R7).clone());
+            }
+            TrapCode::In => {
+                if let Step::Abandon = state.write_all(IN_PROMPT, goal) {
+                    return Step::Abandon;
+                }
+                let Some(value) = state.input(self.max_inputs) else {
+                    return Step::End(PathEnd::InputLimit);
+                };
+                state.registers[Reg::R0 as usize] = value.clone();
+                state.cc = Cc::Sign(state.reg(Reg::R7).clone());
+                if let Step::Abandon = state.write(&value, goal) {
+                    return Step::Abandon;
+                }
+                return state.write_all(b"\n", goal);
+            }
+            TrapCode::Halt => {
+                let end = state.halt(goal);
+                state.write_all(HALT_MESSAGE, None);
+                return end;
+            }
+        }
+        Step::Continue
+    }
+
+    /// Values of the inputs read by `state`, satisfying its constraints.
+    fn solve(&self, state: &State) -> Option<Vec<u16>> {
+        let count = state.inputs;
+        let first = match self.alphabet.first() {
+            Some(&first) => first as u16,
+            None if count == 0 => 0,
+            None => return None,
+        };
+        let mut model = vec![first; count];
+
+        // Inputs are independent unless constrained together, and each
+        // group of dependent inputs is solved on its own.
+        let inputs: Vec<BTreeSet<usize>> = state
+            .constraints
+            .iter()
+            .map(|x| {
+                let mut inputs = BTreeSet::new();
+                x.value().inputs(&mut inputs);
+                inputs
+            })
+            .collect();
+        let mut groups: Vec<usize> = (0..count).collect();
+        fn root(groups: &mut [usize], mut i: usize) -> usize {
+            while groups[i] != i {
+                groups[i] = groups[groups[i]];
+                i = groups[i];
+            }
+            i
+        }
+        for set in &inputs {
+            let mut set = set.iter();
+            if let Some(&first) = set.next() {
+                for &other in set {
+                    let (a, b) = (root(&mut groups, first), root(&mut groups, other));
+                    groups[a] = b;
+                }
+            }
+        }
+
+        let mut levels: BTreeMap<usize, (Vec<usize>, Vec<Vec<&Constraint>>)> = BTreeMap::new();
+        for i in 0..count {
+            let group = root(&mut groups, i);
+            let (members, constraints) = levels.entry(group).or_default();
+            members.push(i);
+            constraints.push(Vec::new());
+        }
+        for (constraint, set) in state.constraints.iter().zip(&inputs) {
+            let Some(&last) = set.last() else {
+                if !constraint.check(&model) {
+                    return None;
+                }
+                continue;
+            };
+            let (members, constraints) = levels.get_mut(&root(&mut groups, last)).unwrap();
+            let level = members.binary_search(&last).unwrap();
+            constraints[level].push(constraint);
+        }
+
+        let mut budget = SEARCH_LIMIT;
+        for (members, constraints) in levels.values() {
+            if constraints.iter().any(|x| !x.is_empty())
+                && !self.assign(0, members, constraints, &mut model, &mut budget)
+            {
+                return None;
+            }
+        }
+        Some(model)
+    }
+
+    /// Assign the inputs `members[level..]` of `model` by backtracking,
+    /// checking the `constraints` of each level once its input is assigned.
+    fn assign(
+        &self,
+        level: usize,
+        members: &[usize],
+        constraints: &[Vec<&Constraint>],
+        model: &mut [u16],
+        budget: &mut u64,
+    ) -> bool {
+        if level == members.len() {
+            return true;
+        }
+        for &value in &self.alphabet {
+            if *budget == 0 {
+                return false;
+            }
+            *budget -= 1;
+            model[members[level]] = value as u16;
+            if constraints[level].iter().all(|x| x.check(model))
+                && self.assign(level + 1, members, constraints, model, budget)
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl Default for Explorer {
+    fn default() -> Self {
+        Explorer::new()
+    }
+}
+
+impl State {
+    fn reg(&self, reg: Reg) -> &Value {
+        &self.registers[reg as usize]
+    }
+
+    /// Set `reg` to `value`, and the condition codes after it.
+    fn set(&mut self, reg: Reg, value: Value) {
+        self.cc = Cc::Sign(value.clone());
+        self.registers[reg as usize] = value;
+    }
+
+    /// The word at `addr`, as last written along the path or in `memory`.
+    fn load<S: Storage + ?Sized>(&self, memory: &S, addr: u16) -> Value {
+        match self.written.get(&addr) {
+            Some(value) => value.clone(),
+            None => constant(memory.load(addr)),
+        }
+    }
+
+    /// Read `addr` as [`crate::Memory::read`] does, unless that would
+    /// read more than `max_inputs` characters.
+    fn read<S: Storage + ?Sized>(
+        &mut self,
+        memory: &S,
+        addr: u16,
+        max_inputs: usize,
+    ) -> Result<Value, PathEnd> {
+        Ok(match IoDeviceRegister::from_u16(addr) {
+            Some(IoDeviceRegister::Kbsr) | Some(IoDeviceRegister::Dsr) => {
+                constant(IoDeviceRegister::STATUS_ACCEPT)
+            }
+            Some(IoDeviceRegister::Kbdr) => {
+                return self.input(max_inputs).ok_or(PathEnd::InputLimit);
+            }
+            Some(IoDeviceRegister::Ddr) => constant(IoDeviceRegister::STATUS_DECLINE),
+            _ => self.load(memory, addr),
+        })
+    }
+
+    /// Address loaded from or stored to by `inst` at `pc`, or how the
+    /// path ends if it is not known.
+    fn address<S: Storage + ?Sized>(
+        &mut self,
+        memory: &S,
+        inst: Instruction,
+        pc: u16,
+        max_inputs: usize,
+    ) -> Result<u16, PathEnd> {
+        let addr = match inst {
+            Instruction::Ldi { .. } | Instruction::Sti { .. } => {
+                self.read(memory, inst.target(pc).unwrap(), max_inputs)?
+            }
+            Instruction::Ldr { baser, offset6, .. } | Instruction::Str { baser, offset6, .. } => {
+                add(self.reg(baser), &constant(offset6))
+            }
+            _ => constant(inst.target(pc).unwrap()),
+        };
+        addr.constant().ok_or(PathEnd::Unsupported { pc })
+    }
+
+    /// The next character of input, unless `max_inputs` were read.
+    fn input(&mut self, max_inputs: usize) -> Option<Value> {
+        if self.inputs >= max_inputs {
+            return None;
+        }
+        self.inputs += 1;
+        Some(Rc::new(Expr::Input(self.inputs - 1)))
+    }
+
+    /// Write the low byte of `value`, abandoning the path if it can no
+    /// longer produce the output of `goal`.
+    fn write(&mut self, value: &Value, goal: Option<&Goal>) -> Step {
+        let value = and(value, &constant(0x00FF));
+        if let Some(Goal::Output(expected)) = goal {
+            let Some(&expected) = expected.get(self.output.len()) else {
+                return Step::Abandon;
+            };
+            match value.constant() {
+                Some(byte) if byte != expected as u16 => return Step::Abandon,
+                Some(_) => {}
+                None => self.constraints.push(Constraint::Equals {
+                    value: value.clone(),
+                    expected: expected as u16,
+                }),
+            }
+        }
+        self.output.push(value);
+        Step::Continue
+    }
+
+    /// Halt, abandoning the path if it did not produce all of the output of `goal`.
+    fn halt(&self, goal: Option<&Goal>) -> Step {
+        match goal {
+            Some(Goal::Output(expected)) if self.output.len() != expected.len() => Step::Abandon,
+            _ => Step::End(PathEnd::Halted),
+        }
+    }
+
+    fn write_all(&mut self, bytes: &[u8], goal: Option<&Goal>) -> Step {
+        for &byte in bytes {
+            if let Step::Abandon = self.write(&constant(byte as u16), goal) {
+                return Step::Abandon;
+            }
+        }
+        Step::Continue
+    }
+}
+
+impl Expr {
+    fn constant(&self) -> Option<u16> {
+        match *self {
+            Expr::Const(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Value of the expression given the values of all `inputs`.
+    fn eval(&self, inputs: &[u16]) -> u16 {
+        match self {
+            Expr::Const(value) => *value,
+            Expr::Input(i) => inputs[*i],
+            Expr::Add(a, b) => a.eval(inputs).wrapping_add(b.eval(inputs)),
+            Expr::And(a, b) => a.eval(inputs) & b.eval(inputs),
+            Expr::Not(a) => !a.eval(inputs),
+        }
+    }
+
+    /// Add the indices of the inputs the expression depends on to `inputs`.
+    fn inputs(&self, inputs: &mut BTreeSet<usize>) {
+        match self {
+            Expr::Const(_) => {}
+            Expr::Input(i) => {
+                inputs.insert(*i);
+            }
+            Expr::Add(a, b) | Expr::And(a, b) => {
+                a.inputs(inputs);
+                b.inputs(inputs);
+            }
+            Expr::Not(a) => a.inputs(inputs),
+        }
+    }
+}
+
+impl Constraint {
+    fn value(&self) -> &Value {
+        match self {
+            Constraint::Sign { value, .. } | Constraint::Equals { value, .. } => value,
+        }
+    }
+
+    /// True iff the constraint holds given the values of all `inputs`.
+    fn check(&self, inputs: &[u16]) -> bool {
+        match self {
+            Constraint::Sign { value, cc, holds } => {
+                CondCodes::from_signum(value.eval(inputs)).intersects(*cc) == *holds
+            }
+            Constraint::Equals { value, expected } => value.eval(inputs) == *expected,
+        }
+    }
+}
+
+fn constant(value: u16) -> Value {
+    Rc::new(Expr::Const(value))
+}
+
+fn add(a: &Value, b: &Value) -> Value {
+    match (&**a, &**b) {
+        (&Expr::Const(x), &Expr::Const(y)) => constant(x.wrapping_add(y)),
+        (_, Expr::Const(0)) => a.clone(),
+        (Expr::Const(0), _) => b.clone(),
+        _ => Rc::new(Expr::Add(a.clone(), b.clone())),
+    }
+}
+
+fn and(a: &Value, b: &Value) -> Value {
+    match (&**a, &**b) {
+        (&Expr::Const(x), &Expr::Const(y)) => constant(x & y),
+        (_, Expr::Const(0)) | (Expr::Const(0), _) => constant(0),
+        (_, Expr::Const(0xFFFF)) => a.clone(),
+        (Expr::Const(0xFFFF), _) => b.clone(),
+        // Characters read are bytes.
+        (Expr::Input(_), &Expr::Const(mask)) if mask & 0x00FF == 0x00FF => a.clone(),
+        _ => Rc::new(Expr::And(a.clone(), b.clone())),
+    }
+}
+
+fn not(a: &Value) -> Value {
+    match &**a {
+        &Expr::Const(x) => constant(!x),
+        Expr::Not(x) => x.clone(),
+        _ => Rc::new(Expr::Not(a.clone())),
+    }
+}
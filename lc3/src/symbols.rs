@@ -0,0 +1,256 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/// A symbol table mapping labels to addresses.
+///
+/// Symbol tables are read from the `.sym` files emitted by LC-3 assemblers:
+///
+/// ```text
+/// // Symbol table
+/// // Scope level 0:
+/// //      Symbol Name       Page Address
+/// //      ----------------  ------------
+/// //      LOOP              3004
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::SymbolTable;
+/// let mut symbols = SymbolTable::parse("//\tLOOP   3004\n//\tARRAY  3010\n").unwrap();
+/// symbols.add_region(0x3000..0x3020);
+/// assert_eq!(symbols.get("LOOP"), Some(0x3004));
+/// assert_eq!(symbols.get("array"), Some(0x3010));
+/// assert_eq!(symbols.locate(0x3012), Some(("ARRAY", 2)));
+/// assert_eq!(symbols.locate(0x3000), None);
+/// assert_eq!(symbols.locate(0x3020), None);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SymbolTable {
+    by_name: BTreeMap<String, u16>,
+    by_addr: BTreeMap<u16, String>,
+    /// Memory addresses of the images labels are in, see [`Self::add_region`].
+    regions: Vec<Range<usize>>,
+}
+
+/// A label defined twice with different addresses, see [`SymbolTable::merge`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Conflict {
+    /// The conflicting label.
+    pub name: String,
+    /// Address the table already had for the label, and kept.
+    pub kept: u16,
+    /// Address the label was redefined to, and ignored.
+    pub ignored: u16,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "symbol '{}' redefined as x{:04X}, keeping x{:04X}",
+            self.name, self.ignored, self.kept
+        )
+    }
+}
+
+/// A line of a `.sym` file that neither defines a symbol nor is part of
+/// the header, see [`SymbolTable::parse`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct SymbolError {
+    /// Number of the line, starting at 1.
+    pub line: usize,
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: expected a label and a hexadecimal address.",
+            self.line
+        )
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl SymbolTable {
+    /// Initialize an empty symbol table.
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Parse the contents of a `.sym` file.
+    ///
+    /// Every line with a label followed by a hexadecimal address, optionally
+    /// prefixed with `//` and `x`, defines a symbol. Blank lines and the
+    /// header written by assemblers, its title, scope and column headings,
+    /// are skipped; any other line is an error. Later definitions of a label
+    /// replace earlier ones.
+    pub fn parse(text: &str) -> Result<SymbolTable, SymbolError> {
+        let mut symbols = SymbolTable::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim_start().trim_start_matches('/').trim();
+            if line.is_empty() || is_header(line) {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(addr), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(SymbolError { line: n + 1 });
+            };
+            let addr = addr
+                .strip_prefix('x')
+                .or_else(|| addr.strip_prefix('X'))
+                .unwrap_or(addr);
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| SymbolError { line: n + 1 })?;
+            symbols.insert(name, addr);
+        }
+        Ok(symbols)
+    }
+
+    /// Define `name` at `addr`, returning the previous address of `name`.
+    pub fn insert(&mut self, name: &str, addr: u16) -> Option<u16> {
+        let prev = self.by_name.insert(name.into(), addr);
+        if let Some(prev) = prev {
+            if self.by_addr.get(&prev).is_some_and(|x| x == name) {
+                self.by_addr.remove(&prev);
+                if let Some((other, _)) = self.by_name.iter().find(|(_, &x)| x == prev) {
+                    self.by_addr.insert(prev, other.clone());
+                }
+            }
+        }
+        self.by_addr.entry(addr).or_insert_with(|| name.into());
+        prev
+    }
+
+    /// Consider the memory addresses `region` loaded by one image, so that
+    /// labels in it locate addresses in it only, see [`Self::locate`].
+    pub fn add_region(&mut self, region: Range<usize>) {
+        self.regions.push(region);
+    }
+
+    /// Add all symbols and regions of `other`, returning the labels defined in both
+    /// tables with different addresses. Conflicting labels keep their
+    /// address in `self`.
+    pub fn merge(&mut self, other: SymbolTable) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        self.regions.extend(other.regions);
+        for (name, addr) in other.by_name {
+            match self.by_name.get(&name) {
+                Some(&kept) if kept != addr => conflicts.push(Conflict {
+                    name,
+                    kept,
+                    ignored: addr,
+                }),
+                Some(_) => (),
+                None => {
+                    self.insert(&name, addr);
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Address of the label `name`, matched case-insensitively if there
+    /// is no exact match.
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied().or_else(|| {
+            self.by_name
+                .iter()
+                .find(|(x, _)| x.eq_ignore_ascii_case(name))
+                .map(|(_, &addr)| addr)
+        })
+    }
+
+    /// Label defined exactly at `addr`.
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.by_addr.get(&addr).map(String::as_str)
+    }
+
+    /// Nearest label at or before `addr`, and the offset of `addr` from it.
+    ///
+    /// A label in a region added by [`Self::add_region`] locates only
+    /// addresses in the same region. Any other label locates addresses
+    /// up to the next label, and the last label only its own address.
+    pub fn locate(&self, addr: u16) -> Option<(&str, u16)> {
+        let (&base, name) = self.by_addr.range(..=addr).next_back()?;
+        let located = match self.regions.iter().find(|x| x.contains(&(base as usize))) {
+            Some(region) => region.contains(&(addr as usize)),
+            None => base == addr || self.by_addr.range(addr..).next().is_some(),
+        };
+        located.then(|| (name.as_str(), addr - base))
+    }
+
+    /// Iterate over all symbols in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        let mut symbols: Vec<_> = self
+            .by_name
+            .iter()
+            .map(|(name, &addr)| (name.as_str(), addr))
+            .collect();
+        symbols.sort_by_key(|&(name, addr)| (addr, name));
+        symbols.into_iter()
+    }
+
+    /// Number of symbols in the table.
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    /// True iff the table has no symbols.
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// Display `addr` as `x3006 <LOOP+2>`, or just `x3006` without a label locating it.
+    pub fn display(&self, addr: u16) -> impl fmt::Display + '_ {
+        DisplayAddr {
+            addr,
+            symbols: self,
+        }
+    }
+}
+
+/// True iff `line`, stripped of `//`, is part of the header of a `.sym` file.
+fn is_header(line: &str) -> bool {
+    ["Symbol table", "Scope level", "Symbol Name"]
+        .iter()
+        .any(|x| line.starts_with(x))
+        || line.chars().all(|c| c == '-' || c.is_whitespace())
+}
+
+struct DisplayAddr<'a> {
+    addr: u16,
+    symbols: &'a SymbolTable,
+}
+
+impl fmt::Display for DisplayAddr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "x{:04X}", self.addr)?;
+        match self.symbols.locate(self.addr) {
+            Some((name, 0)) => write!(f, " <{name}>"),
+            Some((name, offset)) => write!(f, " <{name}+{offset}>"),
+            None => Ok(()),
+        }
+    }
+}
@@ -24,10 +24,14 @@ use crate::IoDevice;
 
 /// [termios(3)] [`IoDevice`][`crate::IoDevice`].
 ///
+/// Echo and canonical mode are turned off while the device lives, if
+/// output is a terminal.
+///
 /// [termios(3)]: https://man7.org/linux/man-pages/man3/termios.3.html
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Termios<W: Write + AsFd = Stdout, R: Read = Stdin> {
-    prev_ios: libc::termios,
+    /// Attributes of the terminal to restore, unless output isn't one.
+    prev_ios: Option<libc::termios>,
     output: W,
     input: R,
 }
@@ -40,10 +44,16 @@ impl Termios {
 
 impl<W: Write + AsFd, R: Read> Termios<W, R> {
     pub fn from(output: W, input: R) -> io::Result<Termios<W, R>> {
-        let mut ios = get_terminal_attr(output.as_fd())?;
-        let prev_ios = ios;
-        ios.c_lflag &= !libc::ICANON & !libc::ECHO;
-        set_terminal_attr(output.as_fd(), libc::TCSAFLUSH, &ios)?;
+        let prev_ios = match get_terminal_attr(output.as_fd()) {
+            Ok(mut ios) => {
+                let prev_ios = ios;
+                ios.c_lflag &= !libc::ICANON & !libc::ECHO;
+                set_terminal_attr(output.as_fd(), libc::TCSAFLUSH, &ios)?;
+                Some(prev_ios)
+            }
+            Err(err) if err.raw_os_error() == Some(libc::ENOTTY) => None,
+            Err(err) => return Err(err),
+        };
         Ok(Termios {
             prev_ios,
             output,
@@ -54,7 +64,9 @@ impl<W: Write + AsFd, R: Read> Termios<W, R> {
 
 impl<W: Write + AsFd, R: Read> Drop for Termios<W, R> {
     fn drop(&mut self) {
-        let _ = set_terminal_attr(self.output.as_fd(), libc::TCSANOW, &self.prev_ios);
+        if let Some(prev_ios) = &self.prev_ios {
+            let _ = set_terminal_attr(self.output.as_fd(), libc::TCSANOW, prev_ios);
+        }
     }
 }
 
@@ -0,0 +1,319 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::memory::LEN;
+use crate::{CondCodes, Error, IoDevice, IoDeviceRegister, Memory, Registers, Storage, TrapCode};
+
+use core::slice;
+
+/// Written by [`VirtualTrapVectorTable`] for a vector not in [`TrapCode`].
+pub(crate) const UNDEFINED_TRAP_MESSAGE: &[u8] = b"\n\n--- undefined trap executed ---\n\n";
+
+/// Written by [`VirtualTrapVectorTable`] before reading the character of `IN`.
+pub(crate) const IN_PROMPT: &[u8] = b"\nInput a character> ";
+
+/// Written by [`VirtualTrapVectorTable`] on `HALT`, and after [`UNDEFINED_TRAP_MESSAGE`].
+pub(crate) const HALT_MESSAGE: &[u8] = b"\n\n--- halting the LC-3 ---\n\n";
+
+/// What to do after a [`TrapHandler`] returns.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum TrapAction {
+    /// The trap was serviced, continue execution at [`Registers::pc`].
+    Continue,
+    /// The trap was serviced, halt the machine.
+    Halt,
+    /// The trap was not serviced, dispatch it through the trap vector
+    /// table in memory as the hardware does.
+    Dispatch,
+}
+
+/// A trap service routine interface.
+///
+/// Before a handler is called, [`Registers::r7`] holds the address of the
+/// instruction following the [`Trap`][`crate::OpCode::Trap`] and
+/// [`Registers::pc`] points to it as well.
+pub trait TrapHandler<IO: IoDevice, S: Storage = [u16; LEN]> {
+    /// Service the trap with the 8-bit trap vector `vector`.
+    fn trap(
+        &mut self,
+        vector: u8,
+        registers: &mut Registers,
+        memory: &mut Memory<IO, S>,
+    ) -> Result<TrapAction, Error<IO::Error>>;
+
+    /// True iff servicing `vector` may write to memory.
+    ///
+    /// Handlers that never write to memory can return false to keep
+    /// a [`DecodeCache`][`crate::DecodeCache`] valid across traps.
+    fn writes_memory(&self, _vector: u8) -> bool {
+        true
+    }
+}
+
+/// Dispatch every trap through the trap vector table in memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct TrapVectorTable;
+
+impl<IO: IoDevice, S: Storage> TrapHandler<IO, S> for TrapVectorTable {
+    fn trap(
+        &mut self,
+        _vector: u8,
+        _registers: &mut Registers,
+        _memory: &mut Memory<IO, S>,
+    ) -> Result<TrapAction, Error<IO::Error>> {
+        Ok(TrapAction::Dispatch)
+    }
+
+    fn writes_memory(&self, _vector: u8) -> bool {
+        false
+    }
+}
+
+/// Emulate the service routines of [`TrapCode`] in Rust
+/// instead of dispatching them through memory.
+///
+/// Every trap writes the same characters, and leaves the same registers
+/// and condition codes, as the routines of the bundled operating system,
+/// which halt the machine on vectors not in [`TrapCode`]. Only addresses
+/// within those routines differ: once halted, the PC is left after the
+/// `TRAP` and R7 holds its return address.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct VirtualTrapVectorTable;
+
+impl<IO: IoDevice, S: Storage> TrapHandler<IO, S> for VirtualTrapVectorTable {
+    fn trap(
+        &mut self,
+        vector: u8,
+        registers: &mut Registers,
+        memory: &mut Memory<IO, S>,
+    ) -> Result<TrapAction, Error<IO::Error>> {
+        let trapcode = match TrapCode::from_u16(vector as u16) {
+            Some(trapcode) => trapcode,
+            None => {
+                memory.io.write(UNDEFINED_TRAP_MESSAGE)?;
+                return halt(registers, memory);
+            }
+        };
+
+        match trapcode {
+            TrapCode::Getc => {
+                let mut byte = 0;
+                memory.io.read(slice::from_mut(&mut byte))?;
+                registers.r0 = byte as u16;
+                registers.cc = CondCodes::from_signum(registers.r0);
+            }
+            TrapCode::Out => {
+                let byte = registers.r0 as u8;
+                memory.io.write(slice::from_ref(&byte))?;
+                memory.io.flush()?;
+                // The routine returns right after restoring R1, and those
+                // below right after restoring R7.
+                registers.cc = CondCodes::from_signum(registers.r1);
+            }
+            TrapCode::Puts => {
+                write_string(registers.r0, memory, false)?;
+                registers.cc = CondCodes::from_signum(registers.r7);
+            }
+            TrapCode::In => {
+                memory.io.write(IN_PROMPT)?;
+                let mut byte = 0;
+                memory.io.read(slice::from_mut(&mut byte))?;
+                memory.io.write(&[byte, b'\n'])?;
+                memory.io.flush()?;
+                registers.r0 = byte as u16;
+                registers.cc = CondCodes::from_signum(registers.r7);
+            }
+            TrapCode::PutSp => {
+                write_string(registers.r0, memory, true)?;
+                registers.cc = CondCodes::from_signum(registers.r7);
+            }
+            TrapCode::Halt => return halt(registers, memory),
+        }
+
+        Ok(TrapAction::Continue)
+    }
+
+    fn writes_memory(&self, _vector: u8) -> bool {
+        false
+    }
+}
+
+/// Write the halting message and clear the clock enable bit of the MCR
+/// into R0 as the operating system's `HALT` routine does, leaving the
+/// machine to be halted.
+fn halt<IO: IoDevice, S: Storage>(
+    registers: &mut Registers,
+    memory: &mut Memory<IO, S>,
+) -> Result<TrapAction, Error<IO::Error>> {
+    memory.io.write(HALT_MESSAGE)?;
+    memory.io.flush()?;
+    registers.r1 = 0x7FFF;
+    registers.r0 = memory.read(IoDeviceRegister::Mcr as u16) & registers.r1;
+    registers.cc = CondCodes::from_signum(registers.r0);
+    Ok(TrapAction::Halt)
+}
+
+/// Write the string at `addr` in `memory` as the operating system's `PUTS`
+/// routine does, or `PUTSP` if `packed`, reading every word as its `LDR`
+/// would. A string without a terminator is written once around memory.
+fn write_string<IO: IoDevice, S: Storage>(
+    addr: u16,
+    memory: &mut Memory<IO, S>,
+    packed: bool,
+) -> Result<(), Error<IO::Error>> {
+    for offset in 0..=u16::MAX {
+        let word = memory.read(addr.wrapping_add(offset));
+        let chars = word.to_le_bytes();
+        // Strings end with x0000, or for packed ones with any zero byte.
+        let (len, last) = match (packed, chars) {
+            (false, _) => (usize::from(word != 0), word == 0),
+            (true, [0, _]) => (0, true),
+            (true, [_, 0]) => (1, true),
+            (true, _) => (2, false),
+        };
+        memory.io.write(&chars[..len])?;
+        if last {
+            break;
+        }
+    }
+    Ok(memory.io.flush()?)
+}
+
+#[cfg(feature = "std")]
+type Handler<'a, IO, S> = Box<
+    dyn FnMut(
+            &mut Registers,
+            &mut Memory<IO, S>,
+        ) -> Result<TrapAction, Error<<IO as IoDevice>::Error>>
+        + 'a,
+>;
+
+/// A table of user-registered trap handlers, one per trap vector.
+///
+/// Vectors without a registered handler, and handlers returning
+/// [`TrapAction::Dispatch`], fall back to the trap vector table in memory,
+/// or to [`VirtualTrapVectorTable`] if created with
+/// [`TrapTable::with_virtual_trap_vector_table`].
+///
+/// # Examples
+///
+/// ```
+/// # use lc3::{IoDevice, Lc3, TrapAction, TrapTable};
+/// # #[derive(Default)]
+/// # struct Null;
+/// # impl IoDevice for Null {
+/// #     type Error = ();
+/// #     fn read(&mut self, _: &mut [u8]) -> Result<usize, ()> { Ok(0) }
+/// #     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> { Ok(buf.len()) }
+/// #     fn flush(&mut self) -> Result<(), ()> { Ok(()) }
+/// # }
+/// let mut lc3 = Lc3::new(Null);
+/// lc3.memory.as_mut()[0x3000] = 0xF030; // TRAP x30
+/// lc3.memory.as_mut()[0x3001] = 0xF025; // HALT
+///
+/// let mut traps = TrapTable::with_virtual_trap_vector_table();
+/// traps.register(0x30, |registers, _memory| {
+///     registers.r2 = 42;
+///     Ok(TrapAction::Continue)
+/// });
+///
+/// assert!(lc3.run_with_trap_handler(&mut traps).is_ok());
+/// assert_eq!(lc3.registers.r2, 42);
+/// ```
+#[cfg(feature = "std")]
+pub struct TrapTable<'a, IO: IoDevice, S: Storage = [u16; LEN]> {
+    handlers: Vec<Option<Handler<'a, IO, S>>>,
+    virtual_trap_vector_table: bool,
+}
+
+#[cfg(feature = "std")]
+impl<'a, IO: IoDevice, S: Storage> TrapTable<'a, IO, S> {
+    /// Initialize an empty table falling back to the trap vector table in memory.
+    pub fn new() -> TrapTable<'a, IO, S> {
+        TrapTable {
+            handlers: (0..=u8::MAX).map(|_| None).collect(),
+            virtual_trap_vector_table: false,
+        }
+    }
+
+    /// Initialize an empty table falling back to [`VirtualTrapVectorTable`].
+    pub fn with_virtual_trap_vector_table() -> TrapTable<'a, IO, S> {
+        TrapTable {
+            virtual_trap_vector_table: true,
+            ..TrapTable::new()
+        }
+    }
+
+    /// Register `handler` for the trap vector `vector`, replacing any previous one.
+    pub fn register<F>(&mut self, vector: u8, handler: F) -> &mut TrapTable<'a, IO, S>
+    where
+        F: FnMut(&mut Registers, &mut Memory<IO, S>) -> Result<TrapAction, Error<IO::Error>> + 'a,
+    {
+        self.handlers[vector as usize] = Some(Box::new(handler));
+        self
+    }
+
+    /// Register a [`TrapHandler`] for the trap vector `vector`, replacing any previous one.
+    pub fn register_handler<H>(&mut self, vector: u8, mut handler: H) -> &mut TrapTable<'a, IO, S>
+    where
+        H: TrapHandler<IO, S> + 'a,
+    {
+        self.register(vector, move |registers, memory| {
+            handler.trap(vector, registers, memory)
+        })
+    }
+
+    /// Remove the handler for the trap vector `vector`, returning true if there was one.
+    pub fn unregister(&mut self, vector: u8) -> bool {
+        self.handlers[vector as usize].take().is_some()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<IO: IoDevice, S: Storage> Default for TrapTable<'_, IO, S> {
+    fn default() -> Self {
+        TrapTable::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<IO: IoDevice, S: Storage> TrapHandler<IO, S> for TrapTable<'_, IO, S> {
+    fn trap(
+        &mut self,
+        vector: u8,
+        registers: &mut Registers,
+        memory: &mut Memory<IO, S>,
+    ) -> Result<TrapAction, Error<IO::Error>> {
+        if let Some(handler) = &mut self.handlers[vector as usize] {
+            match handler(registers, memory)? {
+                TrapAction::Dispatch => (),
+                action => return Ok(action),
+            }
+        }
+
+        if self.virtual_trap_vector_table {
+            VirtualTrapVectorTable.trap(vector, registers, memory)
+        } else {
+            Ok(TrapAction::Dispatch)
+        }
+    }
+
+    fn writes_memory(&self, vector: u8) -> bool {
+        self.handlers[vector as usize].is_some()
+    }
+}
@@ -0,0 +1,99 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Writes to code and execution of data.
+
+#![cfg(feature = "std")]
+
+use lc3::{CodeChecker, CodeEvent, DebugInfo, Headless, Lc3, TrapVectorTable, LC3OS};
+
+const HELLO: &[u8] = include_bytes!("../../examples/hello.obj");
+
+#[test]
+fn operating_system_is_clean() {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.load_image(&mut &LC3OS[..]).unwrap();
+    lc3.load_image(&mut &HELLO[..]).unwrap();
+
+    let mut checker = CodeChecker::new();
+    lc3.boot_monitored(0x3000, &mut TrapVectorTable, &mut checker)
+        .unwrap();
+    assert!(lc3
+        .memory
+        .io()
+        .output()
+        .ends_with(b"Hello World\n\n\n--- halting the LC-3 ---\n\n"));
+    assert_eq!(checker.events(), []);
+}
+
+#[test]
+fn runaway_pointer() {
+    let mut debug_info = DebugInfo::new();
+    debug_info.add_source(
+        "runaway.asm",
+        "        .ORIG x3000
+         LOOP    STR R0, R1, #0
+                 ADD R1, R1, #-1
+                 BRnzp LOOP
+         DATA    .BLKW 2
+                 .END",
+    );
+
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.memory.store(0x3000, 0x7040); // STR R0, R1, #0
+    lc3.memory.store(0x3001, 0x127F); // ADD R1, R1, #-1
+    lc3.memory.store(0x3002, 0x0FFD); // BRnzp #-3
+    lc3.registers.r1 = 0x3004;
+
+    // Writes to DATA are fine, the write below it isn't.
+    let mut checker = CodeChecker::stopping();
+    checker.add_debug_info(&debug_info);
+    lc3.run_monitored_at(0x3000, &mut TrapVectorTable, &mut checker)
+        .unwrap();
+    assert_eq!(
+        checker.events(),
+        [CodeEvent::Overwritten {
+            pc: 0x3000,
+            addr: 0x3002
+        }]
+    );
+    assert_eq!(lc3.memory.load(0x3002), 0x0FFD);
+}
+
+#[test]
+fn falling_through_to_data() {
+    let mut debug_info = DebugInfo::new();
+    debug_info.add_source(
+        "fallthrough.asm",
+        "        .ORIG x3000
+                 AND R0, R0, #0
+         COUNT   .FILL x1021
+                 .END",
+    );
+
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.memory.store(0x3000, 0x5020); // AND R0, R0, #0
+    lc3.memory.store(0x3001, 0x1021); // ADD R0, R0, #1
+
+    let mut checker = CodeChecker::stopping();
+    checker.add_debug_info(&debug_info);
+    lc3.run_monitored_at(0x3000, &mut TrapVectorTable, &mut checker)
+        .unwrap();
+    assert_eq!(checker.events(), [CodeEvent::ExecutedData { pc: 0x3001 }]);
+    assert_eq!(lc3.registers.r0, 0);
+}
@@ -0,0 +1,93 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Helpers shared by the integration tests and the benchmarks.
+
+#![allow(dead_code)]
+
+use lc3::IoDevice;
+use std::convert::Infallible;
+
+/// Keyboard input repeating a script of keys forever, recording output.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Script {
+    pub keys: &'static [u8],
+    pub next: usize,
+    pub output: Vec<u8>,
+}
+
+impl Script {
+    pub fn new(keys: &'static [u8]) -> Script {
+        Script {
+            keys,
+            ..Script::default()
+        }
+    }
+}
+
+impl IoDevice for Script {
+    type Error = Infallible;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        for byte in buf.iter_mut() {
+            *byte = self.keys[self.next % self.keys.len()];
+            self.next += 1;
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Deterministic pseudo-random numbers, xorshift64.
+pub struct XorShift(u64);
+
+impl XorShift {
+    /// Numbers from the same fixed seed on every run.
+    pub fn new() -> XorShift {
+        XorShift(0x9E37_79B9_7F4A_7C15)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Random 16-bit word.
+    pub fn word(&mut self) -> u16 {
+        (self.next() >> 32) as u16
+    }
+
+    /// Random number below `n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
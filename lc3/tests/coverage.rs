@@ -0,0 +1,92 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Coverage of a small program, mapped to its assembly source.
+
+#![cfg(feature = "std")]
+
+use lc3::{Coverage, DebugInfo, Headless, Lc3, SymbolTable, VirtualTrapVectorTable};
+
+const SOURCE: &str = "
+        .ORIG x3000
+        AND R0, R0, #0
+        ADD R1, R0, #3
+LOOP    ADD R0, R0, #1   ; count
+        ADD R1, R1, #-1
+        BRp LOOP
+        BRn NEVER
+        HALT
+NEVER   HALT
+        .END
+";
+
+const WORDS: [u16; 8] = [
+    0x5020, 0x1223, 0x1021, 0x127F, 0x03FD, 0x0801, 0xF025, 0xF025,
+];
+
+/// Run [`WORDS`] loaded at `x3000`.
+fn run() -> (Lc3<Headless>, Coverage) {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.memory.as_mut()[0x3000..][..WORDS.len()].copy_from_slice(&WORDS);
+    let mut coverage = Coverage::new();
+    lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut coverage)
+        .unwrap();
+    (lc3, coverage)
+}
+
+#[test]
+fn lcov_maps_to_source_lines() {
+    let (lc3, coverage) = run();
+    let mut debug_info = DebugInfo::new();
+    debug_info.add_source("count.asm", SOURCE);
+
+    let mut lcov = Vec::new();
+    coverage
+        .write_lcov(&mut lcov, lc3.memory.storage(), &debug_info)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(lcov).unwrap(),
+        "TN:\nSF:count.asm\n\
+         BRDA:7,0,0,2\nBRDA:7,0,1,1\nBRDA:8,0,0,0\nBRDA:8,0,1,1\nBRF:4\nBRH:3\n\
+         DA:3,1\nDA:4,1\nDA:5,3\nDA:6,3\nDA:7,3\nDA:8,1\nDA:9,1\nDA:10,0\n\
+         LF:8\nLH:7\nend_of_record\n",
+    );
+}
+
+#[test]
+fn merged_runs_add_up() {
+    let (lc3, mut coverage) = run();
+    coverage.merge(&run().1);
+    assert_eq!(coverage.count(0x3002), 6);
+    let branch = coverage.branch(0x3004).unwrap();
+    assert_eq!((branch.taken, branch.not_taken), (4, 2));
+
+    let mut report = Vec::new();
+    coverage
+        .write_annotated(
+            &mut report,
+            lc3.memory.storage(),
+            &[0x3004..=0x3007],
+            &SymbolTable::new(),
+        )
+        .unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.starts_with("Coverage: 3 of 4 addresses executed, 3 of 4 branch"));
+    assert!(report.contains("6  x3004    x03FD  BRp #-3  ; x3002  [taken 4, not taken 2]"));
+    assert!(report.contains("#####  x3007    xF025  HALT\n"));
+}
@@ -0,0 +1,284 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Differential tests of instruction semantics against the reference
+//! model of the specification, on random machine states and a corpus
+//! of interesting cases.
+
+#![cfg(feature = "std")]
+
+mod common;
+mod reference;
+
+use common::XorShift;
+use lc3::{CondCodes, Headless, Lc3, Registers};
+use reference::{Exception, Machine};
+
+/// Random machines to run.
+const RUNS: usize = 300;
+
+/// Instructions executed per random machine.
+const STEPS: usize = 300;
+
+/// An interesting machine state: registers R0 through R7, the PC and
+/// condition codes, and the words in memory differing from zero.
+struct Case {
+    name: &'static str,
+    r: [u16; 8],
+    pc: u16,
+    cc: u16,
+    memory: &'static [(u16, u16)],
+    steps: usize,
+}
+
+const CORPUS: &[Case] = &[
+    Case {
+        name: "JSRR R7 jumps to R7 before saving the return address",
+        r: [0, 0, 0, 0, 0, 0, 0, 0x4000],
+        pc: 0x3000,
+        cc: 0b010,
+        memory: &[(0x3000, 0x41C0), (0x4000, 0x1021)],
+        steps: 2,
+    },
+    Case {
+        name: "ADD wraps around from x7FFF to negative",
+        r: [0x7FFF, 0, 0, 0, 0, 0, 0, 0],
+        pc: 0x3000,
+        cc: 0b001,
+        memory: &[(0x3000, 0x1021), (0x3001, 0x03FE)],
+        steps: 2,
+    },
+    Case {
+        name: "AND sign-extends imm5",
+        r: [0xFFFF, 0, 0, 0, 0, 0, 0, 0],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[(0x3000, 0x5030)],
+        steps: 1,
+    },
+    Case {
+        name: "NOT of zero is negative",
+        r: [0; 8],
+        pc: 0x3000,
+        cc: 0b010,
+        memory: &[(0x3000, 0x923F)],
+        steps: 1,
+    },
+    Case {
+        name: "BR with no condition codes is never taken",
+        r: [0; 8],
+        pc: 0x3000,
+        cc: 0b111,
+        memory: &[(0x3000, 0x01FF)],
+        steps: 1,
+    },
+    Case {
+        name: "BR before any condition codes are set is not taken",
+        r: [0; 8],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[(0x3000, 0x0FFF)],
+        steps: 1,
+    },
+    Case {
+        name: "BR wraps around the top of memory",
+        r: [0; 8],
+        pc: 0xFFFE,
+        cc: 0b001,
+        memory: &[(0xFFFE, 0x0202)],
+        steps: 1,
+    },
+    Case {
+        name: "PC wraps around after the last word",
+        r: [0; 8],
+        pc: 0xFFFF,
+        cc: 0,
+        memory: &[(0xFFFF, 0x1021), (0x0000, 0x1021)],
+        steps: 2,
+    },
+    Case {
+        name: "LEA sets the condition codes",
+        r: [0; 8],
+        pc: 0x8000,
+        cc: 0b001,
+        memory: &[(0x8000, 0xE1FF)],
+        steps: 1,
+    },
+    Case {
+        name: "LDI reads through a pointer",
+        r: [0; 8],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[(0x3000, 0xA001), (0x3002, 0x4000), (0x4000, 0x8123)],
+        steps: 1,
+    },
+    Case {
+        name: "LDR and STR with negative offsets",
+        r: [0, 0x4000, 0x1234, 0, 0, 0, 0, 0],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[(0x3000, 0x7460), (0x3001, 0x6660), (0x3FE0, 0x5555)],
+        steps: 2,
+    },
+    Case {
+        name: "LDI of the keyboard registers without a key pressed",
+        r: [0; 8],
+        pc: 0x3000,
+        cc: 0b001,
+        memory: &[
+            (0x3000, 0xA002),
+            (0x3001, 0xA202),
+            (0x3003, 0xFE00),
+            (0x3004, 0xFE02),
+        ],
+        steps: 2,
+    },
+    Case {
+        name: "STI to the display data register writes a character",
+        r: [0x0141, 0, 0, 0, 0, 0, 0, 0],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[
+            (0x3000, 0xB001),
+            (0x3001, 0xA201),
+            (0x3002, 0xFE06),
+            (0x3003, 0xFE04),
+        ],
+        steps: 2,
+    },
+    Case {
+        name: "TRAP jumps through the trap vector table",
+        r: [0; 8],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[(0x3000, 0xF025), (0x0025, 0x0520)],
+        steps: 1,
+    },
+    Case {
+        name: "RET through R7",
+        r: [0, 0, 0, 0, 0, 0, 0, 0x1234],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[(0x3000, 0xC1C0)],
+        steps: 1,
+    },
+    Case {
+        name: "RTI raises an exception in user mode",
+        r: [0; 8],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[(0x3000, 0x8000)],
+        steps: 1,
+    },
+    Case {
+        name: "the reserved opcode raises an exception",
+        r: [0; 8],
+        pc: 0x3000,
+        cc: 0,
+        memory: &[(0x3000, 0xD000)],
+        steps: 1,
+    },
+];
+
+/// Run `model` and `lc3`, in the same state, for at most `steps`
+/// instructions, checking that they stay in the same state.
+fn compare(name: &str, mut model: Machine, mut lc3: Lc3<Headless>, steps: usize) {
+    for step in 0..steps {
+        let pc = model.pc;
+        let word = model.memory[pc as usize];
+        let context = format!("{name}: step {step}, x{word:04X} at x{pc:04X}");
+
+        let expected = model.step();
+        let actual = lc3.next_instruction();
+        match expected {
+            Ok(()) => assert!(actual.is_ok(), "{context}: {actual:?}"),
+            // Both go on past the instruction raising the exception.
+            Err(Exception::Privilege | Exception::IllegalOpcode) => {
+                assert!(actual.is_err(), "{context}: no exception")
+            }
+        }
+
+        let r = &lc3.registers;
+        assert_eq!(
+            [r.r0, r.r1, r.r2, r.r3, r.r4, r.r5, r.r6, r.r7],
+            model.r,
+            "{context}: registers"
+        );
+        assert_eq!(r.pc, model.pc, "{context}: PC");
+        assert_eq!(
+            r.cc,
+            CondCodes::from_u16(model.cc),
+            "{context}: condition codes"
+        );
+        assert!(
+            lc3.memory.as_ref() == model.memory.as_slice(),
+            "{context}: memory"
+        );
+        assert_eq!(lc3.memory.io().output(), model.output, "{context}: output");
+    }
+}
+
+/// A model and a machine both in the state `r`, `pc`, `cc` and `memory`.
+fn machines(r: [u16; 8], pc: u16, cc: u16, memory: Vec<u16>) -> (Machine, Lc3<Headless>) {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.memory.as_mut().copy_from_slice(&memory);
+    let [r0, r1, r2, r3, r4, r5, r6, r7] = r;
+    lc3.registers = Registers {
+        r0,
+        r1,
+        r2,
+        r3,
+        r4,
+        r5,
+        r6,
+        r7,
+        pc,
+        cc: CondCodes::from_u16(cc),
+    };
+
+    let mut model = Machine::new(memory);
+    model.r = r;
+    model.pc = pc;
+    model.cc = cc;
+    (model, lc3)
+}
+
+#[test]
+fn random_machines() {
+    let mut rng = XorShift::new();
+    for run in 0..RUNS {
+        let memory: Vec<u16> = (0..1 << 16).map(|_| rng.word()).collect();
+        let r = [(); 8].map(|_| rng.word());
+        let pc = rng.word();
+        let cc = rng.word() & 0x7;
+        let (model, lc3) = machines(r, pc, cc, memory);
+        compare(&format!("run {run}"), model, lc3, STEPS);
+    }
+}
+
+#[test]
+fn corpus() {
+    for case in CORPUS {
+        let mut memory = vec![0; 1 << 16];
+        for &(addr, word) in case.memory {
+            memory[addr as usize] = word;
+        }
+        let (model, lc3) = machines(case.r, case.pc, case.cc, memory);
+        compare(case.name, model, lc3, case.steps);
+    }
+}
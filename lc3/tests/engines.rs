@@ -0,0 +1,212 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Differential tests of the execution engines against the interpreter.
+
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{Script, XorShift};
+use lc3::{
+    Engine, Lc3, TrapAction, TrapHandler, TrapTable, TrapVectorTable, VirtualTrapVectorTable, LC3OS,
+};
+use std::cell::Cell;
+
+const EXAMPLES: [(&str, &[u8]); 3] = [
+    ("hello", include_bytes!("../../examples/hello.obj")),
+    ("2048", include_bytes!("../../examples/2048.obj")),
+    ("rogue", include_bytes!("../../examples/rogue.obj")),
+];
+
+fn machine(images: &[&[u8]]) -> Box<Lc3<Script>> {
+    let mut lc3 = Box::new(Lc3::new(Script::new(b"wasdwdsaq")));
+    for image in images {
+        lc3.load_image(&mut &image[..]).unwrap();
+    }
+    lc3.registers.pc = Lc3::<Script>::USER_PROGRAMS_START;
+    lc3.reset();
+    lc3
+}
+
+/// Step `engine` and the interpreter side by side in chunks of pseudo-random
+/// sizes for `steps` instructions, restarting the program whenever it halts,
+/// and assert both machines are equal after every chunk.
+fn differential<T: TrapHandler<Script>>(
+    name: &str,
+    images: &[&[u8]],
+    mut engine: Engine,
+    traps: &mut T,
+    steps: usize,
+) {
+    let mut expected = machine(images);
+    let mut actual = machine(images);
+    let mut interpreter = Engine::Interpreter;
+    let mut rng = XorShift::new();
+    let (mut done, mut chunks) = (0, 0);
+
+    while done < steps {
+        if expected.should_halt() {
+            for lc3 in [&mut expected, &mut actual] {
+                lc3.registers.pc = Lc3::<Script>::USER_PROGRAMS_START;
+                lc3.reset();
+            }
+        }
+
+        let limit = 1 + rng.below(50);
+
+        let n = interpreter.next(&mut expected, traps, limit).unwrap();
+        let m = engine.next(&mut actual, traps, limit).unwrap();
+        let engine = engine.name();
+        assert_eq!(n, m, "{name}: {engine}: instructions executed after {done}");
+        assert_eq!(
+            expected.registers, actual.registers,
+            "{name}: {engine}: registers after {done} instructions"
+        );
+        assert_eq!(
+            expected.memory.io(),
+            actual.memory.io(),
+            "{name}: {engine}: I/O after {done} instructions"
+        );
+
+        done += n;
+        chunks += 1;
+        if chunks % 64 == 0 {
+            assert!(
+                expected == actual,
+                "{name}: {engine}: memory after {done} instructions"
+            );
+        }
+    }
+
+    assert!(
+        expected == actual,
+        "{name}: memory after {done} instructions"
+    );
+}
+
+#[test]
+fn examples() {
+    for (name, image) in EXAMPLES {
+        for engine in ["predecoded", "blocks"] {
+            differential(
+                name,
+                &[LC3OS, image],
+                engine.parse().unwrap(),
+                &mut TrapVectorTable,
+                200_000,
+            );
+        }
+    }
+}
+
+#[test]
+fn examples_with_virtual_trap_vector_table() {
+    for (name, image) in EXAMPLES {
+        for engine in ["predecoded", "blocks"] {
+            differential(
+                name,
+                &[image],
+                engine.parse().unwrap(),
+                &mut VirtualTrapVectorTable,
+                100_000,
+            );
+        }
+    }
+}
+
+/// Run `words` at `x3000` with `traps` on every engine,
+/// asserting all end in the same state as the interpreter.
+fn run_all<T: TrapHandler<Script>>(words: &[u16], traps: &mut impl FnMut() -> T) -> Lc3<Script> {
+    let mut image = vec![0x30, 0x00];
+    image.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+
+    let mut results = Vec::new();
+    for name in Engine::NAMES {
+        let mut engine: Engine = name.parse().unwrap();
+        let mut lc3 = machine(&[&image]);
+        lc3.run_with_engine_at(0x3000, &mut engine, &mut traps())
+            .unwrap();
+        results.push((name, lc3));
+    }
+
+    let (_, expected) = &results[0];
+    for (name, lc3) in &results[1..] {
+        assert!(**lc3 == **expected, "{name}: {:?}", lc3.registers);
+    }
+    *results.remove(0).1
+}
+
+#[test]
+fn self_modifying_code() {
+    let lc3 = run_all(
+        &[
+            0x56E0, // AND R3, R3, #0
+            0x2204, // LD R1, #4
+            0x3201, // ST R1, #1
+            0x14A1, // ADD R2, R2, #1
+            0x16E1, // ADD R3, R3, #1 (overwritten)
+            0xF025, // HALT
+            0x16E5, // .FILL ADD R3, R3, #5
+        ],
+        &mut || VirtualTrapVectorTable,
+    );
+    assert_eq!(lc3.registers.r3, 5);
+}
+
+#[test]
+fn jsrr_r7() {
+    let lc3 = run_all(
+        &[
+            0xEE02, // LEA R7, #2
+            0x41C0, // JSRR R7
+            0xF025, // HALT
+            0x16E1, // ADD R3, R3, #1
+            0xF025, // HALT
+        ],
+        &mut || VirtualTrapVectorTable,
+    );
+    assert_eq!(lc3.registers.r3, 1);
+}
+
+#[test]
+fn trap_handler_writing_code() {
+    let calls = Cell::new(0);
+    let lc3 = run_all(
+        &[
+            0x1021, // ADD R0, R0, #1 (overwritten)
+            0xF040, // TRAP x40
+            0x0FFD, // BRnzp #-3
+        ],
+        &mut || {
+            calls.set(0);
+            let mut traps = TrapTable::new();
+            traps.register(0x40, |_, memory| {
+                calls.set(calls.get() + 1);
+                memory.as_mut()[0x3000] = 0x1022; // ADD R0, R0, #2
+                if calls.get() == 3 {
+                    Ok(TrapAction::Halt)
+                } else {
+                    Ok(TrapAction::Continue)
+                }
+            });
+            traps
+        },
+    );
+    assert_eq!(lc3.registers.r0, 5);
+}
@@ -0,0 +1,115 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Control-flow graphs of small programs and their Graphviz rendering.
+
+#![cfg(feature = "std")]
+
+use lc3::{BasicBlock, ControlFlowGraph, Edge, Headless, Lc3, SymbolTable, LC3OS};
+
+/// Load a program with a loop, a direct call, an indirect call and traps.
+fn program() -> Lc3<Headless> {
+    let mut lc3 = Lc3::new(Headless::default());
+    let words = [
+        0x2006, // MAIN  LD R0, DATA
+        0x4803, //       JSR SUB
+        0x0BFD, //       BRnp MAIN
+        0xF021, //       OUT
+        0xF025, //       HALT
+        0x4080, // SUB   JSRR R2
+        0xC1C0, //       RET
+        0x0041, // DATA  .FILL x41
+    ];
+    lc3.memory.as_mut()[0x3000..][..words.len()].copy_from_slice(&words);
+    lc3
+}
+
+#[test]
+fn blocks_and_edges() {
+    let lc3 = program();
+    let cfg = ControlFlowGraph::build(lc3.memory.storage(), &[0x3000, 0x3000]);
+    let block = |start, end, successors: &[Edge]| BasicBlock {
+        start,
+        end,
+        successors: successors.to_vec(),
+    };
+    assert_eq!(
+        cfg.blocks().cloned().collect::<Vec<_>>(),
+        [
+            block(0x3000, 0x3001, &[Edge::Call(0x3005), Edge::Next(0x3002)]),
+            block(0x3002, 0x3002, &[Edge::Branch(0x3000), Edge::Next(0x3003)]),
+            block(0x3003, 0x3003, &[Edge::Trap(0x21), Edge::Next(0x3004)]),
+            block(0x3004, 0x3004, &[Edge::Trap(0x25)]),
+            block(0x3005, 0x3005, &[Edge::IndirectCall, Edge::Next(0x3006)]),
+            block(0x3006, 0x3006, &[Edge::Return]),
+        ]
+    );
+    assert_eq!(cfg.entries(), [0x3000]);
+    assert_eq!(cfg.routines().collect::<Vec<_>>(), [0x3000, 0x3005]);
+    assert_eq!(cfg.block(0x3001).map(|x| x.start), Some(0x3000));
+    assert_eq!(cfg.block(0x3007), None);
+}
+
+#[test]
+fn graphviz() {
+    let lc3 = program();
+    let cfg = ControlFlowGraph::build(lc3.memory.storage(), &[0x3000]);
+    let symbols = SymbolTable::parse("MAIN x3000\nSUB x3005\nDATA x3007\n").unwrap();
+    let mut dot = Vec::new();
+    cfg.write_dot(&mut dot, lc3.memory.storage(), &symbols)
+        .unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.ends_with("}\n"));
+    for line in [
+        "    b3000 [label=\"MAIN:\\lx3000  LD R0, #6  ; x3007 <DATA>\\lx3001  JSR #3  ; x3005 <SUB>\\l\"];",
+        "    b3000 -> b3005 [label=\"call\", style=dashed];",
+        "    b3000 -> b3002;",
+        "    b3002 -> b3000 [label=\"taken\"];",
+        "    b3002 -> b3003 [label=\"not taken\"];",
+        "    b3004 -> trap25 [style=dashed];",
+        "    b3005 -> unknown [label=\"call\", style=dashed];",
+        "    trap21 [label=\"OUT\", shape=ellipse];",
+        "    trap25 [label=\"HALT\", shape=ellipse];",
+    ] {
+        assert!(dot.lines().any(|x| x == line), "{line} missing from\n{dot}");
+    }
+}
+
+#[test]
+fn operating_system_routines() {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.load_image(&mut &LC3OS[..]).unwrap();
+    let vectors: Vec<u16> = (0x20..=0x25).map(|x| lc3.memory.load(x)).collect();
+    let cfg = ControlFlowGraph::build(lc3.memory.storage(), &vectors);
+
+    for &vector in &vectors {
+        assert_eq!(cfg.block(vector).map(|x| x.start), Some(vector));
+    }
+    // Service routines return by RTI or RET, and never run into the
+    // memory mapped I/O registers.
+    for block in cfg.blocks() {
+        assert!(block.end < 0xFE00);
+        for edge in &block.successors {
+            if let Some(target) = edge.target() {
+                assert!(cfg.block(target).is_some(), "{edge:?}");
+            }
+        }
+    }
+}
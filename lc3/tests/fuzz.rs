@@ -0,0 +1,132 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! The fuzz targets of `lc3/fuzz`, run without `cargo fuzz` on random
+//! and mutated inputs. Set `LC3_FUZZ_RUNS` to run more of them.
+
+#![cfg(feature = "std")]
+
+mod common;
+
+#[path = "../fuzz/src/lib.rs"]
+mod targets;
+
+use common::XorShift;
+use lc3::LC3OS;
+use std::panic;
+
+/// Inputs tried per target, unless overridden by `LC3_FUZZ_RUNS`.
+const RUNS: usize = 300;
+
+const EXAMPLES: [&[u8]; 3] = [
+    include_bytes!("../../examples/hello.obj"),
+    include_bytes!("../../examples/2048.obj"),
+    include_bytes!("../../examples/rogue.obj"),
+];
+
+/// `data` with a few random bytes flipped, inserted, removed or
+/// replaced by bytes of `other`.
+fn mutate(rng: &mut XorShift, data: &[u8], other: &[u8]) -> Vec<u8> {
+    let mut data = data.to_vec();
+    for _ in 0..1 + rng.below(8) {
+        let at = rng.below(data.len() + 1);
+        match rng.below(5) {
+            0 if at < data.len() => data[at] ^= 1 << rng.below(8),
+            1 => data.insert(at, rng.next() as u8),
+            2 if at < data.len() => drop(data.remove(at)),
+            3 => data.truncate(at),
+            _ if !other.is_empty() => {
+                let start = rng.below(other.len());
+                let end = start + rng.below(other.len() - start + 1).min(64);
+                data.splice(at..at, other[start..end].iter().copied());
+            }
+            _ => (),
+        }
+    }
+    data
+}
+
+/// Run `target` on every seed, then on random and mutated inputs, each
+/// prefixed with `header` random bytes, reporting the input it fails on.
+fn fuzz(target: fn(&[u8]), seeds: &[&[u8]], header: usize) {
+    let runs = std::env::var("LC3_FUZZ_RUNS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(RUNS);
+    let mut rng = XorShift::new();
+    let mut inputs: Vec<Vec<u8>> = seeds.iter().map(|x| x.to_vec()).collect();
+
+    for run in 0..seeds.len() + runs {
+        let input = if run < seeds.len() {
+            inputs[run].clone()
+        } else {
+            let mut input = rng.bytes(header);
+            match rng.below(3) {
+                0 => {
+                    let len = rng.below(512);
+                    input.extend(rng.bytes(len));
+                }
+                _ => {
+                    let base = &inputs[rng.below(inputs.len())];
+                    let other = &inputs[rng.below(inputs.len())];
+                    input.extend(mutate(&mut rng, base, other));
+                }
+            }
+            input
+        };
+
+        if panic::catch_unwind(|| target(&input)).is_err() {
+            panic!("failed on input {:02X?}", input);
+        }
+        if run >= seeds.len() && inputs.len() < 64 {
+            inputs.push(input[header..].to_vec());
+        }
+    }
+}
+
+#[test]
+fn load_image() {
+    let mut seeds: Vec<Vec<u8>> = Vec::new();
+    for image in [
+        LC3OS,
+        b"3000\nF025\n\n  1234 \r\n",
+        b"0011000000000000\n1111000000100101",
+    ] {
+        for format in 0..4 {
+            seeds.push([&[format, 0xFF, 0x31, 0x30, 0x00], image].concat());
+        }
+    }
+    let seeds: Vec<&[u8]> = seeds.iter().map(|x| &x[..]).collect();
+    fuzz(targets::load_image, &seeds, 3);
+}
+
+#[test]
+fn interpreter() {
+    let seeds: Vec<Vec<u8>> = EXAMPLES
+        .iter()
+        .flat_map(|image| [[&[0], *image].concat(), [&[1], *image].concat()])
+        .collect();
+    let seeds: Vec<&[u8]> = seeds.iter().map(|x| &x[..]).collect();
+    fuzz(targets::interpreter, &seeds, 1);
+}
+
+#[test]
+fn roundtrip() {
+    let all: Vec<u8> = (0..=0xFFFF_u16).flat_map(u16::to_be_bytes).collect();
+    fuzz(targets::roundtrip, &[&all, LC3OS, EXAMPLES[0]], 0);
+}
@@ -0,0 +1,217 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Loading images, and the errors and overlaps reported.
+
+#![cfg(feature = "std")]
+
+use lc3::{Headless, ImageError, ImageFormat, ImageWriter, Lc3, LoadReport};
+use std::io::{self, Read};
+
+fn load(image: &[u8]) -> Result<LoadReport, ImageError<io::Error>> {
+    Lc3::new(Headless::default()).load_image(&mut &image[..])
+}
+
+#[test]
+fn empty() {
+    assert!(matches!(load(&[]), Err(ImageError::Empty)));
+    // An origin without words.
+    assert!(matches!(load(&[0x30, 0x00]), Err(ImageError::Empty)));
+    for (format, text) in [
+        (ImageFormat::Hex, &b"\n3000\n\n"[..]),
+        (ImageFormat::Binary, &b"0011000000000000\n"[..]),
+    ] {
+        let mut lc3 = Lc3::new(Headless::default());
+        let result = lc3.load_image_as(&mut &text[..], format);
+        assert!(matches!(result, Err(ImageError::Empty)), "{format:?}");
+    }
+    let mut lc3 = Lc3::new(Headless::default());
+    let result = lc3.load_image_as(&mut &[][..], ImageFormat::Raw(0x3000));
+    assert!(matches!(result, Err(ImageError::Empty)));
+}
+
+#[test]
+fn odd_length() {
+    assert!(matches!(load(&[0x30]), Err(ImageError::OddLength)));
+    assert!(matches!(
+        load(&[0x30, 0x00, 0xF0]),
+        Err(ImageError::OddLength)
+    ));
+}
+
+#[test]
+fn too_large() {
+    let report = load(&[0xFF, 0xFF, 0xF0, 0x25]).unwrap();
+    assert_eq!(report, LoadReport::new(0xFFFF, 1));
+    assert!(matches!(
+        load(&[0xFF, 0xFF, 0xF0, 0x25, 0xF0, 0x25]),
+        Err(ImageError::TooLarge)
+    ));
+}
+
+/// Load `image` in `format`, returning the report and the words loaded.
+fn load_as(image: &[u8], format: ImageFormat) -> (LoadReport, Vec<u16>) {
+    let mut lc3 = Lc3::new(Headless::default());
+    let report = lc3.load_image_as(&mut &image[..], format).unwrap();
+    let words = report.range().map(|addr| lc3.memory.load(addr as u16));
+    (report, words.collect())
+}
+
+#[test]
+fn hex() {
+    let image = b"3000\n 1021 \r\n\nf025\n0\n";
+    let (report, words) = load_as(image, ImageFormat::Hex);
+    assert_eq!(report, LoadReport::new(0x3000, 3));
+    assert_eq!(words, [0x1021, 0xF025, 0x0000]);
+}
+
+#[test]
+fn binary() {
+    let image = b"0011000000000000\n0001000000100001\n1111000000100101";
+    let (report, words) = load_as(image, ImageFormat::Binary);
+    assert_eq!(report, LoadReport::new(0x3000, 2));
+    assert_eq!(words, [0x1021, 0xF025]);
+}
+
+#[test]
+fn invalid_word() {
+    for (image, format, line) in [
+        (&b"3000\nF025\nHALT\n"[..], ImageFormat::Hex, 3),
+        (b"3000\n\n1F025\n", ImageFormat::Hex, 3),
+        (b"3000\nF0 25 1\n", ImageFormat::Hex, 2),
+        (b"0011000000000000\n101\n", ImageFormat::Binary, 2),
+        (b"0011000000000002\n", ImageFormat::Binary, 1),
+    ] {
+        let mut lc3 = Lc3::new(Headless::default());
+        let result = lc3.load_image_as(&mut &image[..], format);
+        assert!(
+            matches!(result, Err(ImageError::InvalidWord { line: n }) if n == line),
+            "{image:?}: {result:?}"
+        );
+    }
+}
+
+#[test]
+fn raw() {
+    let (report, words) = load_as(&[0x12, 0x34, 0xF0, 0x25], ImageFormat::Raw(0x4000));
+    assert_eq!(report, LoadReport::new(0x4000, 2));
+    assert_eq!(words, [0x1234, 0xF025]);
+}
+
+#[test]
+fn detect() {
+    for (image, format) in [
+        (&b"3000\nF025\n"[..], ImageFormat::Hex),
+        (b"  3000\r\n\n25\n", ImageFormat::Hex),
+        (b"0011000000000000\n1111000000100101\n", ImageFormat::Binary),
+        // 16 binary digits are also hexadecimal, but too long for a word.
+        (b"3000\n0011000000000000\n", ImageFormat::Object),
+        (b"\x30\x00\xF0\x25", ImageFormat::Object),
+        (b"", ImageFormat::Object),
+    ] {
+        assert_eq!(ImageFormat::detect(image), format, "{image:?}");
+    }
+    // A detected image loads as its format.
+    let image = b"3000\nF025\n";
+    let (report, words) = load_as(image, ImageFormat::detect(image));
+    assert_eq!(report, LoadReport::new(0x3000, 1));
+    assert_eq!(words, [0xF025]);
+}
+
+/// A reader failing with [`io::ErrorKind::Interrupted`] before every read.
+struct Interrupting<'a> {
+    data: &'a [u8],
+    interrupt: bool,
+}
+
+impl Read for Interrupting<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.interrupt = !self.interrupt;
+        if self.interrupt {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        self.data.read(buf)
+    }
+}
+
+#[test]
+fn interrupted_reads_are_retried() {
+    let mut lc3 = Lc3::new(Headless::default());
+    let mut file = Interrupting {
+        data: &[0x30, 0x00, 0xF0, 0x25],
+        interrupt: false,
+    };
+    let report = lc3.load_image(&mut file).unwrap();
+    assert_eq!(report, LoadReport::new(0x3000, 1));
+    assert_eq!(lc3.memory.load(0x3000), 0xF025);
+}
+
+#[test]
+fn overwritten() {
+    let mut lc3 = Lc3::new(Headless::default());
+    let os = lc3.load_image(&mut &[0x04, 0xFE, 0, 0, 0, 0][..]).unwrap();
+    assert_eq!(os.overwritten, None);
+    let user = lc3.load_image(&mut &[0x30, 0x00, 0, 0][..]).unwrap();
+    assert_eq!(user.overwritten, None);
+
+    // x04FD-x0501, overwriting x04FE-x04FF of the first image.
+    let patch = lc3
+        .load_image(&mut &[0x04, 0xFD, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1][..])
+        .unwrap();
+    assert_eq!(patch.overwritten, Some(0x04FE..0x0500));
+
+    // x04FC-x3000, overwriting words of all three previous images.
+    let mut image = vec![0x04, 0xFC];
+    image.resize(2 + 2 * (0x3001 - 0x04FC), 0);
+    let all = lc3.load_image(&mut &image[..]).unwrap();
+    assert_eq!(all.overwritten, Some(0x04FD..0x3001));
+}
+
+#[test]
+fn dump_self_modified_code() {
+    let mut image = Vec::new();
+    image
+        .write_image(
+            0x3000,
+            &[
+                0x2203, // LD R1, #3
+                0x3200, // ST R1, #0
+                0x16E1, // ADD R3, R3, #1 (overwritten)
+                0xF025, // HALT
+                0x16E5, // .FILL ADD R3, R3, #5
+            ],
+        )
+        .unwrap();
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.load_image(&mut &image[..]).unwrap();
+    lc3.run_with_virtual_trap_vector_table().unwrap();
+    assert_eq!(lc3.registers.r3, 5);
+
+    let mut dump = Vec::new();
+    lc3.dump_image(0x3000..=0x3004, &mut dump).unwrap();
+    let mut expected = image.clone();
+    expected[6..8].copy_from_slice(&[0x16, 0xE5]);
+    assert_eq!(dump, expected);
+
+    let mut reloaded = Lc3::new(Headless::default());
+    let report = reloaded.load_image(&mut &dump[..]).unwrap();
+    assert_eq!(report, LoadReport::new(0x3000, 5));
+    for addr in 0x3000..=0x3004 {
+        assert_eq!(reloaded.memory.load(addr), lc3.memory.load(addr));
+    }
+}
@@ -0,0 +1,95 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Uninitialized reads of small programs.
+
+#![cfg(feature = "std")]
+
+use lc3::{Headless, InitChecker, Lc3, UninitializedRead, VirtualTrapVectorTable};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+#[test]
+fn loaded_images_are_initialized() {
+    let mut lc3 = Lc3::new(Headless::default());
+    let image: &[u8] = &[
+        0x30, 0x00, // .ORIG x3000
+        0x60, 0x42, // LDR R0, R1, #2
+        0xA0, 0x01, // LDI R0, #1
+        0xF0, 0x25, // HALT
+        0x40, 0x00, // .FILL x4000
+    ];
+    lc3.load_image(&mut &image[..]).unwrap();
+    lc3.registers.r1 = 0x3001;
+
+    let mut checker = InitChecker::new();
+    lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut checker)
+        .unwrap();
+    assert_eq!(
+        checker.reads(),
+        [UninitializedRead {
+            pc: 0x3001,
+            addr: 0x4000
+        }]
+    );
+}
+
+#[test]
+fn stops_before_reading() {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.memory.store(0x3000, 0x1021); // ADD R0, R0, #1
+    lc3.memory.store(0x3001, 0x0BFE); // BRnp #-2
+    lc3.memory.store(0x3004, 0x1021); // ADD R0, R0, #1
+
+    // Execution falls through to x3002 on the second iteration.
+    lc3.registers.r0 = 0xFFFE;
+
+    let mut checker = InitChecker::stopping();
+    lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut checker)
+        .unwrap();
+    assert_eq!(
+        checker.reads(),
+        [UninitializedRead {
+            pc: 0x3002,
+            addr: 0x3002
+        }]
+    );
+    assert_eq!((lc3.registers.pc, lc3.registers.r0), (0x3002, 0));
+
+    // The machine stays halted once stopped.
+    lc3.run_monitored_at(0x3004, &mut VirtualTrapVectorTable, &mut checker)
+        .unwrap();
+    assert_eq!(lc3.registers.r0, 0);
+}
+
+#[test]
+fn initialization_is_not_compared() {
+    let mut stored = Lc3::new(Headless::default());
+    stored.memory.store(0x3000, 0xF025);
+    let mut written = Lc3::new(Headless::default());
+    written.memory.storage_mut()[0x3000] = 0xF025;
+    assert!(stored.memory.is_initialized(0x3000));
+    assert!(!written.memory.is_initialized(0x3000));
+
+    assert!(stored == written);
+    let hash = |lc3: &Lc3<Headless>| {
+        let mut hasher = DefaultHasher::new();
+        lc3.hash(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash(&stored), hash(&written));
+}
@@ -0,0 +1,114 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Static checks of small programs, with and without their sources.
+
+#![cfg(feature = "std")]
+
+use lc3::{ControlFlowGraph, DebugInfo, Diagnostic, Headless, Lc3, LintKind, Linter, Severity};
+
+const SOURCE: &str = "
+        .ORIG x3000
+MAIN    LD R0, CHAR
+        BR SKIP
+        TRAP x30
+SKIP    LEA R1, #-16
+        BRz DONE
+        .FILL xD000
+        ADD R0, R0, #1
+DONE    OUT
+CHAR    .FILL x41
+        .END
+";
+
+const WORDS: [u16; 9] = [
+    0x2007, // MAIN  LD R0, CHAR
+    0x0001, //       BR SKIP
+    0xF030, //       TRAP x30
+    0xE3F0, // SKIP  LEA R1, #-16
+    0x0402, //       BRz DONE
+    0xD000, //       .FILL xD000
+    0x1021, //       ADD R0, R0, #1
+    0xF021, // DONE  OUT
+    0x0041, // CHAR  .FILL x41
+];
+
+/// Lint `words` at x3000 as the whole program.
+fn lint(words: &[u16], debug_info: Option<&DebugInfo>) -> Vec<Diagnostic> {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.memory.as_mut()[0x3000..][..words.len()].copy_from_slice(words);
+    let memory = lc3.memory.storage();
+    let cfg = ControlFlowGraph::build(memory, &[0x3000]);
+    let mut linter = Linter::new();
+    linter.add_region(0x3000..=0x3000 + words.len() as u16 - 1);
+    if let Some(debug_info) = debug_info {
+        linter.add_debug_info(debug_info);
+    }
+    linter.check(memory, &cfg)
+}
+
+#[test]
+fn with_sources() {
+    let mut debug_info = DebugInfo::new();
+    debug_info.add_source("prog.asm", SOURCE);
+    let diagnostics = lint(&WORDS, Some(&debug_info));
+    let kinds: Vec<_> = diagnostics.iter().map(|x| (x.addr, x.kind)).collect();
+    assert_eq!(
+        kinds,
+        [
+            (0x3001, LintKind::NeverTaken),
+            (0x3002, LintKind::UndefinedTrap { vector: 0x30 }),
+            (0x3003, LintKind::LoadsOutside { target: 0x2FF4 }),
+            (0x3004, LintKind::ExecutesData { target: 0x3005 }),
+            (0x3006, LintKind::Unreachable { len: 1 }),
+            (0x3007, LintKind::ExecutesData { target: 0x3008 }),
+        ]
+    );
+    let symbols = debug_info.symbols();
+    assert_eq!(
+        diagnostics[5].message(symbols),
+        "control flows into data at x3008 <CHAR>"
+    );
+    assert_eq!(diagnostics[5].severity(), Severity::Error);
+    assert_eq!(diagnostics[0].severity(), Severity::Warning);
+}
+
+#[test]
+fn without_sources() {
+    let diagnostics = lint(&WORDS, None);
+    let kinds: Vec<_> = diagnostics.iter().map(|x| (x.addr, x.kind)).collect();
+    assert_eq!(
+        kinds,
+        [
+            (0x3001, LintKind::NeverTaken),
+            (0x3002, LintKind::UndefinedTrap { vector: 0x30 }),
+            (0x3003, LintKind::LoadsOutside { target: 0x2FF4 }),
+            (0x3005, LintKind::ReservedOpcode),
+            (0x3007, LintKind::ExecutesData { target: 0x3008 }),
+        ]
+    );
+
+    let diagnostics = lint(&[0x1021, 0xF021], None); // ADD R0, R0, #1; OUT
+    assert_eq!(
+        diagnostics,
+        [Diagnostic {
+            addr: 0x3001,
+            kind: LintKind::LeavesProgram { target: 0x3002 },
+        }]
+    );
+}
@@ -0,0 +1,85 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Profiles of small programs with known costs.
+
+#![cfg(feature = "std")]
+
+use lc3::{Headless, Lc3, Profiler, SymbolTable, VirtualTrapVectorTable};
+
+/// Profile `words` loaded and run at `x3000`.
+fn profile(words: &[u16]) -> Profiler {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.memory.as_mut()[0x3000..][..words.len()].copy_from_slice(words);
+    let mut profiler = Profiler::new();
+    lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut profiler)
+        .unwrap();
+    profiler
+}
+
+#[test]
+fn recursion_is_counted_once() {
+    // MAIN calls DOWN with R0 = 2, which calls itself until R0 is zero.
+    let profiler = profile(&[
+        0x2C0D, // MAIN   LD R6, STACK
+        0x5020, //        AND R0, R0, #0
+        0x1022, //        ADD R0, R0, #2
+        0x4801, //        JSR DOWN
+        0xF025, //        HALT
+        0x1DBF, // DOWN   ADD R6, R6, #-1
+        0x7F80, //        STR R7, R6, #0
+        0x1020, //        ADD R0, R0, #0
+        0x0402, //        BRz DONE
+        0x103F, //        ADD R0, R0, #-1
+        0x4FFA, //        JSR DOWN
+        0x6F80, // DONE   LDR R7, R6, #0
+        0x1DA1, //        ADD R6, R6, #1
+        0xC1C0, //        RET
+        0x4000, // STACK  .FILL x4000
+    ]);
+
+    let routines = profiler.routines();
+    let main = routines.iter().find(|x| x.addr == 0x3000).unwrap();
+    let down = routines.iter().find(|x| x.addr == 0x3005).unwrap();
+    assert_eq!(main.total_instructions, profiler.instructions());
+    assert_eq!(main.self_instructions, 5);
+    assert_eq!(down.calls, 3);
+    assert_eq!(down.total_instructions, profiler.instructions() - 5);
+    assert_eq!(down.self_instructions, down.total_instructions);
+}
+
+#[test]
+fn folded_stacks() {
+    let profiler = profile(&[
+        0x4802, // MAIN   JSR SUB
+        0x4801, //        JSR SUB
+        0xF025, //        HALT
+        0x1021, // SUB    ADD R0, R0, #1
+        0xC1C0, //        RET
+    ]);
+
+    let symbols = SymbolTable::parse("MAIN 3000\nSUB 3003\n").unwrap();
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded, &symbols).unwrap();
+    assert_eq!(String::from_utf8(folded).unwrap(), "MAIN 3\nMAIN;SUB 4\n");
+
+    let calls = profiler.calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!((calls[0].caller, calls[0].callee), (0x3000, 0x3003));
+    assert_eq!((calls[0].calls, calls[0].total_instructions), (2, 4));
+}
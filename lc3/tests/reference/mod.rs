@@ -0,0 +1,185 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Reference model of the LC-3, written from the instruction set
+//! specification independently of the crate, see [`Machine::step`].
+
+/// Keyboard status register.
+pub const KBSR: u16 = 0xFE00;
+/// Keyboard data register.
+pub const KBDR: u16 = 0xFE02;
+/// Display status register.
+pub const DSR: u16 = 0xFE04;
+/// Display data register.
+pub const DDR: u16 = 0xFE06;
+
+/// A machine with an empty keyboard and a display that is always ready.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Machine {
+    /// General purpose registers R0 through R7.
+    pub r: [u16; 8],
+    pub pc: u16,
+    /// Condition codes N, Z and P in bits 2, 1 and 0.
+    pub cc: u16,
+    pub memory: Vec<u16>,
+    /// Characters written to the display data register.
+    pub output: Vec<u8>,
+}
+
+/// Exceptions raised by user programs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Exception {
+    /// `RTI` outside of supervisor mode.
+    Privilege,
+    /// The reserved opcode 1101.
+    IllegalOpcode,
+}
+
+impl Machine {
+    pub fn new(memory: Vec<u16>) -> Machine {
+        assert_eq!(memory.len(), 1 << 16);
+        Machine {
+            r: [0; 8],
+            pc: 0,
+            cc: 0,
+            memory,
+            output: Vec::new(),
+        }
+    }
+
+    /// Fetch, decode and execute one instruction.
+    pub fn step(&mut self) -> Result<(), Exception> {
+        let ir = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+
+        let dr = ((ir >> 9) & 0x7) as usize;
+        let sr1 = ((ir >> 6) & 0x7) as usize;
+        let sr2 = (ir & 0x7) as usize;
+        let imm5 = sext(ir, 5);
+        let pcoffset9 = self.pc.wrapping_add(sext(ir, 9));
+        let offset6 = sext(ir, 6);
+
+        match ir >> 12 {
+            // ADD
+            0b0001 => {
+                let operand = if ir & 0x20 != 0 { imm5 } else { self.r[sr2] };
+                self.r[dr] = self.r[sr1].wrapping_add(operand);
+                self.setcc(dr);
+            }
+            // AND
+            0b0101 => {
+                let operand = if ir & 0x20 != 0 { imm5 } else { self.r[sr2] };
+                self.r[dr] = self.r[sr1] & operand;
+                self.setcc(dr);
+            }
+            // NOT
+            0b1001 => {
+                self.r[dr] = !self.r[sr1];
+                self.setcc(dr);
+            }
+            // BR
+            0b0000 => {
+                if (ir >> 9) & self.cc != 0 {
+                    self.pc = pcoffset9;
+                }
+            }
+            // JMP, RET
+            0b1100 => self.pc = self.r[sr1],
+            // JSR, JSRR
+            0b0100 => {
+                let temp = self.pc;
+                if ir & 0x0800 != 0 {
+                    self.pc = self.pc.wrapping_add(sext(ir, 11));
+                } else {
+                    self.pc = self.r[sr1];
+                }
+                self.r[7] = temp;
+            }
+            // LD
+            0b0010 => {
+                self.r[dr] = self.read(pcoffset9);
+                self.setcc(dr);
+            }
+            // LDI
+            0b1010 => {
+                let addr = self.read(pcoffset9);
+                self.r[dr] = self.read(addr);
+                self.setcc(dr);
+            }
+            // LDR
+            0b0110 => {
+                self.r[dr] = self.read(self.r[sr1].wrapping_add(offset6));
+                self.setcc(dr);
+            }
+            // LEA
+            0b1110 => {
+                self.r[dr] = pcoffset9;
+                self.setcc(dr);
+            }
+            // ST
+            0b0011 => self.write(pcoffset9, self.r[dr]),
+            // STI
+            0b1011 => {
+                let addr = self.read(pcoffset9);
+                self.write(addr, self.r[dr]);
+            }
+            // STR
+            0b0111 => self.write(self.r[sr1].wrapping_add(offset6), self.r[dr]),
+            // TRAP
+            0b1111 => {
+                self.r[7] = self.pc;
+                self.pc = self.read(ir & 0xFF);
+            }
+            // RTI
+            0b1000 => return Err(Exception::Privilege),
+            // reserved
+            _ => return Err(Exception::IllegalOpcode),
+        }
+        Ok(())
+    }
+
+    fn setcc(&mut self, reg: usize) {
+        self.cc = match self.r[reg] {
+            0 => 0b010,
+            x if x & 0x8000 != 0 => 0b100,
+            _ => 0b001,
+        };
+    }
+
+    fn read(&mut self, addr: u16) -> u16 {
+        match addr {
+            KBSR | KBDR | DDR => 0,
+            DSR => 0x8000,
+            _ => self.memory[addr as usize],
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u16) {
+        match addr {
+            DDR => self.output.push(value as u8),
+            KBSR | KBDR | DSR => {}
+            _ => self.memory[addr as usize] = value,
+        }
+    }
+}
+
+/// Sign-extend the low `bits` bits of `value`.
+fn sext(value: u16, bits: u32) -> u16 {
+    let shift = 16 - bits;
+    (((value << shift) as i16) >> shift) as u16
+}
@@ -0,0 +1,146 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Stack discipline of small programs calling nested subroutines.
+
+#![cfg(feature = "std")]
+
+use lc3::{
+    Headless, Lc3, Reg, StackChecker, StackViolation, StackViolationKind, TrapVectorTable,
+    VirtualTrapVectorTable, LC3OS,
+};
+
+const CALLEE_SAVED: [Reg; 5] = [Reg::R1, Reg::R2, Reg::R3, Reg::R4, Reg::R5];
+
+/// Run MAIN calling OUTER calling INNER, made of the word `inner`,
+/// with the stack starting at `stack`.
+fn run(inner: u16, stack: u16, checker: &mut StackChecker) -> Lc3<Headless> {
+    let mut lc3 = Lc3::new(Headless::default());
+    let words = [
+        0x2C0A, // MAIN   LD R6, STACK
+        0x4801, //        JSR OUTER
+        0xF025, //        HALT
+        0x1DBF, // OUTER  ADD R6, R6, #-1
+        0x7F80, //        STR R7, R6, #0
+        0x4803, //        JSR INNER
+        0x6F80, //        LDR R7, R6, #0
+        0x1DA1, //        ADD R6, R6, #1
+        0xC1C0, //        RET
+        inner,  // INNER  ...
+        0xC1C0, //        RET
+        stack,  // STACK  .FILL ...
+    ];
+    lc3.memory.as_mut()[0x3000..][..words.len()].copy_from_slice(&words);
+    lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, checker)
+        .unwrap();
+    lc3
+}
+
+#[test]
+fn balanced() {
+    let mut checker = StackChecker::new(&CALLEE_SAVED);
+    let lc3 = run(0x1021, 0x4000, &mut checker); // ADD R0, R0, #1
+    assert_eq!(lc3.registers.pc, 0x3003);
+    assert_eq!(checker.violations(), []);
+}
+
+#[test]
+fn unbalanced() {
+    let mut checker = StackChecker::stopping(&CALLEE_SAVED);
+    let lc3 = run(0x1DBF, 0x4000, &mut checker); // ADD R6, R6, #-1
+    assert_eq!(lc3.registers.pc, 0x300A);
+    assert_eq!(
+        checker.violations(),
+        [StackViolation {
+            pc: 0x300A,
+            kind: StackViolationKind::Unbalanced {
+                expected: 0x3FFF,
+                actual: 0x3FFE
+            },
+            trace: vec![0x3001, 0x3005],
+        }]
+    );
+}
+
+#[test]
+fn clobbered() {
+    let mut checker = StackChecker::new(&CALLEE_SAVED);
+    run(0x1BA1, 0x4000, &mut checker); // ADD R5, R6, #1
+    let kind = StackViolationKind::CalleeSaved {
+        reg: Reg::R5,
+        expected: 0,
+        actual: 0x4000,
+    };
+    assert_eq!(
+        checker.violations(),
+        [
+            StackViolation {
+                pc: 0x300A,
+                kind,
+                trace: vec![0x3001, 0x3005],
+            },
+            StackViolation {
+                pc: 0x3008,
+                kind,
+                trace: vec![0x3001],
+            },
+        ]
+    );
+
+    let mut checker = StackChecker::stopping(&CALLEE_SAVED);
+    run(0x5FE0, 0x4000, &mut checker); // AND R7, R7, #0
+    let violation = &checker.violations()[0];
+    assert_eq!(
+        violation.kind,
+        StackViolationKind::ReturnAddress {
+            expected: 0x3006,
+            actual: 0
+        }
+    );
+}
+
+#[test]
+fn overflow() {
+    let mut checker = StackChecker::stopping(&CALLEE_SAVED);
+    checker.protect(0x3000..=0x300B);
+    run(0x1021, 0x3005, &mut checker);
+    assert_eq!(
+        checker.violations(),
+        [StackViolation {
+            pc: 0x3004,
+            kind: StackViolationKind::Overflow { addr: 0x3004 },
+            trace: vec![0x3001],
+        }]
+    );
+}
+
+#[test]
+fn operating_system_is_exempt() {
+    let mut lc3 = Lc3::new(Headless::new("x"));
+    lc3.load_image(&mut &LC3OS[..]).unwrap();
+    lc3.memory.store(0x3000, 0xF023); // IN
+    lc3.memory.store(0x3001, 0xF021); // OUT
+    lc3.memory.store(0x3002, 0xF025); // HALT
+
+    let mut checker = StackChecker::new(&CALLEE_SAVED);
+    lc3.boot_monitored(0x3000, &mut TrapVectorTable, &mut checker)
+        .unwrap();
+    let output = lc3.memory.io().output();
+    assert!(output.ends_with(b"Input a character> x\nx\n\n--- halting the LC-3 ---\n\n"));
+    assert_eq!(checker.violations(), []);
+}
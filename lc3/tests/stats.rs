@@ -0,0 +1,101 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Statistics of a small program accessing memory indirectly.
+
+#![cfg(feature = "std")]
+
+use lc3::{
+    Headless, InitChecker, Lc3, OpCode, RegionStats, Stats, SymbolTable, VirtualTrapVectorTable,
+};
+
+#[test]
+fn indirect_accesses() {
+    let mut lc3 = Lc3::new(Headless::default());
+    let words = [
+        0xA203, // LDI R1, PTR
+        0xB202, // STI R1, PTR
+        0x7240, // STR R1, R1, #0
+        0xF025, // HALT
+        0x4010, // PTR .FILL x4010
+    ];
+    lc3.memory.as_mut()[0x3000..][..words.len()].copy_from_slice(&words);
+    lc3.memory.as_mut()[0x4010] = 0x4020;
+
+    let mut stats = Stats::new();
+    lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut stats)
+        .unwrap();
+    assert_eq!(stats.instructions(), 4);
+    assert_eq!(stats.opcode(OpCode::Sti), 1);
+    assert_eq!(stats.reads(0x3004), 2);
+    assert_eq!((stats.reads(0x4010), stats.writes(0x4010)), (1, 1));
+    assert_eq!(stats.writes(0x4020), 1);
+    assert_eq!(stats.branches(), 0);
+
+    assert_eq!(
+        stats.regions(16),
+        [
+            RegionStats {
+                start: 0x3000,
+                end: 0x300F,
+                fetches: 4,
+                reads: 2,
+                writes: 0
+            },
+            RegionStats {
+                start: 0x4010,
+                end: 0x401F,
+                fetches: 0,
+                reads: 1,
+                writes: 1
+            },
+            RegionStats {
+                start: 0x4020,
+                end: 0x402F,
+                fetches: 0,
+                reads: 0,
+                writes: 1
+            },
+        ]
+    );
+
+    let mut symbols = SymbolTable::new();
+    symbols.insert("MAIN", 0x3000);
+    let mut summary = Vec::new();
+    stats.write_summary(&mut summary, &symbols).unwrap();
+    let summary = String::from_utf8(summary).unwrap();
+    assert!(summary.starts_with("Instructions: 4\n"));
+    assert!(summary.contains("  LDI            1  25.00%\n"));
+    assert!(summary.contains("Branches: 0, taken 0 (0.00%)\n"));
+    assert!(summary.contains("              4           2           0  x3000 <MAIN>-x300F\n"));
+}
+
+#[test]
+fn halted_instructions_are_not_counted() {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.memory.store(0x3000, 0x2001); // LD R0, #1
+    lc3.memory.store(0x3001, 0xF025); // HALT
+
+    let mut monitor = (Stats::new(), InitChecker::stopping());
+    lc3.run_monitored_at(0x3000, &mut VirtualTrapVectorTable, &mut monitor)
+        .unwrap();
+    let (stats, checker) = monitor;
+    assert_eq!(checker.reads().len(), 1);
+    assert_eq!(stats.instructions(), 0);
+    assert_eq!(stats.reads(0x3002), 0);
+}
@@ -0,0 +1,173 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Machines with different memory storage behave the same.
+
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::Script;
+use lc3::{
+    Engine, Headless, Lc3, Memory, PagedStorage, Storage, TrapTable, TrapVectorTable, LC3OS,
+};
+
+const ROGUE: &[u8] = include_bytes!("../../examples/rogue.obj");
+
+/// Run rogue for `steps` instructions on `storage`,
+/// returning the final registers, output and memory.
+fn run<S: Storage>(storage: S, steps: usize) -> (lc3::Registers, Vec<u8>, Vec<u16>) {
+    let mut lc3 = Lc3::with_storage(Script::new(b"wasd"), storage);
+    lc3.load_image(&mut &LC3OS[..]).unwrap();
+    lc3.load_image(&mut &ROGUE[..]).unwrap();
+    lc3.registers.pc = 0x3000;
+    lc3.reset();
+
+    let mut engine = Engine::Interpreter;
+    let mut done = 0;
+    while done < steps && !lc3.should_halt() {
+        done += engine
+            .next(&mut lc3, &mut TrapVectorTable, steps - done)
+            .unwrap();
+    }
+
+    let words = (0..=u16::MAX).map(|addr| lc3.memory.load(addr)).collect();
+    let (io, _) = lc3.memory.into_parts();
+    (lc3.registers, io.output, words)
+}
+
+#[test]
+fn storages_agree() {
+    const STEPS: usize = 100_000;
+    let expected = run([0; 1 << 16], STEPS);
+    assert!(!expected.1.is_empty());
+
+    assert!(run(vec![0; 1 << 16].into_boxed_slice(), STEPS) == expected);
+    assert!(run(vec![0; 1 << 16].as_mut_slice(), STEPS) == expected);
+    assert!(run(PagedStorage::new(), STEPS) == expected);
+}
+
+#[test]
+fn short_storage_reads_zero_past_its_end() {
+    let mut words = vec![0; 0x3006];
+    words[0x3000..].copy_from_slice(&[
+        0x2004, // LD R0, #4
+        0x7000, // STR R0, R0, #0
+        0x6400, // LDR R2, R0, #0
+        0xF022, // PUTS
+        0xC000, // JMP R0
+        0x8000, // .FILL x8000
+    ]);
+
+    let mut lc3 = Lc3::with_storage(Script::new(b"?"), words.as_mut_slice());
+    let mut traps = TrapTable::with_virtual_trap_vector_table();
+    lc3.registers.pc = 0x3000;
+    lc3.registers.r2 = 0xFFFF;
+    for _ in 0..6 {
+        lc3.next_instruction_with_trap_handler(&mut traps).unwrap();
+    }
+    assert_eq!(lc3.registers.r2, 0);
+    assert_eq!(lc3.registers.pc, 0x8001);
+    assert_eq!(lc3.memory.load(0x8000), 0);
+    assert!(lc3.memory.io().output.is_empty());
+
+    // The clock enable bit of the MCR past the end is never set.
+    lc3.reset();
+    assert!(lc3.should_halt());
+}
+
+#[test]
+fn paged_storage_copies_on_write() {
+    let mut parent = Lc3::with_storage(Script::new(b"wasd"), PagedStorage::new());
+    parent.load_image(&mut &LC3OS[..]).unwrap();
+    parent.load_image(&mut &ROGUE[..]).unwrap();
+    let pages = parent.memory.storage().allocated_pages();
+
+    let mut child = parent.clone();
+    assert_eq!(
+        child.memory.storage().shared_pages(parent.memory.storage()),
+        pages
+    );
+    assert!(child == parent);
+
+    child.memory.store(0x3000, 0xF025);
+    assert_eq!(
+        child.memory.storage().shared_pages(parent.memory.storage()),
+        pages - 1
+    );
+    assert_ne!(parent.memory.load(0x3000), 0xF025);
+    assert!(child != parent);
+}
+
+#[test]
+fn forks_share_loaded_state() {
+    let mut parent = Lc3::with_storage(Script::new(b"?"), PagedStorage::new());
+    parent.load_image(&mut &LC3OS[..]).unwrap();
+    parent
+        .load_image(&mut &[0x30, 0x00, 0xF0, 0x20, 0xF0, 0x21, 0xF0, 0x25][..]) // GETC, OUT, HALT
+        .unwrap();
+    let pages = parent.memory.storage().allocated_pages();
+
+    for keys in [b"a", b"b", b"c"] {
+        let mut child = parent.fork(Script::new(keys));
+        assert_eq!(
+            child.memory.storage().shared_pages(parent.memory.storage()),
+            pages
+        );
+
+        child.run_at(0x3000).unwrap();
+        let (io, _) = child.memory.into_parts();
+        assert!(io.output.starts_with(keys));
+        assert!(io.output.ends_with(b"\n\n--- halting the LC-3 ---\n\n"));
+    }
+
+    assert!(parent.memory.io().output.is_empty());
+}
+
+#[test]
+fn machines_are_send() {
+    fn assert_send<T: Send + Sync>() {}
+    assert_send::<Lc3<Headless, PagedStorage>>();
+    assert_send::<Lc3<Headless>>();
+}
+
+#[test]
+fn paged_memory_is_small() {
+    // Words and which of them were initialized or loaded live on the heap.
+    assert!(std::mem::size_of::<Memory<Headless, PagedStorage>>() <= 256);
+    assert!(std::mem::size_of::<Lc3<Headless, PagedStorage>>() <= 512);
+}
+
+#[test]
+fn forks_mark_words_apart() {
+    let mut parent = Lc3::with_storage(Headless::default(), PagedStorage::new());
+    parent
+        .load_image(&mut &[0x30, 0x00, 0xF0, 0x25][..]) // HALT
+        .unwrap();
+
+    let mut child = parent.fork(Headless::default());
+    assert!(child.memory.is_initialized(0x3000));
+    child.memory.store(0x4000, 0);
+    assert!(child.memory.is_initialized(0x4000));
+    assert!(!parent.memory.is_initialized(0x4000));
+
+    let report = child.load_image(&mut &[0x30, 0x00, 0, 0][..]).unwrap();
+    assert_eq!(report.overwritten, Some(0x3000..0x3001));
+    let report = parent.load_image(&mut &[0x40, 0x00, 0, 0][..]).unwrap();
+    assert_eq!(report.overwritten, None);
+}
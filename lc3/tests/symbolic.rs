@@ -0,0 +1,116 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Symbolic execution of small programs reading the keyboard, checked
+//! against running them on the inputs found.
+
+#![cfg(feature = "std")]
+
+use lc3::{Explorer, Goal, Headless, Lc3, PathEnd, VirtualTrapVectorTable};
+
+/// Load `words` at x3000, ready to run from there on `input`.
+fn load(words: &[u16], input: &[u8]) -> Lc3<Headless> {
+    let mut lc3 = Lc3::new(Headless::new(input));
+    lc3.memory.as_mut()[0x3000..][..words.len()].copy_from_slice(words);
+    lc3.registers.pc = 0x3000;
+    lc3
+}
+
+/// Answer "Y" to `y`, or else echo the next character.
+const ANSWER: [u16; 13] = [
+    0xF020, //       GETC
+    0x2208, //       LD R1, NEGY
+    0x1201, //       ADD R1, R0, R1
+    0x0A03, //       BRnp NO
+    0xE006, //       LEA R0, YES
+    0xF022, //       PUTS
+    0xF025, //       HALT
+    0xF020, // NO    GETC
+    0xF021, //       OUT
+    0xF025, //       HALT
+    0xFF87, // NEGY  .FILL #-121
+    0x0059, // YES   .STRINGZ "Y"
+    0x0000,
+];
+
+/// Poll the keyboard for characters until a newline.
+const NEWLINE: [u16; 8] = [
+    0xA005, // POLL  LDI R0, KBSR
+    0x07FE, //       BRzp POLL
+    0xA004, //       LDI R0, KBDR
+    0x1036, //       ADD R0, R0, #-10
+    0x0BFB, //       BRnp POLL
+    0xF025, //       HALT
+    0xFE00, // KBSR  .FILL xFE00
+    0xFE02, // KBDR  .FILL xFE02
+];
+
+#[test]
+fn paths_replay() {
+    let lc3 = load(&ANSWER, b"");
+    let paths = Explorer::new().explore(lc3.memory.storage(), lc3.registers);
+    let inputs: Vec<_> = paths.iter().map(|x| x.input.as_slice()).collect();
+    assert_eq!(inputs, [&b"y"[..], b"  "]);
+
+    for path in paths {
+        assert_eq!(path.end, PathEnd::Halted);
+        let mut lc3 = load(&ANSWER, &path.input);
+        lc3.run_with_trap_handler_at(0x3000, &mut VirtualTrapVectorTable)
+            .unwrap();
+        assert_eq!(lc3.memory.io().output(), path.output);
+    }
+}
+
+#[test]
+fn output_goal() {
+    let lc3 = load(&ANSWER, b"");
+    let explorer = Explorer::new();
+    let find = |output: &[u8]| {
+        let goal = Goal::Output(output.to_vec());
+        explorer
+            .find(lc3.memory.storage(), lc3.registers, &goal)
+            .map(|path| path.input)
+    };
+    assert_eq!(find(b"Q"), Some(b" Q".to_vec()));
+    assert_eq!(find(b"Y"), Some(b"y".to_vec()));
+    assert_eq!(find(b"YY"), None);
+}
+
+#[test]
+fn keyboard_polling() {
+    let lc3 = load(&NEWLINE, b"");
+    let mut explorer = Explorer::new();
+    let path = explorer
+        .find(lc3.memory.storage(), lc3.registers, &Goal::Reach(0x3005))
+        .unwrap();
+    assert_eq!((path.input, path.end), (b"\n".to_vec(), PathEnd::Reached));
+
+    let paths = explorer
+        .max_inputs(3)
+        .explore(lc3.memory.storage(), lc3.registers);
+    let ends: Vec<_> = paths.iter().map(|x| (x.input.as_slice(), x.end)).collect();
+    assert_eq!(
+        ends,
+        [
+            (&b"\n"[..], PathEnd::Halted),
+            (b" \n", PathEnd::Halted),
+            (b"  \n", PathEnd::Halted),
+            (b"   ", PathEnd::InputLimit),
+        ]
+    );
+}
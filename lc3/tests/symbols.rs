@@ -0,0 +1,165 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Parsing and merging symbol tables.
+
+#![cfg(feature = "std")]
+
+use lc3::{Conflict, SymbolError, SymbolTable};
+
+/// A symbol table as written by `lc3as`.
+const LC3AS: &str = "\
+// Symbol table
+// Scope level 0:
+//\tSymbol Name       Page Address
+//\t----------------  ------------
+//\tMAIN              3000
+//\tLOOP              3004
+//\tARRAY             3010
+
+";
+
+#[test]
+fn parse_assembler_output() {
+    let symbols = SymbolTable::parse(LC3AS).unwrap();
+    assert_eq!(
+        symbols.iter().collect::<Vec<_>>(),
+        [("MAIN", 0x3000), ("LOOP", 0x3004), ("ARRAY", 0x3010)]
+    );
+}
+
+#[test]
+fn parse_addresses() {
+    let symbols = SymbolTable::parse("A 3000\nB x3001\nC X3002\n  D\tffff  \n").unwrap();
+    assert_eq!(symbols.get("A"), Some(0x3000));
+    assert_eq!(symbols.get("B"), Some(0x3001));
+    assert_eq!(symbols.get("C"), Some(0x3002));
+    assert_eq!(symbols.get("D"), Some(0xFFFF));
+}
+
+#[test]
+fn parse_malformed() {
+    for (text, line) in [
+        ("MAIN 3000\nLOOP\n", 2),
+        ("MAIN 3000 3001\n", 1),
+        ("// Symbol table\nMAIN #3000\n", 2),
+        ("MAIN 10000\n", 1),
+        ("\n\nMAIN -1\n", 3),
+    ] {
+        assert_eq!(
+            SymbolTable::parse(text),
+            Err(SymbolError { line }),
+            "{text:?}"
+        );
+    }
+}
+
+#[test]
+fn parse_redefinition() {
+    let symbols = SymbolTable::parse("LOOP 3000\nSTART 3000\nLOOP 3004\n").unwrap();
+    assert_eq!(symbols.get("LOOP"), Some(0x3004));
+    assert_eq!(symbols.name(0x3000), Some("START"));
+    assert_eq!(symbols.name(0x3004), Some("LOOP"));
+    assert_eq!(symbols.len(), 2);
+}
+
+#[test]
+fn merge() {
+    let mut symbols = SymbolTable::parse("MAIN 3000\nLOOP 3004\n").unwrap();
+    let other = SymbolTable::parse("LOOP 3004\nSUB 4000\n").unwrap();
+    assert_eq!(symbols.merge(other), []);
+    assert_eq!(
+        symbols.iter().collect::<Vec<_>>(),
+        [("MAIN", 0x3000), ("LOOP", 0x3004), ("SUB", 0x4000)]
+    );
+}
+
+#[test]
+fn merge_conflicts() {
+    let mut symbols = SymbolTable::parse("MAIN 3000\nLOOP 3004\n").unwrap();
+    let other = SymbolTable::parse("LOOP 3008\nMAIN 4000\nEND 4010\n").unwrap();
+    let conflicts = symbols.merge(other);
+    assert_eq!(
+        conflicts,
+        [
+            Conflict {
+                name: "LOOP".into(),
+                kept: 0x3004,
+                ignored: 0x3008,
+            },
+            Conflict {
+                name: "MAIN".into(),
+                kept: 0x3000,
+                ignored: 0x4000,
+            },
+        ]
+    );
+    assert_eq!(
+        conflicts[0].to_string(),
+        "symbol 'LOOP' redefined as x3008, keeping x3004"
+    );
+    // Conflicting labels keep their first address, others are added.
+    assert_eq!(symbols.get("LOOP"), Some(0x3004));
+    assert_eq!(symbols.get("MAIN"), Some(0x3000));
+    assert_eq!(symbols.get("END"), Some(0x4010));
+    assert_eq!(symbols.name(0x3008), None);
+}
+
+#[test]
+fn lookup() {
+    let symbols = SymbolTable::parse(LC3AS).unwrap();
+    assert_eq!(symbols.get("loop"), Some(0x3004));
+    assert_eq!(symbols.get("LOOP2"), None);
+    assert_eq!(symbols.locate(0x3006), Some(("LOOP", 2)));
+    assert_eq!(symbols.locate(0x2FFF), None);
+    assert_eq!(symbols.display(0x3006).to_string(), "x3006 <LOOP+2>");
+    assert_eq!(symbols.display(0x3000).to_string(), "x3000 <MAIN>");
+    assert_eq!(symbols.display(0x0200).to_string(), "x0200");
+}
+
+#[test]
+fn locate_without_regions() {
+    let symbols = SymbolTable::parse(LC3AS).unwrap();
+    // The last label locates only its own address.
+    assert_eq!(symbols.locate(0x3010), Some(("ARRAY", 0)));
+    assert_eq!(symbols.locate(0x3012), None);
+    assert_eq!(symbols.display(0xFE00).to_string(), "xFE00");
+}
+
+#[test]
+fn locate_within_regions() {
+    let mut symbols = SymbolTable::parse(LC3AS).unwrap();
+    symbols.add_region(0x3000..0x3020);
+    assert_eq!(symbols.locate(0x3012), Some(("ARRAY", 2)));
+    assert_eq!(symbols.locate(0x301F), Some(("ARRAY", 0xF)));
+    assert_eq!(symbols.locate(0x3020), None);
+    assert_eq!(symbols.display(0xFE00).to_string(), "xFE00");
+
+    // A label locates nothing past the end of its region, even before the next label.
+    let mut symbols = SymbolTable::parse("MAIN 3000\nSUB 4000\n").unwrap();
+    symbols.add_region(0x3000..0x3010);
+    symbols.add_region(0x4000..0x4010);
+    assert_eq!(symbols.locate(0x300F), Some(("MAIN", 0xF)));
+    assert_eq!(symbols.locate(0x3800), None);
+    assert_eq!(symbols.locate(0x4001), Some(("SUB", 1)));
+
+    // Regions are merged along with the labels.
+    let mut merged = SymbolTable::new();
+    merged.merge(symbols);
+    assert_eq!(merged.locate(0x3800), None);
+}
@@ -0,0 +1,323 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Conformance of the emulated trap service routines with those of the
+//! bundled operating system, running the same programs both ways.
+
+#![cfg(feature = "std")]
+
+use lc3::{CondCodes, Headless, Lc3, Reg, LC3OS};
+
+/// End of the operating system, whose routines save registers below it.
+const OS_END: usize = 0x0500;
+
+/// Code at `pc` to run with registers `r`, `data` in memory and keyboard
+/// `input`, until the machine halts or the PC leaves the code.
+struct Program<'a> {
+    code: &'a [u16],
+    pc: u16,
+    r: [u16; 8],
+    data: &'a [(u16, &'a [u16])],
+    input: &'a [u8],
+}
+
+impl Default for Program<'_> {
+    fn default() -> Self {
+        Program {
+            code: &[0xF025],
+            pc: 0x3000,
+            r: [0; 8],
+            data: &[],
+            input: b"",
+        }
+    }
+}
+
+impl Program<'_> {
+    fn machine(&self) -> Lc3<Headless> {
+        let mut lc3 = Lc3::new(Headless::new(self.input));
+        lc3.load_image(&mut &LC3OS[..]).unwrap();
+        for &(addr, words) in self.data {
+            lc3.memory.as_mut()[addr as usize..][..words.len()].copy_from_slice(words);
+        }
+        lc3.memory.as_mut()[self.pc as usize..][..self.code.len()].copy_from_slice(self.code);
+        for (i, value) in self.r.into_iter().enumerate() {
+            lc3.registers[Reg::from_u16(i as u16)] = value;
+        }
+        lc3.registers.pc = self.pc;
+        lc3.registers.cc = CondCodes::Z;
+        lc3.reset();
+        lc3
+    }
+
+    /// Step `lc3` until it halts or the PC leaves the code for user memory.
+    fn run(&self, lc3: &mut Lc3<Headless>, mut step: impl FnMut(&mut Lc3<Headless>)) {
+        let code = self.pc as usize..self.pc as usize + self.code.len();
+        let mut steps = 0;
+        loop {
+            step(lc3);
+            steps += 1;
+            let pc = lc3.registers.pc as usize;
+            if lc3.should_halt() || (pc >= OS_END && !code.contains(&pc)) {
+                break;
+            }
+            assert!(steps < 1_000_000, "program never halted");
+        }
+    }
+
+    /// Run the program with traps serviced by the operating system's
+    /// routines and emulated, asserting both end with the same output,
+    /// registers, condition codes and memory outside the operating system.
+    /// Returns the machine the traps were emulated on.
+    fn conform(&self) -> Lc3<Headless> {
+        let mut os = self.machine();
+        self.run(&mut os, |lc3| lc3.next_instruction().unwrap());
+        let mut emulated = self.machine();
+        self.run(&mut emulated, |lc3| {
+            lc3.next_instruction_with_virtual_trap_vector_table()
+                .unwrap()
+        });
+
+        assert_eq!(
+            emulated.memory.io().output().escape_ascii().to_string(),
+            os.memory.io().output().escape_ascii().to_string(),
+            "output"
+        );
+        assert_eq!(
+            emulated.memory.io().remaining_input(),
+            os.memory.io().remaining_input(),
+            "input"
+        );
+        // Addresses within the routines have no emulated counterpart: the
+        // PC of the halted machine, and the return address of the `PUTS`
+        // called by the `HALT` routine.
+        let mut expected = os.registers;
+        if (expected.pc as usize) < OS_END {
+            expected.pc = emulated.registers.pc;
+        }
+        if (expected.r7 as usize) < OS_END {
+            expected.r7 = emulated.registers.r7;
+        }
+        assert_eq!(emulated.registers, expected, "registers");
+        assert!(
+            emulated.memory.as_mut()[OS_END..] == os.memory.as_mut()[OS_END..],
+            "memory"
+        );
+        assert_eq!(emulated.should_halt(), os.should_halt(), "halted");
+        emulated
+    }
+}
+
+#[test]
+fn getc() {
+    let lc3 = Program {
+        code: &[0xF020],
+        input: b"ab",
+        ..Program::default()
+    }
+    .conform();
+    assert_eq!(lc3.registers.r0, b'a' as u16);
+}
+
+#[test]
+fn out() {
+    for r1 in [0x8000, 0, 1] {
+        let lc3 = Program {
+            code: &[0xF021],
+            r: [0x1241, r1, 0, 0, 0, 0, 0, 0],
+            ..Program::default()
+        }
+        .conform();
+        assert_eq!(lc3.memory.io().output(), b"A");
+    }
+}
+
+#[test]
+fn puts() {
+    let lc3 = Program {
+        code: &[0xF022],
+        r: [0x4000, 0, 0, 0, 0, 0, 0, 0],
+        data: &[(0x4000, &[0x48, 0x0100, 0x69, 0])],
+        ..Program::default()
+    }
+    .conform();
+    assert_eq!(lc3.memory.io().output(), b"H\0i");
+}
+
+#[test]
+fn puts_wraps_around_memory() {
+    let lc3 = Program {
+        code: &[0xF022],
+        r: [0xFFFF, 0, 0, 0, 0, 0, 0, 0],
+        data: &[(0xFFFF, &[0x21])],
+        ..Program::default()
+    }
+    .conform();
+    assert_eq!(lc3.memory.io().output()[0], b'!');
+}
+
+#[test]
+fn in_() {
+    for pc in [0x3000, 0xC000] {
+        let lc3 = Program {
+            code: &[0xF023],
+            pc,
+            input: b"z",
+            ..Program::default()
+        }
+        .conform();
+        assert_eq!(lc3.registers.r0, b'z' as u16);
+        assert_eq!(lc3.memory.io().output(), b"\nInput a character> z\n");
+    }
+}
+
+#[test]
+fn putsp() {
+    let strings: [(&[u16], &[u8]); 5] = [
+        (&[0x6948, 0x0021, 0x0000], b"Hi!"),
+        (&[0x6948, 0x0000], b"Hi"),
+        (&[0x0048, 0x6969, 0x0000], b"H"),
+        (&[0x4800, 0x6969, 0x0000], b""),
+        (&[0x0000, 0x6969], b""),
+    ];
+    for (words, expected) in strings {
+        let lc3 = Program {
+            code: &[0xF024],
+            r: [0x4000, 0, 0, 0, 0, 0, 0, 0],
+            data: &[(0x4000, words)],
+            ..Program::default()
+        }
+        .conform();
+        assert_eq!(lc3.memory.io().output(), expected);
+    }
+}
+
+#[test]
+fn putsp_wraps_around_memory() {
+    Program {
+        code: &[0xF024],
+        r: [0xFFFF, 0, 0, 0, 0, 0, 0, 0],
+        data: &[(0xFFFF, &[0x6948])],
+        ..Program::default()
+    }
+    .conform();
+}
+
+#[test]
+fn halt() {
+    let lc3 = Program {
+        r: [1, 2, 3, 4, 5, 6, 7, 8],
+        ..Program::default()
+    }
+    .conform();
+    assert!(lc3.registers.r0 == 0 && lc3.registers.r1 == 0x7FFF);
+    assert_eq!(
+        lc3.memory.io().output(),
+        b"\n\n--- halting the LC-3 ---\n\n"
+    );
+}
+
+#[test]
+fn undefined_traps_halt() {
+    for vector in (0x00..0x20).chain(0x26..=0xFF) {
+        let lc3 = Program {
+            code: &[0xF000 | vector, 0xF021],
+            r: [0x41, 0, 0, 0, 0, 0, 0, 0],
+            ..Program::default()
+        }
+        .conform();
+        assert_eq!(
+            lc3.memory.io().output(),
+            b"\n\n--- undefined trap executed ---\n\n\n\n--- halting the LC-3 ---\n\n"
+        );
+    }
+}
+
+#[test]
+fn every_trap_from_anywhere() {
+    for pc in [0x3000, 0x7FFE, 0xC000, 0xFDFE] {
+        for vector in 0x00..=0xFF {
+            Program {
+                code: &[0xF000 | vector, 0xF025],
+                pc,
+                r: [0x4000, 0x8000, 2, 3, 4, 5, 6, 7],
+                data: &[(0x4000, &[0x6948, 0x0021, 0x0000])],
+                input: b"xy",
+            }
+            .conform();
+        }
+    }
+}
+
+#[test]
+fn echo() {
+    let lc3 = Program {
+        code: &[
+            0xF023, // IN
+            0x1220, // ADD R1, R0, #0
+            0xF020, // GETC
+            0xF021, // OUT
+            0x1060, // ADD R0, R1, #0
+            0xF021, // OUT
+            0xE004, // LEA R0, #4
+            0xF022, // PUTS
+            0xE005, // LEA R0, #5
+            0xF024, // PUTSP
+            0xF025, // HALT
+            0x0021, // .STRINGZ "!?"
+            0x003F, 0x0000, 0x6948, // .STRINGZP "Hi"
+            0x0000,
+        ],
+        input: b"ab",
+        ..Program::default()
+    }
+    .conform();
+    assert_eq!(
+        lc3.memory.io().output(),
+        b"\nInput a character> a\nba!?Hi\n\n--- halting the LC-3 ---\n\n"
+    );
+}
+
+#[test]
+fn boot_hands_control_to_user_program() {
+    let mut lc3 = Lc3::new(Headless::default());
+    lc3.load_image(&mut &LC3OS[..]).unwrap();
+    let user = [
+        0x30, 0x00, // .ORIG x3000
+        0x30, 0x01, // ST R0, #1
+        0xF0, 0x25, // HALT
+        0x00, 0x00, // .FILL 0
+    ];
+    lc3.load_image(&mut &user[..]).unwrap();
+    lc3.registers.r6 = 0xFE00;
+    lc3.boot_with_trap_handler(0x3000, &mut lc3::TrapVectorTable)
+        .unwrap();
+
+    let (io, _) = lc3.memory.clone().into_parts();
+    let output = String::from_utf8(io.into_output()).unwrap();
+    let (welcome, halting) = output.split_once("LC-3 simulator").unwrap();
+    assert!(welcome.starts_with("\nWelcome to the"), "{output:?}");
+    assert!(
+        halting.ends_with("--- halting the LC-3 ---\n\n"),
+        "{output:?}"
+    );
+    // The user program ran once, with R0 as the startup code left it after
+    // PUTS, and the stack pointer untouched by the operating system.
+    assert_eq!(lc3.memory.load(0x3002), 0x0203);
+    assert_eq!(lc3.registers.r6, 0xFE00);
+}
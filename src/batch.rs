@@ -0,0 +1,360 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! The `batch` subcommand, running many programs headless in parallel.
+
+use crate::{
+    invalid, is_symbol_file, load_image_file, option_value, parse_number, ErrWithContext, Error,
+    ErrorKind,
+};
+use lc3::{Engine, Headless, Lc3, PagedStorage, TrapTable};
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+pub const USAGE: &str = "batch [--jobs N] [--steps N] [--output FILE] [--no-default-os] [--virtual-trap-vector-table] [--engine NAME] DIRECTORY|MANIFEST";
+
+/// Default maximum number of instructions executed per program.
+const DEFAULT_STEPS: u64 = 10_000_000;
+
+/// A program to run, with the file its keyboard input is read from.
+struct Job {
+    image: PathBuf,
+    input: Option<PathBuf>,
+    /// Maximum number of instructions executed, instead of `--steps`.
+    steps: Option<u64>,
+}
+
+/// How a program stopped.
+enum Status {
+    Halted,
+    StepLimit,
+    Error(String),
+}
+
+/// What running a [`Job`] produced.
+struct Outcome {
+    status: Status,
+    steps: u64,
+    registers: lc3::Registers,
+    output: Vec<u8>,
+}
+
+struct Options {
+    steps: u64,
+    virtual_trap_vector_table: bool,
+    engine: Engine,
+}
+
+/// Run the `batch` subcommand with arguments `args`, returning
+/// the exit status: success iff every program halted.
+pub fn run(mut args: impl Iterator<Item = OsString>) -> Result<u8, Error> {
+    let mut jobs = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut output = None;
+    let mut default_os = true;
+    let mut options = Options {
+        steps: DEFAULT_STEPS,
+        virtual_trap_vector_table: false,
+        engine: Engine::Interpreter,
+    };
+    let mut source = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--jobs" {
+            let value = option_value(&arg, args.next())?;
+            jobs = parse_number(&value)
+                .filter(|&n| n > 0)
+                .ok_or_else(|| invalid(&value))?;
+        } else if arg == "--steps" {
+            let value = option_value(&arg, args.next())?;
+            options.steps = parse_number(&value).ok_or_else(|| invalid(&value))?;
+        } else if arg == "--output" {
+            output = Some(PathBuf::from(option_value(&arg, args.next())?));
+        } else if arg == "--no-default-os" {
+            default_os = false;
+        } else if arg == "--virtual-trap-vector-table" {
+            options.virtual_trap_vector_table = true;
+        } else if arg == "--engine" {
+            let value = option_value(&arg, args.next())?;
+            options.engine = value
+                .to_str()
+                .and_then(|x| x.parse().ok())
+                .ok_or_else(|| invalid(&value))?;
+        } else if arg.as_encoded_bytes().starts_with(b"-") || source.is_some() {
+            return Err(Error::new(
+                ErrorKind::UnrecognizedOption,
+                PathBuf::from(arg).display(),
+            ));
+        } else {
+            source = Some(PathBuf::from(arg));
+        }
+    }
+
+    let source = source.ok_or_else(|| Error::new(ErrorKind::MissingFile, "batch"))?;
+    let jobs_list = if source.is_dir() {
+        scan_directory(&source)
+    } else {
+        read_manifest(&source)
+    }
+    .err_with_context(source.display())?;
+
+    let mut base = Lc3::with_storage(Headless::default(), PagedStorage::new());
+    if default_os {
+        base.load_image(&mut &lc3::LC3OS[..])?;
+    }
+
+    let outcomes = run_parallel(&base, &jobs_list, &options, jobs);
+
+    let mut results = Vec::new();
+    for (job, outcome) in jobs_list.iter().zip(&outcomes) {
+        results.extend_from_slice(json_line(job, outcome).as_bytes());
+    }
+    match output {
+        Some(path) => fs::write(&path, results).err_with_context(path.display())?,
+        None => io::stdout().write_all(&results)?,
+    }
+
+    let halted = outcomes.iter().all(|x| matches!(x.status, Status::Halted));
+    Ok(if halted { 0 } else { 1 })
+}
+
+/// Run all `jobs` forked from `base` on `threads` threads,
+/// returning their outcomes in order.
+fn run_parallel(
+    base: &Lc3<Headless, PagedStorage>,
+    jobs: &[Job],
+    options: &Options,
+    threads: usize,
+) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::with_capacity(jobs.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..threads.min(jobs.len()) {
+            scope.spawn(|| {
+                let mut engine = options.engine.clone();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(i) else {
+                        break;
+                    };
+                    engine.clear();
+                    let outcome = run_job(base, job, options, &mut engine);
+                    outcomes.lock().unwrap().push((i, outcome));
+                }
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|&(i, _)| i);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+/// Run `job` on a fork of `base` at the origin of its image.
+fn run_job(
+    base: &Lc3<Headless, PagedStorage>,
+    job: &Job,
+    options: &Options,
+    engine: &mut Engine,
+) -> Outcome {
+    let input = match &job.input {
+        Some(path) => match fs::read(path) {
+            Ok(input) => input,
+            Err(err) => {
+                let err = Error::new(ErrorKind::Io(err), path.display());
+                return Outcome::error(base, err.to_string());
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let mut lc3 = base.fork(Headless::new(input));
+    let report = match load_image_file(&mut lc3, &job.image, None) {
+        Ok(report) => report,
+        Err(err) => return Outcome::error(base, err.to_string()),
+    };
+
+    let mut traps = if options.virtual_trap_vector_table {
+        TrapTable::with_virtual_trap_vector_table()
+    } else {
+        TrapTable::new()
+    };
+
+    lc3.reset();
+    lc3.registers.pc = report.origin;
+    let max_steps = job.steps.unwrap_or(options.steps);
+    let mut steps = 0;
+    let mut count = 0;
+    let status = loop {
+        if lc3.should_halt() {
+            break Status::Halted;
+        }
+        if steps >= max_steps {
+            break Status::StepLimit;
+        }
+        let limit = usize::try_from(max_steps - steps).unwrap_or(usize::MAX);
+        let result = engine.next_counted(&mut lc3, &mut traps, limit, &mut count);
+        steps += count as u64;
+        if let Err(err) = result {
+            // The instruction that failed counts as a step.
+            steps += 1;
+            let pc = lc3.registers.pc.wrapping_sub(1);
+            break Status::Error(format!("x{pc:04X}: {err}"));
+        }
+    };
+
+    let (io, _) = lc3.memory.into_parts();
+    Outcome {
+        status,
+        steps,
+        registers: lc3.registers,
+        output: io.into_output(),
+    }
+}
+
+impl Outcome {
+    /// A program that failed to start with error `message`.
+    fn error(base: &Lc3<Headless, PagedStorage>, message: String) -> Outcome {
+        Outcome {
+            status: Status::Error(message),
+            steps: 0,
+            registers: base.registers,
+            output: Vec::new(),
+        }
+    }
+}
+
+/// Jobs for all images in `dir` in file name order, each with
+/// input from the file of the same name with the extension `.in`.
+fn scan_directory(dir: &Path) -> io::Result<Vec<Job>> {
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_image = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(lc3::ImageFormat::from_extension)
+            .is_some();
+        if path.is_file() && is_image && !is_symbol_file(&path) {
+            images.push(path);
+        }
+    }
+    images.sort();
+
+    Ok(images
+        .into_iter()
+        .map(|image| {
+            let input = image.with_extension("in");
+            Job {
+                input: input.is_file().then_some(input),
+                image,
+                steps: None,
+            }
+        })
+        .collect())
+}
+
+/// Jobs listed in the manifest at `path`, one `IMAGE [INPUT] [steps=N]`
+/// per line, relative to the manifest's directory. Blank lines and lines
+/// starting with `#` are ignored.
+fn read_manifest(path: &Path) -> io::Result<Vec<Job>> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut jobs = Vec::new();
+    for (n, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields: Vec<_> = line.split_whitespace().collect();
+        let steps = match fields.last().and_then(|x| x.strip_prefix("steps=")) {
+            Some(value) => {
+                fields.pop();
+                lc3::parse_number(value).map(Some)
+            }
+            None => Some(None),
+        };
+        let (Some(steps), Some(image), input, None) =
+            (steps, fields.first(), fields.get(1), fields.get(2))
+        else {
+            let msg = format!("line {}: expected IMAGE [INPUT] [steps=N]", n + 1);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        };
+        jobs.push(Job {
+            image: dir.join(image),
+            input: input.map(|input| dir.join(input)),
+            steps,
+        });
+    }
+    Ok(jobs)
+}
+
+/// A JSON object describing `outcome` of `job`, followed by a newline.
+fn json_line(job: &Job, outcome: &Outcome) -> String {
+    let mut line = String::from("{\"image\":");
+    json_string(&mut line, &job.image.to_string_lossy());
+    line.push_str(",\"input\":");
+    match &job.input {
+        Some(input) => json_string(&mut line, &input.to_string_lossy()),
+        None => line.push_str("null"),
+    }
+    line.push_str(",\"status\":");
+    let status = match outcome.status {
+        Status::Halted => "halted",
+        Status::StepLimit => "step_limit",
+        Status::Error(_) => "error",
+    };
+    json_string(&mut line, status);
+    if let Status::Error(message) = &outcome.status {
+        line.push_str(",\"error\":");
+        json_string(&mut line, message);
+    }
+    let r = &outcome.registers;
+    let _ = write!(
+        line,
+        ",\"steps\":{},\"pc\":{},\"registers\":[{},{},{},{},{},{},{},{}],\"output\":",
+        outcome.steps, r.pc, r.r0, r.r1, r.r2, r.r3, r.r4, r.r5, r.r6, r.r7,
+    );
+    json_string(&mut line, &String::from_utf8_lossy(&outcome.output));
+    line.push_str("}\n");
+    line
+}
+
+/// Append `s` to `out` as a JSON string literal.
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' || c == '\u{7f}' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
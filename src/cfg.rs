@@ -0,0 +1,135 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! The `cfg` subcommand, writing the control-flow graph of programs
+//! in the Graphviz DOT language.
+
+use crate::{
+    check_sources, invalid, load_debug_file, load_image_file, option_value, parse_addr,
+    ErrWithContext, Error, ErrorKind,
+};
+use lc3::{ControlFlowGraph, DebugInfo, Headless, Lc3, LoadReport, SymbolTable};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+pub const USAGE: &str =
+    "cfg [--entry ADDR]... [--output FILE] IMAGE-FILE|SYMBOL-FILE|SOURCE-FILE...";
+
+/// Images loaded without the operating system for static analysis,
+/// with the symbols and sources given alongside them.
+pub struct Program {
+    pub lc3: Lc3<Headless>,
+    pub images: Vec<(PathBuf, LoadReport)>,
+    pub symbols: SymbolTable,
+    pub debug_info: DebugInfo,
+}
+
+impl Program {
+    /// Load the images, symbol files and assembly sources `files`. Every
+    /// source must have been assembled to one of the images.
+    pub fn load(files: Vec<PathBuf>, subcommand: &str) -> Result<Program, Error> {
+        if files.is_empty() {
+            return Err(Error::new(ErrorKind::MissingFile, subcommand));
+        }
+
+        let mut program = Program {
+            lc3: Lc3::new(Headless::default()),
+            images: Vec::new(),
+            symbols: SymbolTable::new(),
+            debug_info: DebugInfo::new(),
+        };
+        for path in files {
+            if load_debug_file(&path, &mut program.symbols, &mut program.debug_info)? {
+                continue;
+            }
+            let format = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(lc3::ImageFormat::from_extension);
+            let report = load_image_file(&mut program.lc3, &path, format)?;
+            program.images.push((path, report));
+        }
+        check_sources(&program.debug_info, &program.images)?;
+        for (_, report) in &program.images {
+            program.symbols.add_region(report.range());
+        }
+        Ok(program)
+    }
+
+    /// Addresses of `values`, or the origins of all images if there are none.
+    pub fn entries(&self, values: &[OsString]) -> Result<Vec<u16>, Error> {
+        if values.is_empty() {
+            return Ok(self.images.iter().map(|(_, x)| x.origin).collect());
+        }
+        values
+            .iter()
+            .map(|value| {
+                value
+                    .to_str()
+                    .and_then(|addr| parse_addr(addr, &self.symbols))
+                    .ok_or_else(|| invalid(value))
+            })
+            .collect()
+    }
+
+    /// Memory addresses every image was loaded to.
+    pub fn regions(&self) -> impl Iterator<Item = RangeInclusive<u16>> + '_ {
+        self.images
+            .iter()
+            .map(|(_, x)| x.origin..=x.origin.wrapping_add((x.len - 1) as u16))
+    }
+}
+
+/// Run the `cfg` subcommand with arguments `args`. The graph is built
+/// from the given entry points, or from the origin of every image.
+pub fn run(mut args: impl Iterator<Item = OsString>) -> Result<u8, Error> {
+    let mut entries = Vec::new();
+    let mut output = None;
+    let mut files = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--entry" {
+            entries.push(option_value(&arg, args.next())?);
+        } else if arg == "--output" {
+            output = Some(PathBuf::from(option_value(&arg, args.next())?));
+        } else if arg.as_encoded_bytes().starts_with(b"-") {
+            return Err(Error::new(
+                ErrorKind::UnrecognizedOption,
+                PathBuf::from(arg).display(),
+            ));
+        } else {
+            files.push(PathBuf::from(arg));
+        }
+    }
+
+    let program = Program::load(files, "cfg")?;
+    let entries = program.entries(&entries)?;
+    let memory = program.lc3.memory.storage();
+    let cfg = ControlFlowGraph::build(memory, &entries);
+    let mut dot = Vec::new();
+    cfg.write_dot(&mut dot, memory, &program.symbols)?;
+    match output {
+        Some(path) => fs::write(&path, dot).err_with_context(path.display())?,
+        None => io::stdout().write_all(&dot)?,
+    }
+
+    Ok(0)
+}
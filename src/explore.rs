@@ -0,0 +1,118 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! The `explore` subcommand, finding inputs that lead programs down
+//! each of their paths by symbolic execution.
+
+use crate::cfg::Program;
+use crate::{arg0, invalid, option_value, parse_number, Error, ErrorKind};
+use lc3::{ExecutionPath, Explorer, Goal, PathEnd};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "explore [--entry ADDR] [--reach ADDR | --output TEXT] [--inputs N] [--steps N] [--paths N] IMAGE-FILE|SYMBOL-FILE|SOURCE-FILE...";
+
+/// Run the `explore` subcommand with arguments `args`, returning the
+/// exit status: success unless a goal was given and no path reaches it.
+pub fn run(mut args: impl Iterator<Item = OsString>) -> Result<u8, Error> {
+    let mut entry = Vec::new();
+    let mut reach = None;
+    let mut output = None;
+    let mut explorer = Explorer::new();
+    let mut files = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--entry" {
+            entry = vec![option_value(&arg, args.next())?];
+        } else if arg == "--reach" {
+            reach = Some(option_value(&arg, args.next())?);
+        } else if arg == "--output" {
+            output = Some(option_value(&arg, args.next())?);
+        } else if arg == "--inputs" {
+            let value = option_value(&arg, args.next())?;
+            explorer.max_inputs(parse_number(&value).ok_or_else(|| invalid(&value))?);
+        } else if arg == "--steps" {
+            let value = option_value(&arg, args.next())?;
+            explorer.max_steps(parse_number(&value).ok_or_else(|| invalid(&value))?);
+        } else if arg == "--paths" {
+            let value = option_value(&arg, args.next())?;
+            explorer.max_paths(
+                parse_number(&value)
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| invalid(&value))?,
+            );
+        } else if arg.as_encoded_bytes().starts_with(b"-") {
+            return Err(Error::new(
+                ErrorKind::UnrecognizedOption,
+                PathBuf::from(arg).display(),
+            ));
+        } else {
+            files.push(PathBuf::from(arg));
+        }
+    }
+
+    let program = Program::load(files, "explore")?;
+    let goal = match (reach, output) {
+        (Some(value), None) => Some(Goal::Reach(program.entries(&[value])?[0])),
+        (None, Some(value)) => Some(Goal::Output(value.into_encoded_bytes())),
+        (None, None) => None,
+        (Some(_), Some(value)) => return Err(invalid(&value)),
+    };
+    let Some(&entry) = program.entries(&entry)?.first() else {
+        return Err(Error::new(ErrorKind::MissingFile, "explore"));
+    };
+
+    let memory = program.lc3.memory.storage();
+    let mut registers = program.lc3.registers;
+    registers.pc = entry;
+    let mut out = io::stdout().lock();
+    match goal {
+        Some(goal) => match explorer.find(memory, registers, &goal) {
+            Some(path) => write_path(&mut out, &path)?,
+            None => {
+                eprintln!("{}: no path found", arg0());
+                return Ok(1);
+            }
+        },
+        None => {
+            for path in explorer.explore(memory, registers) {
+                write_path(&mut out, &path)?;
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// Write how `path` ended, its input and its output on a line.
+fn write_path(w: &mut impl Write, path: &ExecutionPath) -> io::Result<()> {
+    let end = match path.end {
+        PathEnd::Halted => "halted".to_string(),
+        PathEnd::Reached => "reached".to_string(),
+        PathEnd::StepLimit => "step limit".to_string(),
+        PathEnd::InputLimit => "input limit".to_string(),
+        PathEnd::Unsupported { pc } => format!("unsupported instruction at x{pc:04X}"),
+    };
+    writeln!(
+        w,
+        "{end} after {} steps: input \"{}\", output \"{}\"",
+        path.steps,
+        path.input.escape_ascii(),
+        path.output.escape_ascii(),
+    )
+}
@@ -0,0 +1,94 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! The `lint` subcommand, reporting suspicious code of programs
+//! without running them.
+
+use crate::cfg::Program;
+use crate::{option_value, Error, ErrorKind};
+use lc3::{ControlFlowGraph, Diagnostic, Instruction, Linter, Severity};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "lint [--entry ADDR]... IMAGE-FILE|SYMBOL-FILE|SOURCE-FILE...";
+
+/// Run the `lint` subcommand with arguments `args`, returning the exit
+/// status: success iff no diagnostic is an error.
+pub fn run(mut args: impl Iterator<Item = OsString>) -> Result<u8, Error> {
+    let mut entries = Vec::new();
+    let mut files = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--entry" {
+            entries.push(option_value(&arg, args.next())?);
+        } else if arg.as_encoded_bytes().starts_with(b"-") {
+            return Err(Error::new(
+                ErrorKind::UnrecognizedOption,
+                PathBuf::from(arg).display(),
+            ));
+        } else {
+            files.push(PathBuf::from(arg));
+        }
+    }
+
+    let program = Program::load(files, "lint")?;
+    let entries = program.entries(&entries)?;
+    let memory = program.lc3.memory.storage();
+    let cfg = ControlFlowGraph::build(memory, &entries);
+    let mut linter = Linter::new();
+    for region in program.regions() {
+        linter.add_region(region);
+    }
+    linter.add_debug_info(&program.debug_info);
+    let diagnostics = linter.check(memory, &cfg);
+
+    let mut out = io::stdout().lock();
+    for diagnostic in &diagnostics {
+        let inst = Instruction::decode(memory[diagnostic.addr as usize]);
+        writeln!(
+            out,
+            "{}: {}: {}",
+            location(&program, diagnostic),
+            diagnostic.severity(),
+            diagnostic.message(&program.symbols),
+        )?;
+        writeln!(out, "    x{:04X}  {inst}", diagnostic.addr)?;
+    }
+
+    let failed = diagnostics.iter().any(|x| x.severity() == Severity::Error);
+    Ok(if failed { 1 } else { 0 })
+}
+
+/// Where `diagnostic` is: the source line its address was assembled
+/// from, or the last image loaded to the address.
+fn location(program: &Program, diagnostic: &Diagnostic) -> String {
+    let addr = diagnostic.addr;
+    if let Some(line) = program.debug_info.line(addr) {
+        return format!("{}:{}", program.debug_info.files()[line.file], line.line);
+    }
+    match program
+        .images
+        .iter()
+        .rev()
+        .find(|(_, x)| x.range().contains(&(addr as usize)))
+    {
+        Some((path, _)) => format!("{}:x{addr:04X}", path.display()),
+        None => format!("x{addr:04X}"),
+    }
+}
@@ -16,30 +16,72 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
-use lc3::{self, Lc3, Termios};
-use std::{env, fmt, fs::File, io, path::PathBuf, process::ExitCode};
+mod batch;
+mod cfg;
+mod explore;
+mod lint;
+mod monitors;
+
+use lc3::{self, Engine, ImageError, ImageFormat, Lc3, LoadReport, SymbolTable, Termios};
+use lc3::{DebugInfo, Reg, TrapAction, TrapCode, TrapTable};
+use monitors::{CheckMode, MonitorOptions, Monitors};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::{cell::Cell, env, ffi::OsString, fmt, fs, fs::File, io, process::ExitCode};
 
 const LICENSE: &str = "lc3-vm  Copyright (c) 2024  Fares A. Bakhit <fares@duck.com>";
-const USAGE: &str = "[--no-default-os] [--virtual-trap-vector-table] [IMAGE-FILE...]";
+const USAGE: &str =
+    "[--no-default-os] [--virtual-trap-vector-table] [--engine NAME] [--entry ADDR | --entry-origin] [--boot] [--exit-code SOURCE] [--dump RANGE:FILE] [--profile FILE] [--coverage FILE] [--stats] [--uninitialized warn|stop] [--check-code warn|stop] [--check-stack warn|stop] [--callee-saved REGS] [[--load-at ADDR] IMAGE-FILE|SYMBOL-FILE|SOURCE-FILE...]";
 
 fn main() -> ExitCode {
-    let arg0 = env::args().next().unwrap_or("path/to/lc3-vm".into());
+    let arg0 = arg0();
 
     if env::args().len() <= 1 {
         eprintln!("{LICENSE}");
         eprintln!("Usage: {arg0} {USAGE}");
+        eprintln!("       {arg0} {}", batch::USAGE);
+        eprintln!("       {arg0} {}", cfg::USAGE);
+        eprintln!("       {arg0} {}", lint::USAGE);
+        eprintln!("       {arg0} {}", explore::USAGE);
         return ExitCode::from(2);
     }
 
-    match run() {
-        Ok(()) => ExitCode::SUCCESS,
+    let subcommand = env::args_os().nth(1);
+    let result = if subcommand.as_ref().is_some_and(|x| x == "batch") {
+        batch::run(env::args_os().skip(2))
+    } else if subcommand.as_ref().is_some_and(|x| x == "cfg") {
+        cfg::run(env::args_os().skip(2))
+    } else if subcommand.as_ref().is_some_and(|x| x == "lint") {
+        lint::run(env::args_os().skip(2))
+    } else if subcommand.as_ref().is_some_and(|x| x == "explore") {
+        explore::run(env::args_os().skip(2))
+    } else {
+        run()
+    };
+
+    match result {
+        Ok(status) => ExitCode::from(status),
         Err(err) => {
             eprintln!("{arg0}: {err}");
             match err.kind {
-                ErrorKind::Io(_) => ExitCode::from(2),
-                ErrorKind::Lc3(_) => ExitCode::FAILURE,
-                ErrorKind::UnrecognizedOption => {
+                ErrorKind::Io(_)
+                | ErrorKind::Image(_)
+                | ErrorKind::Symbols(_)
+                | ErrorKind::SourceNotLoaded => ExitCode::from(2),
+                ErrorKind::Lc3(_)
+                | ErrorKind::UninitializedRead(_)
+                | ErrorKind::CodeOverwritten(_)
+                | ErrorKind::DataExecuted
+                | ErrorKind::Stack(_) => ExitCode::FAILURE,
+                ErrorKind::UnrecognizedOption
+                | ErrorKind::MissingArgument
+                | ErrorKind::MissingFile
+                | ErrorKind::InvalidArgument => {
                     eprintln!("Usage: {arg0} {USAGE}");
+                    eprintln!("       {arg0} {}", batch::USAGE);
+                    eprintln!("       {arg0} {}", cfg::USAGE);
+                    eprintln!("       {arg0} {}", lint::USAGE);
+                    eprintln!("       {arg0} {}", explore::USAGE);
                     ExitCode::from(2)
                 }
             }
@@ -47,19 +89,66 @@ fn main() -> ExitCode {
     }
 }
 
-fn run() -> Result<(), Error> {
+fn run() -> Result<u8, Error> {
     let mut files = Vec::with_capacity(env::args_os().len());
     let mut default_os = true;
     let mut virtual_trap_vector_table = false;
+    let mut engine: Option<Engine> = None;
+    let mut entry = Entry::UserPrograms;
+    let mut boot = false;
+    let mut exit_code = None;
+    let mut load_at = None;
+    let mut dumps = Vec::new();
+    let mut monitor_options = MonitorOptions::default();
     let mut stop_options_processing = false;
+    let mut args = env::args_os().skip(1);
 
-    for arg in env::args_os().skip(1) {
+    while let Some(arg) = args.next() {
         if stop_options_processing {
-            files.push(arg);
+            files.push((arg, load_at.take()));
         } else if arg == "--no-default-os" {
             default_os = false;
         } else if arg == "--virtual-trap-vector-table" {
             virtual_trap_vector_table = true;
+        } else if arg == "--engine" {
+            let value = option_value(&arg, args.next())?;
+            engine = Some(
+                value
+                    .to_str()
+                    .and_then(|x| x.parse().ok())
+                    .ok_or_else(|| invalid(&value))?,
+            );
+        } else if arg == "--entry" {
+            entry = Entry::Addr(option_value(&arg, args.next())?);
+        } else if arg == "--entry-origin" {
+            entry = Entry::LastOrigin;
+        } else if arg == "--boot" {
+            boot = true;
+        } else if arg == "--exit-code" {
+            exit_code = Some(option_value(&arg, args.next())?);
+        } else if arg == "--dump" {
+            dumps.push(option_value(&arg, args.next())?);
+        } else if arg == "--profile" {
+            monitor_options.profile = Some(PathBuf::from(option_value(&arg, args.next())?));
+        } else if arg == "--coverage" {
+            monitor_options.coverage = Some(PathBuf::from(option_value(&arg, args.next())?));
+        } else if arg == "--stats" {
+            monitor_options.stats = true;
+        } else if arg == "--uninitialized" {
+            monitor_options.uninitialized =
+                Some(CheckMode::parse(option_value(&arg, args.next())?)?);
+        } else if arg == "--check-code" {
+            monitor_options.check_code = Some(CheckMode::parse(option_value(&arg, args.next())?)?);
+        } else if arg == "--check-stack" {
+            monitor_options.check_stack = Some(CheckMode::parse(option_value(&arg, args.next())?)?);
+        } else if arg == "--callee-saved" {
+            let value = option_value(&arg, args.next())?;
+            monitor_options.callee_saved = value
+                .to_str()
+                .and_then(parse_regs)
+                .ok_or_else(|| invalid(&value))?;
+        } else if arg == "--load-at" {
+            load_at = Some(option_value(&arg, args.next())?);
         } else if arg == "--" {
             stop_options_processing = true;
         } else if arg.as_encoded_bytes().starts_with(b"-") {
@@ -68,29 +157,311 @@ fn run() -> Result<(), Error> {
                 PathBuf::from(arg).display(),
             ));
         } else {
-            files.push(arg);
+            files.push((arg, load_at.take()));
+        }
+    }
+
+    // Monitors observe instructions only as the interpreter executes them.
+    if let Some(engine) = engine.as_ref().filter(|_| monitor_options.any()) {
+        if *engine != Engine::Interpreter {
+            return Err(Error::new(ErrorKind::InvalidArgument, engine.name()));
         }
     }
 
     let mut lc3 = Lc3::new(Termios::new()?);
 
+    let mut loaded = Vec::with_capacity(files.len() + 1);
+
     if default_os {
-        let lc3os_img = include_bytes!("lc3os.obj");
-        lc3.load_image(&mut lc3os_img.as_slice())?;
+        let report = lc3.load_image(&mut &lc3::LC3OS[..])?;
+        loaded.push((PathBuf::from("<lc3os>"), report));
+    }
+    let os_images = loaded.len();
+
+    let mut symbols = SymbolTable::new();
+    let mut debug_info = DebugInfo::new();
+
+    for (x, load_at) in files {
+        let x = PathBuf::from(x);
+        let format = if let Some(load_at) = load_at {
+            let origin = load_at
+                .to_str()
+                .and_then(|addr| parse_addr(addr, &symbols))
+                .ok_or_else(|| invalid(&load_at))?;
+            Some(ImageFormat::Raw(origin))
+        } else if load_debug_file(&x, &mut symbols, &mut debug_info)? {
+            continue;
+        } else {
+            x.extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(ImageFormat::from_extension)
+        };
+
+        let report = load_image_file(&mut lc3, &x, format)?;
+        warn_overlaps(&x, &report, &loaded);
+        loaded.push((x, report));
+    }
+    check_sources(&debug_info, &loaded)?;
+    for (_, report) in &loaded {
+        symbols.add_region(report.range());
     }
 
-    files.into_iter().try_for_each(|x| {
-        File::open(&x)
-            .and_then(|mut x| lc3.load_image(&mut x))
-            .err_with_context(PathBuf::from(x).display())
+    let exit_code = match exit_code {
+        Some(value) => ExitCodeSource::parse(&value, &symbols).ok_or_else(|| invalid(&value))?,
+        None => ExitCodeSource::None,
+    };
+
+    let entry = match entry {
+        Entry::UserPrograms => Lc3::<Termios>::USER_PROGRAMS_START,
+        Entry::LastOrigin => loaded
+            .last()
+            .map_or(Lc3::<Termios>::USER_PROGRAMS_START, |(_, x)| x.origin),
+        Entry::Addr(value) => value
+            .to_str()
+            .and_then(|addr| parse_addr(addr, &symbols))
+            .ok_or_else(|| invalid(&value))?,
+    };
+
+    let dumps = dumps
+        .into_iter()
+        .map(|value| parse_dump(&value, &symbols).ok_or_else(|| invalid(&value)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let status = Cell::new(0);
+    let mut traps = if virtual_trap_vector_table {
+        TrapTable::with_virtual_trap_vector_table()
+    } else {
+        TrapTable::new()
+    };
+
+    match exit_code {
+        ExitCodeSource::None | ExitCodeSource::Memory(_) => (),
+        ExitCodeSource::HaltR0 => {
+            traps.register(TrapCode::Halt as u8, |registers, _| {
+                status.set(registers.r0);
+                Ok(TrapAction::Dispatch)
+            });
+        }
+        ExitCodeSource::Trap(vector) => {
+            traps.register(vector, |registers, _| {
+                status.set(registers.r0);
+                Ok(TrapAction::Halt)
+            });
+        }
+    }
+
+    let monitored = monitor_options.any();
+    let mut monitors = Monitors::new(&monitor_options, &debug_info, &loaded[..os_images]);
+    let mut engine = engine.unwrap_or(Engine::Interpreter);
+    let result = match (boot, monitored) {
+        (true, false) => lc3.boot_with_engine(entry, &mut engine, &mut traps),
+        (false, false) => lc3.run_with_engine_at(entry, &mut engine, &mut traps),
+        (true, true) => lc3.boot_monitored(entry, &mut traps, &mut monitors),
+        (false, true) => lc3.run_monitored_at(entry, &mut traps, &mut monitors),
+    };
+    drop(traps);
+
+    let images = &loaded[os_images..];
+    monitors.write(&monitor_options, &lc3, images, &symbols, &debug_info)?;
+
+    for (range, path) in dumps {
+        File::create(&path)
+            .and_then(|mut file| lc3.dump_image(range, &mut file))
+            .err_with_context(path.display())?;
+    }
+
+    monitors.check(&monitor_options, &symbols)?;
+
+    result.map_err(|err| match err {
+        lc3::Error::Io(_) => Error::new(ErrorKind::from(err), "<termios>"),
+        _ => {
+            let pc = lc3.registers.pc.wrapping_sub(1);
+            Error::new(ErrorKind::from(err), symbols.display(pc))
+        }
     })?;
 
-    if virtual_trap_vector_table {
-        lc3.run_with_virtual_trap_vector_table()
+    if let ExitCodeSource::Memory(addr) = exit_code {
+        status.set(lc3.memory.as_ref()[addr as usize]);
+    }
+
+    Ok(exit_status(status.get()))
+}
+
+/// The process exit status for the result `value` of a program: its low
+/// 8 bits, or 1 if those are all zero but `value` isn't.
+fn exit_status(value: u16) -> u8 {
+    match value as u8 {
+        0 if value != 0 => 1,
+        status => status,
+    }
+}
+
+/// Load the image file at `path` in `format`, or in the format detected from its content.
+fn load_image_file<IO: lc3::IoDevice, S: lc3::Storage>(
+    lc3: &mut Lc3<IO, S>,
+    path: &Path,
+    format: Option<ImageFormat>,
+) -> Result<LoadReport, Error> {
+    let content = fs::read(path).err_with_context(path.display())?;
+    let format = format.unwrap_or_else(|| ImageFormat::detect(&content));
+    lc3.load_image_as(&mut content.as_slice(), format)
+        .err_with_context(path.display())
+}
+
+/// Load the symbol file or assembly source at `path`, merging its labels
+/// into `symbols`, with a warning for every conflict, and its lines into
+/// `debug_info`. Returns false if `path` is neither.
+fn load_debug_file(
+    path: &Path,
+    symbols: &mut SymbolTable,
+    debug_info: &mut DebugInfo,
+) -> Result<bool, Error> {
+    let table = if is_symbol_file(path) {
+        let text = fs::read_to_string(path).err_with_context(path.display())?;
+        SymbolTable::parse(&text).err_with_context(path.display())?
+    } else if is_source_file(path) {
+        let text = fs::read_to_string(path).err_with_context(path.display())?;
+        let mut source = DebugInfo::new();
+        source.add_source(path.display().to_string(), &text);
+        let table = source.symbols().clone();
+        debug_info.merge(source);
+        table
     } else {
-        lc3.run()
+        return Ok(false);
+    };
+    for conflict in symbols.merge(table) {
+        eprintln!("{}: warning: {}: {conflict}", arg0(), path.display());
+    }
+    Ok(true)
+}
+
+/// Fail unless every assembly source of `debug_info` with any lines has
+/// some of them at the addresses of the `loaded` images.
+fn check_sources(debug_info: &DebugInfo, loaded: &[(PathBuf, LoadReport)]) -> Result<(), Error> {
+    // Without its image, a source would describe zeroed memory.
+    for (file, name) in debug_info.files().iter().enumerate() {
+        let mut addrs = debug_info
+            .iter()
+            .filter(|(_, line)| line.file == file)
+            .map(|(addr, _)| addr as usize)
+            .peekable();
+        if addrs.peek().is_some()
+            && !addrs.any(|addr| loaded.iter().any(|(_, x)| x.range().contains(&addr)))
+        {
+            return Err(Error::new(ErrorKind::SourceNotLoaded, name));
+        }
+    }
+    Ok(())
+}
+
+/// Warn about the regions of previously `loaded` images overwritten by the image at `path`.
+fn warn_overlaps(path: &Path, report: &LoadReport, loaded: &[(PathBuf, LoadReport)]) {
+    let Some(overwritten) = &report.overwritten else {
+        return;
+    };
+    for (prev_path, prev_report) in loaded {
+        let start = overwritten.start.max(prev_report.range().start);
+        let end = overwritten.end.min(prev_report.range().end);
+        if start < end {
+            eprintln!(
+                "{}: warning: {}: x{:04X}-x{:04X} overwrites {}",
+                arg0(),
+                path.display(),
+                start,
+                end - 1,
+                prev_path.display(),
+            );
+        }
+    }
+}
+
+/// Parse a comma-separated list of registers, such as `R1,R2,R3`.
+fn parse_regs(s: &str) -> Option<Vec<Reg>> {
+    s.split(',')
+        .filter(|x| !x.is_empty())
+        .map(|x| match x.strip_prefix(['R', 'r'])?.parse::<u16>().ok()? {
+            n @ 0..=7 => Some(Reg::from_u16(n)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Where execution of the user program starts.
+enum Entry {
+    /// [`Lc3::USER_PROGRAMS_START`].
+    UserPrograms,
+    /// The origin of the last loaded image.
+    LastOrigin,
+    /// An address or label.
+    Addr(OsString),
+}
+
+/// Where the process exit status comes from once the machine halts.
+#[derive(Clone, Copy)]
+enum ExitCodeSource {
+    /// Always exit successfully.
+    None,
+    /// R0 at the time of `HALT`.
+    HaltR0,
+    /// R0 at the time of `TRAP vector`, which halts the machine.
+    Trap(u8),
+    /// The word at a memory address.
+    Memory(u16),
+}
+
+impl ExitCodeSource {
+    /// Parse `none`, `r0`, `trap:VECTOR` or `mem:ADDR`.
+    fn parse(s: &OsString, symbols: &SymbolTable) -> Option<ExitCodeSource> {
+        let s = s.to_str()?;
+        match s.split_once(':') {
+            None if s == "none" => Some(ExitCodeSource::None),
+            None if s == "r0" => Some(ExitCodeSource::HaltR0),
+            Some(("trap", vector)) => lc3::parse_number(vector).map(ExitCodeSource::Trap),
+            Some(("mem", addr)) => parse_addr(addr, symbols).map(ExitCodeSource::Memory),
+            _ => None,
+        }
     }
-    .err_with_context("<termios>")
+}
+
+/// Parse `START-END:FILE`, dumping memory at addresses `START` through `END` to `FILE`.
+fn parse_dump(s: &OsString, symbols: &SymbolTable) -> Option<(RangeInclusive<u16>, PathBuf)> {
+    let (range, path) = s.to_str()?.split_once(':')?;
+    let (start, end) = range.split_once('-')?;
+    let start = parse_addr(start, symbols)?;
+    let end = parse_addr(end, symbols)?;
+    (start <= end && !path.is_empty()).then(|| (start..=end, PathBuf::from(path)))
+}
+
+/// Parse an address written as a number or as a label in `symbols`.
+fn parse_addr(s: &str, symbols: &SymbolTable) -> Option<u16> {
+    lc3::parse_number(s).or_else(|| symbols.get(s))
+}
+
+fn is_symbol_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("sym"))
+}
+
+fn is_source_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("asm"))
+}
+
+fn arg0() -> String {
+    env::args().next().unwrap_or("path/to/lc3-vm".into())
+}
+
+fn option_value(option: &OsString, value: Option<OsString>) -> Result<OsString, Error> {
+    value.ok_or_else(|| Error::new(ErrorKind::MissingArgument, PathBuf::from(option).display()))
+}
+
+/// Parse the value of an option as a number, see [`lc3::parse_number`].
+fn parse_number<T: TryFrom<u64>>(value: &OsString) -> Option<T> {
+    value.to_str().and_then(lc3::parse_number)
+}
+
+fn invalid(value: &OsString) -> Error {
+    Error::new(ErrorKind::InvalidArgument, PathBuf::from(value).display())
 }
 
 struct Error {
@@ -109,7 +480,11 @@ impl Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let ErrorKind::UnrecognizedOption = self.kind {
+        if let ErrorKind::UnrecognizedOption
+        | ErrorKind::MissingArgument
+        | ErrorKind::MissingFile
+        | ErrorKind::InvalidArgument = self.kind
+        {
             write!(f, "{} '{}'", self.kind, self.ctx)
         } else {
             write!(f, "{}: {}", self.ctx, self.kind)
@@ -133,7 +508,17 @@ where
 enum ErrorKind {
     Io(io::Error),
     Lc3(lc3::Error<io::Error>),
+    Image(ImageError<io::Error>),
+    Symbols(lc3::SymbolError),
     UnrecognizedOption,
+    MissingArgument,
+    MissingFile,
+    InvalidArgument,
+    SourceNotLoaded,
+    UninitializedRead(String),
+    CodeOverwritten(String),
+    DataExecuted,
+    Stack(String),
 }
 
 impl fmt::Display for ErrorKind {
@@ -141,7 +526,19 @@ impl fmt::Display for ErrorKind {
         match self {
             ErrorKind::Io(err) => err.fmt(f),
             ErrorKind::Lc3(err) => err.fmt(f),
+            ErrorKind::Image(err) => err.fmt(f),
+            ErrorKind::Symbols(err) => err.fmt(f),
             Self::UnrecognizedOption => f.write_str("unrecognized option"),
+            Self::MissingArgument => f.write_str("missing argument for option"),
+            Self::MissingFile => f.write_str("missing file for subcommand"),
+            Self::InvalidArgument => f.write_str("invalid argument"),
+            Self::SourceNotLoaded => f.write_str("no image loaded at the addresses of the source"),
+            Self::UninitializedRead(addr) => {
+                write!(f, "read of uninitialized memory at {addr}")
+            }
+            Self::CodeOverwritten(addr) => write!(f, "write to executed code at {addr}"),
+            Self::DataExecuted => f.write_str("execution of data"),
+            Self::Stack(message) => f.write_str(message),
         }
     }
 }
@@ -158,6 +555,18 @@ impl From<lc3::Error<io::Error>> for ErrorKind {
     }
 }
 
+impl From<ImageError<io::Error>> for ErrorKind {
+    fn from(value: ImageError<io::Error>) -> ErrorKind {
+        ErrorKind::Image(value)
+    }
+}
+
+impl From<lc3::SymbolError> for ErrorKind {
+    fn from(value: lc3::SymbolError) -> ErrorKind {
+        ErrorKind::Symbols(value)
+    }
+}
+
 impl<E> From<E> for Error
 where
     ErrorKind: From<E>,
@@ -0,0 +1,294 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! The monitors observing a program run from the command line: the
+//! profiler, coverage, statistics and checkers.
+
+use crate::{arg0, invalid, ErrWithContext, Error, ErrorKind};
+use lc3::{
+    CodeChecker, CodeEvent, Coverage, DebugInfo, InitChecker, Instruction, IoDevice, Lc3,
+    LoadReport, Monitor, Profiler, Reg, StackChecker, StackViolation, StackViolationKind, Stats,
+    Storage, SymbolTable,
+};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Which monitors to run, and where they report.
+pub struct MonitorOptions {
+    pub profile: Option<PathBuf>,
+    pub coverage: Option<PathBuf>,
+    pub stats: bool,
+    pub uninitialized: Option<CheckMode>,
+    pub check_code: Option<CheckMode>,
+    pub check_stack: Option<CheckMode>,
+    pub callee_saved: Vec<Reg>,
+}
+
+impl Default for MonitorOptions {
+    fn default() -> MonitorOptions {
+        MonitorOptions {
+            profile: None,
+            coverage: None,
+            stats: false,
+            uninitialized: None,
+            check_code: None,
+            check_stack: None,
+            callee_saved: vec![Reg::R1, Reg::R2, Reg::R3, Reg::R4, Reg::R5],
+        }
+    }
+}
+
+impl MonitorOptions {
+    /// True iff any monitor runs.
+    pub fn any(&self) -> bool {
+        self.profile.is_some()
+            || self.coverage.is_some()
+            || self.stats
+            || self.uninitialized.is_some()
+            || self.check_code.is_some()
+            || self.check_stack.is_some()
+    }
+}
+
+/// The monitors selected by [`MonitorOptions`].
+pub struct Monitors {
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    stats: Option<Stats>,
+    init_checker: Option<InitChecker>,
+    code_checker: Option<CodeChecker>,
+    stack_checker: Option<StackChecker>,
+}
+
+impl Monitors {
+    /// Monitors selected by `options`, knowing the data of `debug_info`.
+    /// Pushes must not write to the images of the operating system `os`.
+    pub fn new(
+        options: &MonitorOptions,
+        debug_info: &DebugInfo,
+        os: &[(PathBuf, LoadReport)],
+    ) -> Monitors {
+        let init_checker = options.uninitialized.map(|mode| match mode {
+            CheckMode::Warn => InitChecker::new(),
+            CheckMode::Stop => InitChecker::stopping(),
+        });
+        let code_checker = options.check_code.map(|mode| {
+            let mut checker = match mode {
+                CheckMode::Warn => CodeChecker::new(),
+                CheckMode::Stop => CodeChecker::stopping(),
+            };
+            checker.add_debug_info(debug_info);
+            checker
+        });
+        let stack_checker = options.check_stack.map(|mode| {
+            let mut checker = match mode {
+                CheckMode::Warn => StackChecker::new(&options.callee_saved),
+                CheckMode::Stop => StackChecker::stopping(&options.callee_saved),
+            };
+            checker.add_debug_info(debug_info);
+            for (_, report) in os {
+                let end = report.origin.wrapping_add((report.len - 1) as u16);
+                checker.protect(report.origin..=end);
+            }
+            checker
+        });
+        Monitors {
+            profiler: options.profile.as_ref().map(|_| Profiler::new()),
+            coverage: options.coverage.as_ref().map(|_| Coverage::new()),
+            stats: options.stats.then(Stats::new),
+            init_checker,
+            code_checker,
+            stack_checker,
+        }
+    }
+
+    /// Write the profile and coverage to their files, and the statistics
+    /// to standard error. Coverage is of the user `images` in `lc3`.
+    pub fn write<IO: IoDevice>(
+        &self,
+        options: &MonitorOptions,
+        lc3: &Lc3<IO>,
+        images: &[(PathBuf, LoadReport)],
+        symbols: &SymbolTable,
+        debug_info: &DebugInfo,
+    ) -> Result<(), Error> {
+        if let (Some(path), Some(profiler)) = (&options.profile, &self.profiler) {
+            let folded = path.extension().is_some_and(|x| x == "folded");
+            File::create(path)
+                .map(io::BufWriter::new)
+                .and_then(|mut file| {
+                    if folded {
+                        profiler.write_folded(&mut file, symbols)?;
+                    } else {
+                        profiler.write_report(&mut file, symbols)?;
+                    }
+                    file.flush()
+                })
+                .err_with_context(path.display())?;
+        }
+
+        if let (Some(path), Some(coverage)) = (&options.coverage, &self.coverage) {
+            let regions: Vec<_> = images
+                .iter()
+                .map(|(_, x)| x.origin..=x.origin.wrapping_add((x.len - 1) as u16))
+                .collect();
+            let memory = lc3.memory.storage();
+            File::create(path)
+                .map(io::BufWriter::new)
+                .and_then(|mut file| {
+                    if debug_info.files().is_empty() {
+                        coverage.write_annotated(&mut file, memory, &regions, symbols)?;
+                    } else {
+                        coverage.write_lcov(&mut file, memory, debug_info)?;
+                    }
+                    file.flush()
+                })
+                .err_with_context(path.display())?;
+        }
+
+        if let Some(stats) = &self.stats {
+            stats.write_summary(&mut io::stderr().lock(), symbols)?;
+        }
+        Ok(())
+    }
+
+    /// Warn about the problems found by the checkers, or fail with the
+    /// first one found by a checker stopping the machine.
+    pub fn check(&self, options: &MonitorOptions, symbols: &SymbolTable) -> Result<(), Error> {
+        let reads = self
+            .init_checker
+            .iter()
+            .flat_map(|x| x.reads())
+            .map(|read| {
+                Error::new(
+                    ErrorKind::UninitializedRead(symbols.display(read.addr).to_string()),
+                    symbols.display(read.pc),
+                )
+            });
+        report_checks(options.uninitialized, reads)?;
+
+        let events = self
+            .code_checker
+            .iter()
+            .flat_map(|x| x.events())
+            .map(|event| match *event {
+                CodeEvent::Overwritten { pc, addr } => Error::new(
+                    ErrorKind::CodeOverwritten(symbols.display(addr).to_string()),
+                    symbols.display(pc),
+                ),
+                CodeEvent::ExecutedData { pc } => {
+                    Error::new(ErrorKind::DataExecuted, symbols.display(pc))
+                }
+            });
+        report_checks(options.check_code, events)?;
+
+        let violations = self.stack_checker.iter().flat_map(|x| x.violations());
+        report_checks(
+            options.check_stack,
+            violations.map(|x| stack_error(x, symbols)),
+        )
+    }
+}
+
+/// Report to every monitor, halting if any one does.
+impl<IO: IoDevice, S: Storage> Monitor<IO, S> for Monitors {
+    fn executing(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) -> bool {
+        [
+            self.profiler.executing(pc, inst, lc3),
+            self.coverage.executing(pc, inst, lc3),
+            self.stats.executing(pc, inst, lc3),
+            self.init_checker.executing(pc, inst, lc3),
+            self.code_checker.executing(pc, inst, lc3),
+            self.stack_checker.executing(pc, inst, lc3),
+        ]
+        .into_iter()
+        .all(|x| x)
+    }
+
+    fn executed(&mut self, pc: u16, inst: Instruction, lc3: &Lc3<IO, S>) {
+        self.profiler.executed(pc, inst, lc3);
+        self.coverage.executed(pc, inst, lc3);
+        self.stats.executed(pc, inst, lc3);
+        self.init_checker.executed(pc, inst, lc3);
+        self.code_checker.executed(pc, inst, lc3);
+        self.stack_checker.executed(pc, inst, lc3);
+    }
+}
+
+/// Warn about the problems found by a checker in `mode`, or fail with the first one.
+fn report_checks<I>(mode: Option<CheckMode>, problems: I) -> Result<(), Error>
+where
+    I: Iterator<Item = Error>,
+{
+    for err in problems {
+        if mode == Some(CheckMode::Stop) {
+            return Err(err);
+        }
+        eprintln!("{}: warning: {err}", arg0());
+    }
+    Ok(())
+}
+
+/// Describe `violation` of the stack discipline, with its call trace.
+fn stack_error(violation: &StackViolation, symbols: &SymbolTable) -> Error {
+    let mut message = match violation.kind {
+        StackViolationKind::Unbalanced { expected, actual } => {
+            format!(
+                "unbalanced stack on return: R6 is x{actual:04X}, was x{expected:04X} at the call"
+            )
+        }
+        StackViolationKind::ReturnAddress { expected, actual } => format!(
+            "return to {} instead of {}",
+            symbols.display(actual),
+            symbols.display(expected)
+        ),
+        StackViolationKind::CalleeSaved {
+            reg,
+            expected,
+            actual,
+        } => format!("callee-saved {reg:?} is x{actual:04X}, was x{expected:04X} at the call"),
+        StackViolationKind::Overflow { addr } => {
+            format!("stack overflow: push to {}", symbols.display(addr))
+        }
+    };
+    for &call in violation.trace.iter().rev() {
+        message.push_str(&format!("\n    called from {}", symbols.display(call)));
+    }
+    Error::new(ErrorKind::Stack(message), symbols.display(violation.pc))
+}
+
+/// What a checker does once it finds a problem.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CheckMode {
+    /// Warn once the machine halts.
+    Warn,
+    /// Stop the machine and fail.
+    Stop,
+}
+
+impl CheckMode {
+    pub fn parse(s: OsString) -> Result<CheckMode, Error> {
+        match s.to_str() {
+            Some("warn") => Ok(CheckMode::Warn),
+            Some("stop") => Ok(CheckMode::Stop),
+            _ => Err(invalid(&s)),
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//
+// lc3-vm, a virtual machine for the LC-3 (Little Computer 3) architecture.
+// Copyright (C) 2024  Fares A. Bakhit
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! The `lc3-vm` command line, run on small programs without a terminal.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// A new directory for the files of `test`.
+fn test_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lc3-vm-{}-{test}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write `words` as an `.obj` image at `x3000` to `path`.
+fn write_image(path: &Path, words: &[u16]) {
+    let bytes: Vec<u8> = [0x3000]
+        .iter()
+        .chain(words)
+        .flat_map(|word| word.to_be_bytes())
+        .collect();
+    fs::write(path, bytes).unwrap();
+}
+
+/// Write `words` as an `.obj` image to a new directory for `test`,
+/// returning the path of the image.
+fn image(test: &str, words: &[u16]) -> PathBuf {
+    let path = test_dir(test).join("program.obj");
+    write_image(&path, words);
+    path
+}
+
+fn lc3_vm(args: &[&str], image: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lc3-vm"))
+        .args(args)
+        .arg(image)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+/// Exit status of `HALT` with R0 holding `r0`, given `--exit-code` `source`.
+fn halt_status(source: &str, r0: u16) -> Option<i32> {
+    let words = [
+        0x2001, // LD R0, #1
+        0xF025, // HALT
+        r0,     // .FILL r0
+    ];
+    let path = image(&format!("halt-{source}-{r0}"), &words);
+    lc3_vm(&["--exit-code", source], &path).status.code()
+}
+
+#[test]
+fn exit_code_none() {
+    assert_eq!(halt_status("none", 5), Some(0));
+}
+
+#[test]
+fn exit_code_r0() {
+    assert_eq!(halt_status("r0", 0), Some(0));
+    assert_eq!(halt_status("r0", 3), Some(3));
+    assert_eq!(halt_status("r0", 0x0100), Some(1));
+    assert_eq!(halt_status("r0", 0x0203), Some(3));
+}
+
+#[test]
+fn exit_code_trap() {
+    let words = [
+        0x2002, // LD R0, #2
+        0xF026, // TRAP x26
+        0xF025, // HALT
+        0x0200, // .FILL x0200
+    ];
+    let path = image("trap", &words);
+    let output = lc3_vm(&["--exit-code", "trap:x26"], &path);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn exit_code_mem() {
+    let words = [
+        0xF025, // HALT
+        0x002A, // .FILL #42
+        0x0400, // .FILL x0400
+    ];
+    let path = image("mem", &words);
+    let status = |source| lc3_vm(&["--exit-code", source], &path).status.code();
+    assert_eq!(status("mem:x3001"), Some(42));
+    assert_eq!(status("mem:x3002"), Some(1));
+    assert_eq!(status("mem:x3003"), Some(0));
+}
+
+#[test]
+fn batch_manifest() {
+    let dir = test_dir("batch");
+    write_image(
+        &dir.join("echo.obj"),
+        &[
+            0xF020, // GETC
+            0xF021, // OUT
+            0xF025, // HALT
+        ],
+    );
+    write_image(&dir.join("loop.obj"), &[0x0FFF]); // BRnzp #-1
+    write_image(
+        &dir.join("bad.obj"),
+        &[
+            0x1021, // ADD R0, R0, #1
+            0x1021, // ADD R0, R0, #1
+            0xD000, // reserved
+        ],
+    );
+    fs::write(dir.join("echo.in"), "a").unwrap();
+    let manifest = dir.join("manifest");
+    fs::write(
+        &manifest,
+        "# programs\necho.obj echo.in\n\nloop.obj steps=x100\nbad.obj\n",
+    )
+    .unwrap();
+
+    for engine in ["interpreter", "predecoded", "blocks"] {
+        let output = lc3_vm(&["batch", "--jobs", "2", "--engine", engine], &manifest);
+        assert_eq!(output.status.code(), Some(1), "{engine}");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<_> = stdout.lines().collect();
+        assert_eq!(lines.len(), 3, "{engine}: {stdout}");
+
+        let field = |line: &str, name: &str| {
+            let start = line.find(&format!("\"{name}\":")).unwrap() + name.len() + 3;
+            let end = start + line[start..].find([',', '}']).unwrap();
+            line[start..end].to_string()
+        };
+        let echo = dir.join("echo.obj").display().to_string();
+        assert!(lines[0].starts_with(&format!("{{\"image\":\"{echo}\",")));
+        assert_eq!(field(lines[0], "status"), "\"halted\"", "{engine}");
+        assert!(lines[0].contains("\"output\":\"a\\n\\n--- halting the LC-3 ---\\n\\n\"}"));
+
+        assert_eq!(field(lines[1], "input"), "null", "{engine}");
+        assert_eq!(field(lines[1], "status"), "\"step_limit\"", "{engine}");
+        assert_eq!(field(lines[1], "steps"), "256", "{engine}");
+
+        assert_eq!(field(lines[2], "status"), "\"error\"", "{engine}");
+        assert_eq!(
+            field(lines[2], "error"),
+            "\"x3002: opcode not implemented.\"",
+            "{engine}"
+        );
+        assert_eq!(field(lines[2], "steps"), "3", "{engine}");
+        assert_eq!(field(lines[2], "pc"), "12291", "{engine}");
+        assert!(lines[2].contains("\"registers\":[2,0,0,0,0,0,0,0]"));
+    }
+}
+
+#[test]
+fn monitors_need_the_interpreter() {
+    let path = image("monitors", &[0xF025]); // HALT
+    let output = lc3_vm(&["--engine", "blocks", "--stats"], &path);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("invalid argument 'blocks'"), "{stderr}");
+
+    let output = lc3_vm(&["--engine", "interpreter", "--stats"], &path);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Instructions: "), "{stderr}");
+}
+
+#[test]
+fn symbol_files() {
+    let path = image(
+        "symbols",
+        &[
+            0xF025, // HALT
+            0x002A, // RESULT .FILL #42
+        ],
+    );
+    let dir = path.parent().unwrap();
+    let sym = |name, text| {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        path.to_str().unwrap().to_owned()
+    };
+    let first = sym("first.sym", "// Symbol table\n//\tRESULT  3001\n");
+    let second = sym("second.sym", "//\tRESULT  3002\n");
+    let malformed = sym("malformed.sym", "//\tRESULT\n");
+
+    let output = lc3_vm(&["--exit-code", "mem:RESULT", &first, &second], &path);
+    assert_eq!(output.status.code(), Some(42));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("symbol 'RESULT' redefined as x3002, keeping x3001"));
+
+    let output = lc3_vm(&[&malformed], &path);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("line 1: expected a label and a hexadecimal address."));
+}
+
+#[test]
+fn sources_need_their_image() {
+    let path = image("sources", &[0xF025]); // HALT
+    let dir = path.parent().unwrap();
+    let source = |name: &str, text: &str| {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        path.to_str().unwrap().to_owned()
+    };
+    let loaded = source("loaded.asm", ".ORIG x3000\nMAIN HALT\n.END\n");
+    let missing = source("missing.asm", ".ORIG x4000\nSUB RET\n.END\n");
+
+    let output = lc3_vm(&["--exit-code", "mem:MAIN", &loaded], &path);
+    assert_eq!(output.status.code(), Some(0x25));
+
+    let output = lc3_vm(&[&missing], &path);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no image loaded at the addresses of the source"));
+}
+
+#[test]
+fn image_formats() {
+    let dir = test_dir("formats");
+    let run = |name: &str, content: &[u8], args: &[&str]| {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        let mut args = args.to_vec();
+        args.extend(["--exit-code", "mem:x4000"]);
+        lc3_vm(&args, &path).status.code()
+    };
+    // AND R0, R0, #0; ADD R0, R0, #5; ADD R0, R0, #2; STI R0, #1; HALT; .FILL x4000
+    let hex = b"3000\n5020\n1025\n1022\nB001\nF025\n4000\n";
+    assert_eq!(run("program.hex", hex, &[]), Some(7));
+    // Detected from content when the extension is unknown.
+    assert_eq!(run("program.txt", hex, &[]), Some(7));
+
+    let raw = [
+        0x50, 0x20, 0x10, 0x25, 0x10, 0x22, 0xB0, 0x01, 0xF0, 0x25, 0x40, 0x00,
+    ];
+    assert_eq!(run("program.raw", &raw, &["--load-at", "x3000"]), Some(7));
+}
+
+#[test]
+fn dump_self_modified_code() {
+    let path = image(
+        "dump",
+        &[
+            0x2203, // LD R1, #3
+            0x3200, // ST R1, #0
+            0x16E1, // ADD R3, R3, #1 (overwritten)
+            0xF025, // HALT
+            0x16E5, // .FILL ADD R3, R3, #5
+        ],
+    );
+    let dump = path.with_file_name("dump.obj");
+    let range = format!("x3000-x3004:{}", dump.display());
+    assert_eq!(lc3_vm(&["--dump", &range], &path).status.code(), Some(0));
+
+    let words = [0x3000, 0x2203, 0x3200, 0x16E5, 0xF025, 0x16E5];
+    let expected: Vec<u8> = words.iter().flat_map(|x: &u16| x.to_be_bytes()).collect();
+    assert_eq!(fs::read(&dump).unwrap(), expected);
+
+    // The dump loads back with the modified instruction, and runs.
+    let status = lc3_vm(&["--exit-code", "mem:x3002"], &dump).status.code();
+    assert_eq!(status, Some(0xE5));
+}
+
+#[test]
+fn entry() {
+    let path = image(
+        "entry",
+        &[
+            0x1021, // ADD R0, R0, #1
+            0x1021, // ADD R0, R0, #1
+            0xF025, // HALT
+        ],
+    );
+    let dir = path.parent().unwrap();
+    let symbols = dir.join("entry.sym");
+    fs::write(&symbols, "SECOND 3001\n").unwrap();
+    let symbols = symbols.to_str().unwrap();
+    let status = |args: &[&str]| {
+        let mut args = args.to_vec();
+        args.extend(["--exit-code", "r0"]);
+        lc3_vm(&args, &path).status.code()
+    };
+    assert_eq!(status(&[]), Some(2));
+    assert_eq!(status(&["--entry", "x3001"]), Some(1));
+    assert_eq!(status(&["--entry", "SECOND", symbols]), Some(1));
+
+    let output = lc3_vm(&["--entry", "THIRD", symbols], &path);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid argument 'THIRD'"), "{stderr}");
+}
+
+#[test]
+fn entry_origin() {
+    let first = image(
+        "entry-origin",
+        &[
+            0x1021, // ADD R0, R0, #1
+            0xF025, // HALT
+        ],
+    );
+    let last = first.with_file_name("last.obj");
+    let words = [
+        0x4000, // .ORIG x4000
+        0x1025, // ADD R0, R0, #5
+        0xF025, // HALT
+    ];
+    fs::write(&last, words.map(u16::to_be_bytes).concat()).unwrap();
+    let first = first.to_str().unwrap();
+
+    let status = |args: &[&str]| lc3_vm(args, &last).status.code();
+    assert_eq!(status(&["--exit-code", "r0", first]), Some(1));
+    let args = ["--entry-origin", "--exit-code", "r0", first];
+    assert_eq!(status(&args), Some(5));
+}
+
+#[test]
+fn boot() {
+    let path = image(
+        "boot",
+        &[
+            0x1021, // ADD R0, R0, #1
+            0xF025, // HALT
+        ],
+    );
+    let run = |args: &[&str]| {
+        let mut args = args.to_vec();
+        args.extend(["--exit-code", "r0"]);
+        lc3_vm(&args, &path)
+    };
+    const WELCOME: &str = "Welcome to the LC-3 simulator";
+
+    let output = run(&[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains(WELCOME));
+
+    // The startup code prints its welcome with PUTS from R0 = x0203, and the
+    // user program starts with the registers it left.
+    let output = run(&["--boot"]);
+    assert_eq!(output.status.code(), Some(0x04));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(WELCOME));
+
+    let output = run(&["--boot", "--entry", "x3001"]);
+    assert_eq!(output.status.code(), Some(0x03));
+}